@@ -1,6 +1,8 @@
 use easy_fs::{
     BlockDevice,
     EasyFileSystem,
+    FS_BLOCKS,
+    SWAP_BLOCKS,
 };
 use std::fs::{File, OpenOptions, read_dir};
 use std::io::{Read, Write, Seek, SeekFrom};
@@ -56,13 +58,14 @@ fn easy_fs_pack() -> std::io::Result<()> {
             .write(true)
             .create(true)
             .open(format!("{}{}", target_path, "fs.img"))?;
-        f.set_len(8192 * 512).unwrap();
+        // 文件系统之后是内核的交换区
+        f.set_len(((FS_BLOCKS + SWAP_BLOCKS) * BLOCK_SZ) as u64).unwrap();
         f
     })));
     // 4MiB, at most 4095 files
     let efs = EasyFileSystem::create(
         block_file.clone(),
-        8192,
+        FS_BLOCKS as u32,
         1,
     );
     let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
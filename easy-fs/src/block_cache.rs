@@ -1,4 +1,5 @@
-use alloc::{collections::VecDeque, sync::Arc};
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc};
+use core::ptr::NonNull;
 use lazy_static::lazy_static;
 use spin::Mutex;
 
@@ -6,10 +7,43 @@ use crate::{block_dev::BlockDevice, BLOCK_SZ};
 
 const BLOCK_CACHE_SIZE: usize = 16;
 
+/// 块缓存数据缓冲区的分配器。默认从堆中分配，使用者（如内核）可以通过
+/// set_block_buffer_allocator 换成自己的对象缓存
+pub trait BlockBufferAllocator: Send + Sync {
+    /// 分配一个清零的缓冲区
+    fn alloc(&self) -> NonNull<[u8; BLOCK_SZ]>;
+    /// 回收 alloc 分配的缓冲区
+    fn dealloc(&self, buffer: NonNull<[u8; BLOCK_SZ]>);
+}
+
+/// 默认的缓冲区分配器，从堆中分配
+struct HeapBufferAllocator;
+
+impl BlockBufferAllocator for HeapBufferAllocator {
+    fn alloc(&self) -> NonNull<[u8; BLOCK_SZ]> {
+        NonNull::from(Box::leak(Box::new([0u8; BLOCK_SZ])))
+    }
+
+    fn dealloc(&self, buffer: NonNull<[u8; BLOCK_SZ]>) {
+        drop(unsafe { Box::from_raw(buffer.as_ptr()) });
+    }
+}
+
+lazy_static! {
+    static ref BLOCK_BUFFER_ALLOCATOR: Mutex<&'static dyn BlockBufferAllocator> =
+        Mutex::new(&HeapBufferAllocator);
+}
+
+/// 设置块缓存缓冲区的分配器，只影响之后创建的块缓存
+pub fn set_block_buffer_allocator(allocator: &'static dyn BlockBufferAllocator) {
+    *BLOCK_BUFFER_ALLOCATOR.lock() = allocator;
+}
+
 /// 块缓存：读写缓存
 pub struct BlockCache {
-    /// 数据
-    cache: [u8; BLOCK_SZ],
+    /// 数据，由 allocator 分配
+    cache: NonNull<[u8; BLOCK_SZ]>,
+    allocator: &'static dyn BlockBufferAllocator,
     /// 对应的块 ID
     block_id: usize,
     /// 本块对应的块设备接口，使用 dyn 表示子类型泛型，即在运行时确定类型
@@ -21,19 +55,25 @@ pub struct BlockCache {
 impl BlockCache {
     /// 根据 block_id 和 device 加载数据，并生成 BlockCache 对象
     pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Self {
-        let mut cache = [0u8; BLOCK_SZ];
-        block_device.read_block(block_id, &mut cache);
+        let allocator = *BLOCK_BUFFER_ALLOCATOR.lock();
+        let mut cache = allocator.alloc();
+        block_device.read_block(block_id, unsafe { cache.as_mut() });
         Self {
             cache,
+            allocator,
             block_id,
             block_device,
             modified: false,
         }
     }
 
+    fn data(&self) -> &[u8; BLOCK_SZ] {
+        unsafe { self.cache.as_ref() }
+    }
+
     /// 根据 offset 得到对应位置的地址
     fn addr_of_offset(&self, offset: usize) -> usize {
-        &self.cache[offset] as *const _ as usize
+        &self.data()[offset] as *const _ as usize
     }
 
     /// 获取类型 T 的只读引用，注意：T必须是 Sized，即固定类型
@@ -63,7 +103,7 @@ impl BlockCache {
     pub fn sync(&mut self) {
         if self.modified {
             self.modified = false;
-            self.block_device.write_block(self.block_id, &self.cache);
+            self.block_device.write_block(self.block_id, self.data());
         }
     }
 
@@ -82,9 +122,13 @@ impl BlockCache {
     }
 }
 
+// 缓冲区由 BlockCache 独占
+unsafe impl Send for BlockCache {}
+
 impl Drop for BlockCache {
     fn drop(&mut self) {
         self.sync();
+        self.allocator.dealloc(self.cache);
     }
 }
 
@@ -2,6 +2,10 @@
 
 /// 块大小（字节数）
 pub const BLOCK_SZ: usize = 512;
+/// easy-fs-fuse 生成的镜像中文件系统占用的块数
+pub const FS_BLOCKS: usize = 8192;
+/// 镜像中紧跟在文件系统之后、留给内核交换区的块数，4 MiB
+pub const SWAP_BLOCKS: usize = 8192;
 
 extern crate alloc;
 
@@ -12,6 +16,7 @@ mod bitmap;
 mod efs;
 mod vfs;
 
+pub use block_cache::{set_block_buffer_allocator, BlockBufferAllocator};
 pub use block_dev::BlockDevice;
 pub use efs::EasyFileSystem;
 pub use vfs::Inode;
@@ -1,4 +1,11 @@
 pub const USER_STACK_SIZE: usize = 4096 * 2; // 一个用户任务分配 8 KB 空间
+/// 每个线程预留的用户栈空间，即 RLIMIT_STACK 的默认硬限制，用户栈之间按这个大小排列
+pub const MAX_USER_STACK_SIZE: usize = 0x10_0000;
+/// 系统中同时存在的进程数的默认上限（RLIMIT_NPROC）。每个进程至少占用页表、内核栈与
+/// TrapContext 等十余个物理页帧，上限按可用物理内存估算，避免 fork 耗尽页帧
+pub const MAX_PROCESS_NUM: usize = 64;
+/// 进程可打开的文件数的默认上限（RLIMIT_NOFILE）
+pub const MAX_FD_NUM: usize = 128;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
 pub const MAX_APP_NUM: usize = 4;
 pub const APP_BASE_ADDRESS: usize = 0x80400000;
@@ -10,15 +17,44 @@ pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 /// TrapContext 所处的虚拟页，放在次高页
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
 
+/// mmap 未指定地址时，从这里开始查找空闲的虚拟地址区间
+pub const MMAP_BASE: usize = 0x10_0000_0000;
+/// mmap 可使用的虚拟地址上限。Sv39 下用户空间为低 256GiB，其最高的 1GiB 与跳板/TrapContext
+/// 共用一级页表项，这里只使用其中的前一半
+pub const MMAP_END: usize = 0x20_0000_0000;
+
+/// 用户地址空间的上界。Sv39 的虚拟地址只有低 39 位有效，用户空间为其中的低半部分，
+/// 更高的地址查页表时会被截断成低地址，检查用户指针时需要先排除
+pub const USER_SPACE_END: usize = 1 << 38;
+
+/// 用户栈顶。栈放在 mmap 区域下方一页处，与向上增长的用户堆相距很远
+pub const USER_STACK_TOP: usize = MMAP_BASE - PAGE_SIZE;
+
 /// 内存页大小
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 
-/// 内核堆大小
+/// 内核堆的初始大小，位于 .bss 段中
 pub const KERNEL_HEAP_SIZE: usize = 0x20_0000;
+/// 内核堆用完时每次至少扩大的大小，64 KiB
+pub const KERNEL_HEAP_GROW_SIZE: usize = 0x1_0000;
+/// 为内核保留的页桢数，1 MiB。内核堆增长时不能换出页面，用户页面与页表只有在无法换出时才动用这部分
+pub const KERNEL_RESERVED_FRAMES: usize = 256;
 /// 物理内存上限，后面应该使用设备查询获取
 pub const MEMORY_END: usize = 0x80800000;
 
+/// 交换区在块设备上的起始块号。块设备的开头是文件系统，交换区紧随其后，
+/// 与 easy-fs-fuse 生成镜像时使用同一组常量
+pub const SWAP_START_BLOCK: usize = easy_fs::FS_BLOCKS;
+/// 交换区最多容纳的页面数。块设备比镜像预留的小时，按实际容量减少
+pub const SWAP_PAGES: usize = easy_fs::SWAP_BLOCKS * easy_fs::BLOCK_SZ / PAGE_SIZE;
+
+/// 支持的最大核（hart）数，hart 编号不小于它的核不会被使用。
+/// entry.asm 开头的 MAX_HART_NUM 按这个数目预留各核的启动栈，修改时需要同时修改
+pub const MAX_HART_NUM: usize = 4;
+/// 每个核的启动栈大小，同样需要与 entry.asm 开头的 BOOT_STACK_SIZE 一致
+pub const BOOT_STACK_SIZE: usize = 4096 * 16;
+
 /// 时钟频率，与硬件有关。
 // 这儿提供的是 qemu 的配置时钟，可用 cfg 编译开关指定。
 // #[cfg(feature = "board_qemu")]
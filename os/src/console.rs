@@ -1,5 +1,9 @@
 use core::fmt::{self, Write};
 use crate::sbi::console_putchar;
+use spin::Mutex;
+
+/// 多个核同时输出时，保证每次 print 的内容不被其它核打断
+static PRINT_LOCK: Mutex<()> = Mutex::new(());
 
 struct Stdout;
 
@@ -13,6 +17,7 @@ impl Write for Stdout {
 }
 
 pub fn print(args: fmt::Arguments) {
+    let _guard = PRINT_LOCK.lock();
     Stdout.write_fmt(args).unwrap();
 }
 
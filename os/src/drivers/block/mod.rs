@@ -12,4 +12,9 @@ lazy_static! {
     pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
 }
 
+/// 块设备的容量，单位为块
+pub fn block_device_capacity() -> usize {
+    BlockDeviceImpl::capacity()
+}
+
 
@@ -4,14 +4,15 @@ use lazy_static::*;
 use spin::Mutex;
 use virtio_drivers::{VirtIOBlk, VirtIOHeader};
 
-use crate::mm::StepByOne;
 use crate::mm::{
-    frame_alloc, frame_dealloc, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum,
+    frame_alloc_contiguous, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum,
     VirtAddr,
 };
 
 /// 通过 MMIO 访问VirtIO 设备对应的寄存器组地址。在 config 中定义
 const VIRTIO0: usize = 0x10001000;
+/// 设备配置空间在寄存器组中的偏移，virtio-blk 的配置空间以 64 位的容量字段开头
+const VIRTIO_CONFIG: usize = 0x100;
 
 /// 这里只是将 virtio_drivers crate 的 Blk 加了一个互斥锁，并实现了我们定义的 BlockDevice crate。
 /// 驱动细节在此未涉及，由现成的 crate 完成
@@ -34,6 +35,11 @@ impl BlockDevice for VirtIOBlock {
 }
 
 impl VirtIOBlock {
+    /// 设备的容量，单位为 512 字节的扇区，与块大小 BLOCK_SZ 相同
+    pub fn capacity() -> usize {
+        unsafe { ((VIRTIO0 + VIRTIO_CONFIG) as *const u64).read_volatile() as usize }
+    }
+
     pub fn new() -> Self {
         Self(Mutex::new(
             VirtIOBlk::new(unsafe {
@@ -67,25 +73,20 @@ extern "C" {
 
 #[no_mangle] // 对应 extern "C"
 pub extern "C" fn virtio_dma_alloc(pages: usize) -> PhysAddr {
-    let mut ppn_base = PhysPageNum(0);
-    for i in 0..pages {
-        let frame = frame_alloc().unwrap();
-        if i == 0 {
-            ppn_base = frame.ppn;
-        }
-        assert_eq!(frame.ppn.0, ppn_base.0 + i);
-        QUEUE_FRAMES.lock().push(frame);
-    }
+    // 环形队列需要物理上连续的页
+    let frames = frame_alloc_contiguous(pages, 1).unwrap();
+    let ppn_base = frames[0].ppn;
+    QUEUE_FRAMES.lock().extend(frames);
     ppn_base.into()
 }
 
 #[no_mangle]
 pub extern "C" fn virtio_dma_dealloc(pa: PhysAddr, pages: usize) -> i32 {
-    let mut ppn_base: PhysPageNum = pa.into();
-    for _ in 0..pages {
-        frame_dealloc(ppn_base);
-        ppn_base.step();
-    }
+    let ppn_base: PhysPageNum = pa.into();
+    // 移出 QUEUE_FRAMES 的 FrameTracker 被释放时回收页桢
+    QUEUE_FRAMES
+        .lock()
+        .retain(|frame| frame.ppn.0 < ppn_base.0 || frame.ppn.0 >= ppn_base.0 + pages);
     0
 }
 
@@ -1,3 +1,3 @@
 mod block;
 
-pub use block::BLOCK_DEVICE;
\ No newline at end of file
+pub use block::{block_device_capacity, BLOCK_DEVICE};
\ No newline at end of file
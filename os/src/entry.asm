@@ -1,12 +1,29 @@
+    # 与 config.rs 中的 MAX_HART_NUM、BOOT_STACK_SIZE 一致，rust_main 启动时检查启动栈的大小
+    .equ MAX_HART_NUM, 4
+    .equ BOOT_STACK_SIZE, 4096 * 16
+
     .section .text.entry # 指定段名为 .text.entry，对应 linker.ld 中第一部分，所以这个 asm 会被放在 .text 的首部，即 0x8020000
     .globl _start # 声明全局符号 _start，在 linker.ld 中将它指定为了整个 os 镜像的入口
 _start:
-    la sp, boot_stack_top # 设置 sp 寄存器
+    # SBI 跳转到内核时 a0 为当前 hart 的编号，内核运行期间一直保存在 tp 中
+    mv tp, a0
+    # 超出 MAX_HART_NUM 的 hart 没有启动栈，不参与运行
+    li t0, MAX_HART_NUM
+    bgeu a0, t0, park
+    # 每个 hart 使用自己的启动栈：sp = boot_stack + (hartid + 1) * BOOT_STACK_SIZE
+    addi t0, a0, 1
+    li t1, BOOT_STACK_SIZE
+    mul t0, t0, t1
+    la sp, boot_stack
+    add sp, sp, t0 # 设置 sp 寄存器
     call rust_main # 初始化栈结束，跳到 rust 入口
+park:
+    wfi
+    j park
 
     .section .bss.stack # 此栈被放入 linker.ld 指定的 .bss 段的低地址空间
     .globl boot_stack # 全局符号 boot_stack，表示栈底
 boot_stack:
-    .space 4096*16 # 64KB 栈空间，栈向下生长
+    .space BOOT_STACK_SIZE * MAX_HART_NUM # 每个 hart 64KB 栈空间，共 MAX_HART_NUM 个，栈向下生长
     .globl boot_stack_top
-boot_stack_top:
\ No newline at end of file
+boot_stack_top:
@@ -0,0 +1,52 @@
+//! 系统调用的错误码，与 Linux 的 errno 取值一致。
+//! 系统调用出错时返回 -errno，fs、mm、task 等模块的可失败操作也使用它来描述错误原因
+
+/// 错误码
+#[allow(dead_code)]
+#[repr(isize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SysError {
+    /// 操作不被允许
+    EPERM = 1,
+    /// 文件不存在
+    ENOENT = 2,
+    /// 进程或线程不存在
+    ESRCH = 3,
+    /// 阻塞被信号打断
+    EINTR = 4,
+    /// 输入输出错误，例如后台进程组读取终端而 SIGTTIN 被忽略
+    EIO = 5,
+    /// 参数过长
+    E2BIG = 7,
+    /// 不是可执行文件
+    ENOEXEC = 8,
+    /// 文件描述符不合法，或者文件不支持该读写方向
+    EBADF = 9,
+    /// 没有可等待的子进程
+    ECHILD = 10,
+    /// 资源暂时不可用，需要重试
+    EAGAIN = 11,
+    /// 内存不足
+    ENOMEM = 12,
+    /// 用户地址不合法
+    EFAULT = 14,
+    /// 已存在
+    EEXIST = 17,
+    /// 参数不合法
+    EINVAL = 22,
+    /// 打开的文件过多
+    EMFILE = 24,
+    /// 文件不是终端
+    ENOTTY = 25,
+    /// 磁盘空间不足
+    ENOSPC = 28,
+    /// 操作会导致死锁
+    EDEADLK = 35,
+    /// 不支持的系统调用
+    ENOSYS = 38,
+    /// 等待超时
+    ETIMEDOUT = 110,
+}
+
+/// 可能失败的操作结果，系统调用成功时的返回值为 usize
+pub type SysResult<T = usize> = Result<T, SysError>;
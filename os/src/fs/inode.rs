@@ -1,11 +1,13 @@
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use easy_fs::{EasyFileSystem, Inode};
+use core::ptr::NonNull;
+use easy_fs::{set_block_buffer_allocator, BlockBufferAllocator, EasyFileSystem, Inode, BLOCK_SZ};
 use lazy_static::*;
 use spin::Mutex;
 
 use crate::drivers::BLOCK_DEVICE;
-use crate::mm::UserBuffer;
+use crate::error::{SysError, SysResult};
+use crate::mm::{SlabBox, UserBuffer};
 
 use super::File;
 
@@ -14,7 +16,8 @@ pub struct OSInode {
     /// 是否可以用 sys_read 读取内容
     readable: bool,
     writable: bool,
-    inner: Mutex<OSInodeInner>,
+    /// 从 os_inode 对象缓存中分配
+    inner: SlabBox<Mutex<OSInodeInner>>,
 }
 
 pub struct OSInodeInner {
@@ -27,7 +30,7 @@ impl OSInode {
         Self {
             readable,
             writable,
-            inner: Mutex::new(OSInodeInner { offset: 0, inode }),
+            inner: SlabBox::new(Mutex::new(OSInodeInner { offset: 0, inode })),
         }
     }
 
@@ -49,7 +52,10 @@ impl OSInode {
 
 /// 打开文件需要放在文件描述符表中，所以需要实现 File crate
 impl File for OSInode {
-    fn read(&self, mut user_buf: UserBuffer) -> usize {
+    fn read(&self, mut user_buf: UserBuffer) -> SysResult<usize> {
+        if !self.readable {
+            return Err(SysError::EBADF);
+        }
         let mut inner = self.inner.lock();
         let mut total_read_size = 0usize;
         for slice in user_buf.buffers.iter_mut() {
@@ -60,10 +66,13 @@ impl File for OSInode {
             total_read_size += read_size;
             inner.offset += read_size;
         }
-        total_read_size
+        Ok(total_read_size)
     }
 
-    fn write(&self, user_buf: UserBuffer) -> usize {
+    fn write(&self, user_buf: UserBuffer) -> SysResult<usize> {
+        if !self.writable {
+            return Err(SysError::EBADF);
+        }
         let mut inner = self.inner.lock();
         let mut total_write_size = 0usize;
         for slice in user_buf.buffers.iter() {
@@ -72,12 +81,27 @@ impl File for OSInode {
             inner.offset += write_size;
             total_write_size += write_size;
         }
-        total_write_size
+        Ok(total_write_size)
+    }
+}
+
+/// 块缓存的缓冲区从 block_buffer 对象缓存中分配
+struct SlabBlockBuffers;
+
+impl BlockBufferAllocator for SlabBlockBuffers {
+    fn alloc(&self) -> NonNull<[u8; BLOCK_SZ]> {
+        SlabBox::into_raw(SlabBox::construct())
+    }
+
+    fn dealloc(&self, buffer: NonNull<[u8; BLOCK_SZ]>) {
+        drop(unsafe { SlabBox::from_raw(buffer) });
     }
 }
 
 lazy_static! {
     pub static ref ROOT_INODE: Arc<Inode> = {
+        // 在文件系统读入第一个块之前设置
+        set_block_buffer_allocator(&SlabBlockBuffers);
         let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
         Arc::new(EasyFileSystem::root_inode(&efs))
     };
@@ -113,26 +137,30 @@ impl OpenFlags {
     }
 }
 
-/// 打开根目录下的文件
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+/// 打开根目录下的文件。文件不存在时返回 ENOENT，需要新建但没有空间时返回 ENOSPC
+pub fn open_file(name: &str, flags: OpenFlags) -> SysResult<Arc<OSInode>> {
     let (readable, writable) = flags.read_write();
     if flags.contains(OpenFlags::CREATE) {
         if let Some(inode) = ROOT_INODE.find(name) {
             // 覆盖原来数据
             inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
+            Ok(Arc::new(OSInode::new(readable, writable, inode)))
         } else {
             // 新建
             ROOT_INODE
                 .create(name)
                 .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
+                .ok_or(SysError::ENOSPC)
         }
     } else {
-        ROOT_INODE.find(name).map(|inode| {
-            if flags.contains(OpenFlags::TRUNC) {
-                inode.clear();
-            }
-            Arc::new(OSInode::new(readable, writable, inode))
-        })
+        ROOT_INODE
+            .find(name)
+            .map(|inode| {
+                if flags.contains(OpenFlags::TRUNC) {
+                    inode.clear();
+                }
+                Arc::new(OSInode::new(readable, writable, inode))
+            })
+            .ok_or(SysError::ENOENT)
     }
 }
@@ -2,12 +2,17 @@ mod stdio;
 mod pipe;
 mod inode;
 
-use crate::mm::UserBuffer;
+use crate::{error::SysResult, mm::UserBuffer};
 pub use stdio::*;
 pub use pipe::*;
-pub use inode::{open_file, OpenFlags, list_apps};
+pub use inode::{open_file, OpenFlags, list_apps, OSInodeInner};
 
+/// 文件的读写接口，返回实际读写的字节数。不支持的读写方向返回 EBADF
 pub trait File: Send + Sync {
-    fn read(&self, user_buf: UserBuffer) -> usize;
-    fn write(&self, user_buf: UserBuffer) -> usize;
+    fn read(&self, user_buf: UserBuffer) -> SysResult<usize>;
+    fn write(&self, user_buf: UserBuffer) -> SysResult<usize>;
+    /// 是否为终端（控制台），只有终端支持 TIOCGPGRP、TIOCSPGRP 等 ioctl
+    fn is_tty(&self) -> bool {
+        false
+    }
 }
@@ -1,7 +1,11 @@
 use alloc::{sync::Arc, sync::Weak};
 use spin::Mutex;
 
-use crate::{mm::UserBuffer, task::suspend_current_and_run_next};
+use crate::{
+    error::{SysError, SysResult},
+    mm::{SlabBox, UserBuffer},
+    task::suspend_current_and_run_next,
+};
 
 use super::File;
 
@@ -92,12 +96,13 @@ impl PipeRingBuffer {
 pub struct Pipe {
     readable: bool,
     writable: bool,
-    buffer: Arc<Mutex<PipeRingBuffer>>,
+    /// 读写两端共享的缓冲区，从 pipe_buffer 对象缓存中分配
+    buffer: Arc<SlabBox<Mutex<PipeRingBuffer>>>,
 }
 
 impl Pipe {
     /// 从已有的管道创建读端
-    pub fn read_end_with_buffer(buffer: Arc<Mutex<PipeRingBuffer>>) -> Self {
+    pub fn read_end_with_buffer(buffer: Arc<SlabBox<Mutex<PipeRingBuffer>>>) -> Self {
         Self {
             readable: true,
             writable: false,
@@ -106,7 +111,7 @@ impl Pipe {
     }
 
     /// 从已有管道创建写端
-    pub fn write_end_with_buffer(buffer: Arc<Mutex<PipeRingBuffer>>) -> Self {
+    pub fn write_end_with_buffer(buffer: Arc<SlabBox<Mutex<PipeRingBuffer>>>) -> Self {
         Self {
             readable: false,
             writable: true,
@@ -117,7 +122,7 @@ impl Pipe {
 
 /// 创建 pipe，并返回读端和写端
 pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
-    let buffer = Arc::new(Mutex::new(PipeRingBuffer::new()));
+    let buffer = Arc::new(SlabBox::construct());
     let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone()));
     let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone()));
     buffer.lock().set_write_end(&write_end);
@@ -125,8 +130,10 @@ pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
 }
 
 impl File for Pipe {
-    fn read(&self, user_buf: crate::mm::UserBuffer) -> usize {
-        assert_eq!(self.readable, true);
+    fn read(&self, user_buf: UserBuffer) -> SysResult<usize> {
+        if !self.readable {
+            return Err(SysError::EBADF);
+        }
         let mut buf_iter = user_buf.into_iter();
         let mut read_size = 0usize;
         loop {
@@ -135,7 +142,7 @@ impl File for Pipe {
             if loop_read == 0 {
                 // 对管道来说，写端已经关闭的情况下，则可以关闭了
                 if ring_buffer.all_write_ends_closed() {
-                    return read_size;
+                    return Ok(read_size);
                 }
                 // 由于下一句会切换进程，这里的上下文被切走，ring_buffer 的锁不会被
                 // 释放，所以需要手动释放一下
@@ -152,14 +159,16 @@ impl File for Pipe {
                     }
                     read_size += 1;
                 } else {
-                    return read_size;
+                    return Ok(read_size);
                 }
             }
         }
     }
 
-    fn write(&self, user_buf: crate::mm::UserBuffer) -> usize {
-        assert_eq!(self.writable, true);
+    fn write(&self, user_buf: UserBuffer) -> SysResult<usize> {
+        if !self.writable {
+            return Err(SysError::EBADF);
+        }
         let mut buf_iter = user_buf.into_iter();
         let mut write_size = 0usize;
         loop {
@@ -177,7 +186,7 @@ impl File for Pipe {
                     ring_buffer.write_byte(unsafe { *byte_ref });
                     write_size += 1;
                 } else {
-                    return write_size;
+                    return Ok(write_size);
                 }
             }
         }
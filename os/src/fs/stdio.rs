@@ -1,45 +1,155 @@
-use crate::{mm::UserBuffer, sbi::console_getchar, task::suspend_current_and_run_next};
+use alloc::collections::VecDeque;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::{
+    error::{SysError, SysResult},
+    mm::UserBuffer,
+    sbi::console_getchar,
+    task::{
+        current_process, current_task, send_signal_to_group, suspend_current_and_run_next,
+        SignalFlags, SIG_IGN,
+    },
+};
 
 use super::File;
 
+/// Ctrl-C，向前台进程组发送 SIGINT
+const CTRL_C: u8 = 0x03;
+/// Ctrl-Z，向前台进程组发送 SIGTSTP
+const CTRL_Z: u8 = 0x1a;
+
+/// 控制台终端的状态，整个系统只有一个控制台
+struct Console {
+    /// 已从 SBI 读入、还没有被进程读走的输入
+    input: VecDeque<u8>,
+    /// 前台进程组，为 0 时没有前台进程组：Ctrl-C、Ctrl-Z 被丢弃，任何进程都可以读取
+    foreground_pgid: usize,
+}
+
+lazy_static! {
+    static ref CONSOLE: Mutex<Console> = Mutex::new(Console {
+        input: VecDeque::new(),
+        foreground_pgid: 0,
+    });
+}
+
+/// 从 SBI 读入所有已到达的字符，Ctrl-C、Ctrl-Z 不进入输入缓冲，而是向前台进程组发送信号。
+/// 在时钟中断与 idle 控制流中调用，这样前台进程不读取输入时也能被打断
+pub fn poll_console() {
+    // 其它核正在读取时直接返回，不必在时钟中断中等待
+    let mut console = match CONSOLE.try_lock() {
+        Some(console) => console,
+        None => return,
+    };
+    loop {
+        // 没有输入时 SBI 返回 -1，有的实现返回 0
+        let c = console_getchar();
+        if c == 0 || c == usize::MAX {
+            break;
+        }
+        let signal = match c as u8 {
+            CTRL_C => SignalFlags::SIGINT,
+            CTRL_Z => SignalFlags::SIGTSTP,
+            ch => {
+                console.input.push_back(ch);
+                continue;
+            }
+        };
+        if console.foreground_pgid != 0 {
+            // 前台进程组可能已经全部退出
+            let _ = send_signal_to_group(console.foreground_pgid, signal);
+        }
+    }
+}
+
+/// 控制台的前台进程组，0 表示没有
+pub fn foreground_pgid() -> usize {
+    CONSOLE.lock().foreground_pgid
+}
+
+/// 设置控制台的前台进程组，由调用方检查进程组是否合法
+pub fn set_foreground_pgid(pgid: usize) {
+    CONSOLE.lock().foreground_pgid = pgid;
+}
+
+/// 后台进程组读取控制台：SIGTTIN 被忽略或屏蔽时返回 EIO，
+/// 否则向其进程组发送 SIGTTIN（默认动作为暂停）并返回 EINTR
+fn read_from_background(pgid: usize) -> SysResult<usize> {
+    let process = current_process();
+    let ignored = {
+        let inner = process.acquire_inner_lock();
+        inner.signal_actions.table[SignalFlags::SIGTTIN.signum()].handler == SIG_IGN
+    };
+    drop(process);
+    let blocked = current_task()
+        .unwrap()
+        .acquire_inner_lock()
+        .signal_mask
+        .contains(SignalFlags::SIGTTIN);
+    if ignored || blocked {
+        return Err(SysError::EIO);
+    }
+    send_signal_to_group(pgid, SignalFlags::SIGTTIN)?;
+    Err(SysError::EINTR)
+}
+
 pub struct Stdin;
 
 pub struct Stdout;
 
 impl File for Stdin {
-    fn read(&self, mut user_buf: UserBuffer) -> usize {
-        assert_eq!(user_buf.len(), 1);
-        let mut c: usize;
+    /// 每次只读取一个字符。没有输入时等待，等待期间可以被信号打断
+    fn read(&self, mut user_buf: UserBuffer) -> SysResult<usize> {
+        if user_buf.len() != 1 {
+            return Err(SysError::EINVAL);
+        }
+        let pgid = current_process().acquire_inner_lock().pgid;
         loop {
-            c = console_getchar();
-            if c == 0 {
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                break;
+            poll_console();
+            let mut console = CONSOLE.lock();
+            if console.foreground_pgid != 0 && console.foreground_pgid != pgid {
+                drop(console);
+                return read_from_background(pgid);
             }
+            if let Some(ch) = console.input.pop_front() {
+                drop(console);
+                unsafe {
+                    user_buf.buffers[0].as_mut_ptr().write_volatile(ch);
+                }
+                return Ok(1);
+            }
+            drop(console);
+            if current_task().unwrap().acquire_inner_lock().has_pending_signal() {
+                return Err(SysError::EINTR);
+            }
+            suspend_current_and_run_next();
         }
-        let ch = c as u8;
-        unsafe {
-            user_buf.buffers[0].as_mut_ptr().write_volatile(ch);
-        }
-        1
     }
 
-    fn write(&self, buf: UserBuffer) -> usize {
-        panic!("Cannot write to stdin!");
+    fn write(&self, _user_buf: UserBuffer) -> SysResult<usize> {
+        Err(SysError::EBADF)
+    }
+
+    fn is_tty(&self) -> bool {
+        true
     }
 }
 
 impl File for Stdout {
-    fn read(&self, user_buf: UserBuffer) -> usize {
-        panic!("Cannot read from stdout!");
+    fn read(&self, _user_buf: UserBuffer) -> SysResult<usize> {
+        Err(SysError::EBADF)
     }
 
-    fn write(&self, user_buf: UserBuffer) -> usize {
+    fn write(&self, user_buf: UserBuffer) -> SysResult<usize> {
         for buffer in user_buf.buffers.iter() {
-            print!("{}", core::str::from_utf8(*buffer).unwrap());
+            let s = core::str::from_utf8(*buffer).map_err(|_| SysError::EINVAL)?;
+            print!("{}", s);
         }
-        user_buf.len()
+        Ok(user_buf.len())
+    }
+
+    fn is_tty(&self) -> bool {
+        true
     }
 }
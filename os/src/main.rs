@@ -35,12 +35,15 @@ mod syscall;
 mod trap;
 mod loader;
 mod config;
+mod error;
 mod task;
 mod timer;
 mod mm;
 mod layout;
 mod fs;
 mod drivers;
+mod smp;
+mod sync;
 
 
 // fn shutdown() -> ! {
@@ -73,6 +76,9 @@ fn clear_bss() {
 // #[no_mangle] 提示编译器不要对函数进行混淆
 #[no_mangle]
 pub fn rust_main() -> ! {
+    if !smp::is_boot_hart() {
+        other_hart_main();
+    }
     extern "C" {
         fn stext();
         fn etext();
@@ -94,20 +100,44 @@ pub fn rust_main() -> ! {
         boot_stack as usize, boot_stack_top as usize
     );
     println!(".bss [{:#x}, {:#x})", sbss as usize, ebss as usize);
+    // entry.asm 无法引用 config.rs 中的常量，检查两处的核数与启动栈大小一致
+    assert_eq!(
+        boot_stack_top as usize - boot_stack as usize,
+        config::MAX_HART_NUM * config::BOOT_STACK_SIZE,
+        "boot stacks in entry.asm do not match MAX_HART_NUM"
+    );
     // panic!("Shutdown machine!");
-    println!("[kernel] Hello, world!");
+    println!("[kernel] Hello, world! (boot hart {})", smp::hart_id());
     mm::init();
     println!("[kernel] mm initilized");
     println!("[kernel] remap test");
     mm::remap_test();
+    mm::frame_allocator_test();
+    mm::slab_test();
     trap::init();
     println!("[kernel] trap init");
     trap::enable_timer_interrupt();
+    trap::enable_software_interrupt();
     println!("[kernel] timer interrupt enabled");
     timer::set_next_trigger();
     fs::list_apps();
     task::add_initproc();
     println!("[kernel] set first trigger");
+    // 全局数据初始化完成后再启动其它核
+    smp::start_other_harts();
     task::run_tasks();
     panic!("Unreachable in rust_main!");
 }
+
+/// 非启动核的入口：全局数据已由启动核初始化，只需要初始化本核的状态
+fn other_hart_main() -> ! {
+    smp::wait_for_boot_hart();
+    mm::init_other_hart();
+    trap::init();
+    trap::enable_timer_interrupt();
+    trap::enable_software_interrupt();
+    timer::set_next_trigger();
+    println!("[kernel] hart {} started", smp::hart_id());
+    task::run_tasks();
+    panic!("Unreachable in other_hart_main!");
+}
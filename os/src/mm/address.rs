@@ -234,6 +234,11 @@ where
     pub fn get_end(&self) -> T {
         self.r
     }
+
+    /// 判断 v 是否处于区间 [l, r) 内
+    pub fn contains(&self, v: T) -> bool {
+        self.l <= v && v < self.r
+    }
 }
 
 pub struct SimpleRangeIterator<T>
@@ -3,7 +3,11 @@ use core::fmt::{self, Debug, Formatter};
 use lazy_static::lazy_static;
 use spin::Mutex;
 
-use crate::{config::MEMORY_END, mm::PhysAddr};
+use crate::{
+    config::{KERNEL_RESERVED_FRAMES, MEMORY_END, PAGE_SIZE},
+    mm::PhysAddr,
+    task::swap_out_page,
+};
 
 use super::address::PhysPageNum;
 
@@ -37,84 +41,281 @@ impl Drop for FrameTracker {
     }
 }
 
+/// 页桢分配器的统计信息，单位均为页
+#[derive(Clone, Copy, Debug)]
+pub struct FrameStats {
+    /// 可分配的页桢总数
+    pub total: usize,
+    /// 空闲页桢数
+    pub free: usize,
+    /// 已分配的页桢数
+    pub used: usize,
+    /// 碎片：位于小于 FRAGMENT_PAGES 页的空闲块中、无法用于较大连续分配的空闲页桢数
+    pub fragmented: usize,
+    /// 最大的空闲块页数，即当前能够分配的最大连续页数
+    pub largest_free: usize,
+}
+
 /// 页桢管理器，负责物理页的分配和回收
 trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPageNum>;
+    /// 分配 pages 个连续的物理页，起始页号按 align 页对齐
+    fn alloc_contiguous(&mut self, pages: usize, align: usize) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
+    fn stats(&self) -> FrameStats;
 }
 
-/// 栈方式实现的物理页桢分配器
-pub struct StackFrameAllocator {
-    /// 物理页号区间[current, end)  此前均 从未 被分配出去过
-    current: usize,
+/// 伙伴系统的阶数上限，最大的空闲块为 2^(MAX_ORDER-1) 页
+const MAX_ORDER: usize = 16;
+/// 统计碎片时的界限，小于这么多页的空闲块算作碎片
+const FRAGMENT_PAGES: usize = 16;
+/// 空闲链表的空指针
+const NIL: usize = usize::MAX;
+
+/// 空闲链表的节点，存放在空闲块的第一页中
+#[derive(Clone, Copy)]
+struct FreeNode {
+    prev: usize,
+    next: usize,
+}
+
+/// 伙伴系统实现的物理页桢分配器。
+/// 2^k 页大小、起始页号按 2^k 对齐的块称为 k 阶块，同阶且合起来是 k+1 阶块的两个块互为伙伴。
+/// 分配时从满足要求的最小阶空闲块中切分，回收时与空闲的伙伴逐级合并，从而可以分配连续的物理页。
+/// 分配器不使用堆，空闲链表存放在空闲页本身中，每页一字节的元数据存放在可用区间开头预留的页中
+pub struct BuddyFrameAllocator {
+    /// 可分配的物理页号区间 [base, end)
+    base: usize,
     end: usize,
-    /// 以后入先出的方式保存了被回收的物理页号
-    // 此时已使用了堆
-    recycled: Vec<usize>,
+    /// 各阶空闲链表的表头
+    free_lists: [usize; MAX_ORDER],
+    /// 各阶空闲块的数量
+    free_blocks: [usize; MAX_ORDER],
+    /// 每页的元数据：k 阶空闲块的第一页为 k+1，其余页为 0
+    meta: &'static mut [u8],
 }
 
-impl StackFrameAllocator {
-    /// 初始化分配器可分配的物理页区间
+impl BuddyFrameAllocator {
+    /// 初始化分配器可分配的物理页区间，区间开头的几页用于存放元数据
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
-        self.current = l.0;
+        let meta_pages = (r.0 - l.0 + PAGE_SIZE - 1) / PAGE_SIZE;
+        self.base = l.0 + meta_pages;
         self.end = r.0;
-        println!("last {} Physical Frames.", self.end - self.current);
+        let meta_start: PhysAddr = l.into();
+        self.meta = unsafe {
+            core::slice::from_raw_parts_mut(meta_start.0 as *mut u8, self.end - self.base)
+        };
+        for byte in self.meta.iter_mut() {
+            *byte = 0;
+        }
+        self.free_range(self.base, self.end - self.base);
+        println!("last {} Physical Frames.", self.end - self.base);
+    }
+
+    fn node(ppn: usize) -> &'static mut FreeNode {
+        PhysPageNum(ppn).get_mut::<FreeNode>()
+    }
+
+    /// 将 order 阶空闲块 ppn 加入空闲链表
+    fn push(&mut self, order: usize, ppn: usize) {
+        let head = self.free_lists[order];
+        *Self::node(ppn) = FreeNode {
+            prev: NIL,
+            next: head,
+        };
+        if head != NIL {
+            Self::node(head).prev = ppn;
+        }
+        self.free_lists[order] = ppn;
+        self.free_blocks[order] += 1;
+        self.meta[ppn - self.base] = order as u8 + 1;
+    }
+
+    /// 将 order 阶空闲块 ppn 从空闲链表中取出
+    fn remove(&mut self, order: usize, ppn: usize) {
+        let FreeNode { prev, next } = *Self::node(ppn);
+        if prev == NIL {
+            self.free_lists[order] = next;
+        } else {
+            Self::node(prev).next = next;
+        }
+        if next != NIL {
+            Self::node(next).prev = prev;
+        }
+        self.free_blocks[order] -= 1;
+        self.meta[ppn - self.base] = 0;
+    }
+
+    /// 分配一个 order 阶块，没有更大的空闲块可以切分时返回 None
+    fn alloc_block(&mut self, order: usize) -> Option<usize> {
+        let mut k = (order..MAX_ORDER).find(|&k| self.free_lists[k] != NIL)?;
+        let ppn = self.free_lists[k];
+        self.remove(k, ppn);
+        // 逐级对半切分，后一半放回空闲链表
+        while k > order {
+            k -= 1;
+            self.push(k, ppn + (1 << k));
+        }
+        Some(ppn)
+    }
+
+    /// 回收 order 阶块 ppn，并与空闲的伙伴逐级合并
+    fn free_block(&mut self, mut ppn: usize, mut order: usize) {
+        while order + 1 < MAX_ORDER {
+            let buddy = ppn ^ (1 << order);
+            if buddy < self.base
+                || buddy >= self.end
+                || self.meta[buddy - self.base] != order as u8 + 1
+            {
+                break;
+            }
+            self.remove(order, buddy);
+            ppn = ppn.min(buddy);
+            order += 1;
+        }
+        self.push(order, ppn);
+    }
+
+    /// 回收从 ppn 开始的 pages 个页，区间被拆成尽可能大的对齐块
+    fn free_range(&mut self, mut ppn: usize, mut pages: usize) {
+        while pages > 0 {
+            // 不超过 pages 的最大的 2 的幂，同时受 ppn 的对齐限制
+            let order = (ppn.trailing_zeros() as usize)
+                .min((usize::MAX.count_ones() - 1 - pages.leading_zeros()) as usize)
+                .min(MAX_ORDER - 1);
+            self.free_block(ppn, order);
+            ppn += 1 << order;
+            pages -= 1 << order;
+        }
+    }
+
+    /// ppn 是否在某个空闲块中
+    fn is_free(&self, ppn: usize) -> bool {
+        (0..MAX_ORDER).any(|order| {
+            let head = ppn & !((1 << order) - 1);
+            head >= self.base && self.meta[head - self.base] == order as u8 + 1
+        })
     }
 }
 
-impl FrameAllocator for StackFrameAllocator {
+impl FrameAllocator for BuddyFrameAllocator {
     fn new() -> Self {
         Self {
-            current: 0,
+            base: 0,
             end: 0,
-            recycled: Vec::new(),
+            free_lists: [NIL; MAX_ORDER],
+            free_blocks: [0; MAX_ORDER],
+            meta: &mut [],
         }
     }
 
-    /// 从页桢资源池中分配需要的页
-    /// 算法：
-    /// 1. 优先从回收栈中选取一页返回，如果有，则直接返回；
-    /// 2. 从[current, end)中分配第一页后返回
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
-        } else {
-            if self.current == self.end {
-                None
-            } else {
-                self.current += 1;
-                Some((self.current - 1).into())
-            }
+        self.alloc_block(0).map(|ppn| ppn.into())
+    }
+
+    /// 分配能容纳 pages 页且满足对齐要求的最小块，多余的尾部立即回收
+    fn alloc_contiguous(&mut self, pages: usize, align: usize) -> Option<PhysPageNum> {
+        assert!(align.is_power_of_two(), "align {} is not a power of two", align);
+        if pages == 0 {
+            return None;
+        }
+        let order = (pages.next_power_of_two().trailing_zeros() as usize)
+            .max(align.trailing_zeros() as usize);
+        if order >= MAX_ORDER {
+            return None;
         }
+        let ppn = self.alloc_block(order)?;
+        self.free_range(ppn + pages, (1 << order) - pages);
+        Some(ppn.into())
     }
 
-    /// 回收到 recycled 链表中。
     fn dealloc(&mut self, ppn: PhysPageNum) {
         let ppn = ppn.0;
-        if ppn >= self.current || self.recycled.iter().find(|&v| *v == ppn).is_some() {
+        if ppn < self.base || ppn >= self.end || self.is_free(ppn) {
             panic!("Frame ppn={:#x} has not been allocated!", ppn);
         }
-        // recycle
-        self.recycled.push(ppn);
+        self.free_block(ppn, 0);
+    }
+
+    fn stats(&self) -> FrameStats {
+        let total = self.end - self.base;
+        let mut free = 0;
+        let mut fragmented = 0;
+        let mut largest_free = 0;
+        for order in 0..MAX_ORDER {
+            let pages = self.free_blocks[order] << order;
+            free += pages;
+            if (1 << order) < FRAGMENT_PAGES {
+                fragmented += pages;
+            }
+            if self.free_blocks[order] > 0 {
+                largest_free = 1 << order;
+            }
+        }
+        FrameStats {
+            total,
+            free,
+            used: total - free,
+            fragmented,
+            largest_free,
+        }
     }
 }
 
-type FrameAllocatorImpl = StackFrameAllocator;
+type FrameAllocatorImpl = BuddyFrameAllocator;
 
 lazy_static! {
-    /// 全局页桢分配器，目前使用栈分配方式实现
+    /// 全局页桢分配器，目前使用伙伴系统实现
     /// 采用 Mutex 获取可变性
     pub static ref FRAME_ALLOCATOR: Mutex<FrameAllocatorImpl> =
         Mutex::new(FrameAllocatorImpl::new());
 }
 
-/// 利用全局页桢分配器分配一个物理页桢
+/// 利用全局页桢分配器分配一个物理页桢。空闲页桢只剩为内核保留的 KERNEL_RESERVED_FRAMES 页时，
+/// 先换出某个进程的一页后重试，把保留的页桢留给不能换出页面的内核堆；
+/// 没有页面可以换出或交换区已满时才动用保留的页桢，仍然没有时返回 None
 pub fn frame_alloc() -> Option<FrameTracker> {
-    FRAME_ALLOCATOR
-        .lock()
-        .alloc()
-        .map(|ppn| FrameTracker::new(ppn))
+    loop {
+        // 换出页面时会回收页桢，需要先释放分配器的锁
+        let ppn = {
+            let mut allocator = FRAME_ALLOCATOR.lock();
+            if allocator.stats().free > KERNEL_RESERVED_FRAMES {
+                allocator.alloc()
+            } else {
+                None
+            }
+        };
+        if let Some(ppn) = ppn {
+            return Some(FrameTracker::new(ppn));
+        }
+        if !swap_out_page() {
+            return FRAME_ALLOCATOR.lock().alloc().map(FrameTracker::new);
+        }
+    }
+}
+
+/// 分配 pages 个物理上连续的页桢，起始页号按 align 页对齐（align 须为 2 的幂），用于 DMA 缓冲区、
+/// 大页等。不会为此换出页面，没有足够大的空闲块时返回 None
+pub fn frame_alloc_contiguous(pages: usize, align: usize) -> Option<Vec<FrameTracker>> {
+    let ppn = FRAME_ALLOCATOR.lock().alloc_contiguous(pages, align)?;
+    Some(
+        (ppn.0..ppn.0 + pages)
+            .map(|ppn| FrameTracker::new(ppn.into()))
+            .collect(),
+    )
+}
+
+/// 为内核堆分配 pages 个连续页桢，pages 须为 2 的幂，起始页号按 pages 对齐。
+/// 这些页桢交给堆后不再回收，所以不构造 FrameTracker；内核堆增长时持有堆的锁，这里也不能使用堆。
+/// 可以使用为内核保留的页桢
+pub fn frame_alloc_for_heap(pages: usize) -> Option<PhysPageNum> {
+    FRAME_ALLOCATOR.lock().alloc_contiguous(pages, pages)
+}
+
+/// 页桢分配器当前的统计信息
+pub fn frame_stats() -> FrameStats {
+    FRAME_ALLOCATOR.lock().stats()
 }
 
 /// 回收页桢
@@ -132,3 +333,22 @@ pub fn init_frame_allocator() {
         PhysAddr::from(MEMORY_END).floor(),
     );
 }
+
+/// 检查连续分配的对齐要求，以及回收后空闲块能够重新合并
+#[allow(unused)]
+pub fn frame_allocator_test() {
+    let before = frame_stats();
+    let single = frame_alloc().unwrap();
+    let frames = frame_alloc_contiguous(5, 4).unwrap();
+    assert_eq!(frames[0].ppn.0 % 4, 0);
+    for (i, frame) in frames.iter().enumerate() {
+        assert_eq!(frame.ppn.0, frames[0].ppn.0 + i);
+    }
+    assert_eq!(frame_stats().free, before.free - 6);
+    drop(frames);
+    drop(single);
+    let after = frame_stats();
+    assert_eq!(after.free, before.free);
+    assert_eq!(after.largest_free, before.largest_free);
+    println!("frame_allocator_test passed!");
+}
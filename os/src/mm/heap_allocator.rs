@@ -1,13 +1,117 @@
-use core::alloc::Layout;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-use buddy_system_allocator::LockedHeap;
+use buddy_system_allocator::Heap;
+use spin::Mutex;
 
-use crate::config::KERNEL_HEAP_SIZE;
+use crate::config::{KERNEL_HEAP_GROW_SIZE, KERNEL_HEAP_SIZE, PAGE_SIZE};
+
+use super::{frame_allocator::frame_alloc_for_heap, PhysAddr};
+
+/// 可以增长的内核堆：先使用 .bss 中的 HEAP_SPACE，用完后从页桢分配器取得连续的页桢加入堆中。
+/// 这些页桢位于 new_kernel 中恒等映射的 [ekernel, MEMORY_END) 区间，无需修改内核页表；
+/// 加入堆后不再归还给页桢分配器
+pub struct GrowableHeap {
+    heap: Mutex<Heap>,
+    /// 从页桢分配器取得的字节数
+    grown: AtomicUsize,
+}
+
+/// 内核堆的使用情况，单位均为字节
+#[derive(Clone, Copy, Debug)]
+pub struct HeapStats {
+    /// 堆的总大小，包括初始的 HEAP_SPACE 与增长的部分
+    pub total: usize,
+    /// 已分配给调用方的大小
+    pub user: usize,
+    /// 实际占用的大小，伙伴算法会将每次分配向上取整到 2 的幂
+    pub actual: usize,
+    /// 其中从页桢分配器增长的大小
+    pub grown: usize,
+}
+
+impl GrowableHeap {
+    const fn empty() -> Self {
+        Self {
+            heap: Mutex::new(Heap::empty()),
+            grown: AtomicUsize::new(0),
+        }
+    }
+
+    /// 为放下 layout 扩大堆，页桢不足时返回 false。
+    /// 优先一次增长 KERNEL_HEAP_GROW_SIZE，没有这么大的空闲块时逐次减半，直到恰好放下 layout 的
+    /// 最小的 2 的幂（至少一页）。新加入的块按自身大小对齐，一定能满足 layout 的大小与对齐要求。
+    /// 调用方持有堆的锁，所以这里不能使用堆，也不能换出页面（换出时会分配堆内存），只能依靠
+    /// 页桢分配器为内核保留的页桢。保留的页桢用完，或者超过一页的请求找不到足够大的连续空闲块时，
+    /// 分配仍会失败
+    fn grow(&self, heap: &mut Heap, layout: &Layout) -> bool {
+        let min_size = layout
+            .size()
+            .max(layout.align())
+            .max(PAGE_SIZE)
+            .next_power_of_two();
+        let mut size = min_size.max(KERNEL_HEAP_GROW_SIZE);
+        let start: usize = loop {
+            if let Some(ppn) = frame_alloc_for_heap(size / PAGE_SIZE) {
+                break PhysAddr::from(ppn).into();
+            }
+            if size == min_size {
+                return false;
+            }
+            size /= 2;
+        };
+        unsafe {
+            heap.add_to_heap(start, start + size);
+        }
+        self.grown.fetch_add(size, Ordering::Relaxed);
+        true
+    }
+
+    unsafe fn alloc_raw(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.heap.lock();
+        loop {
+            if let Ok(ptr) = heap.alloc(layout) {
+                return ptr.as_ptr();
+            }
+            if !self.grow(&mut heap, &layout) {
+                // 返回空指针，由 handle_alloc_error 报告
+                return core::ptr::null_mut();
+            }
+        }
+    }
+
+    unsafe fn dealloc_raw(&self, ptr: *mut u8, layout: Layout) {
+        self.heap
+            .lock()
+            .dealloc(NonNull::new_unchecked(ptr), layout);
+    }
+
+    fn stats(&self) -> HeapStats {
+        let heap = self.heap.lock();
+        HeapStats {
+            total: heap.stats_total_bytes(),
+            user: heap.stats_alloc_user(),
+            actual: heap.stats_alloc_actual(),
+            grown: self.grown.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// alloc 库需要提供一个全局动态内存分配器，用#[global_allocator] 标记（注册）。
+// 对象缓存中的对象由 SlabBox 显式分配，不经过这里
+unsafe impl GlobalAlloc for GrowableHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.alloc_raw(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.dealloc_raw(ptr, layout)
+    }
+}
 
-// LockedHeap 实现了 GlobalAlloc 要求的抽象接口
-// alloc 库需要提供一个全局动态内存分配器，用#[global_allocator] 标记（注册）
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+static HEAP_ALLOCATOR: GrowableHeap = GrowableHeap::empty();
 
 /// 用于分配的内核堆空间
 // 全局初始化数据，链接后被放置于 .bss 段中
@@ -15,18 +119,34 @@ static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 
 /// 初始化内核堆，支持使用动态数据结构。
 /// HEAP_SPACE 分配的空间实际是编译器预留的空间（通过数组类型指定），主要用于支持在内核
-/// 中进行动态数据分配。
+/// 中进行动态数据分配。用完后堆会从页桢分配器增长，所以页桢分配器需要随后初始化，且本身不能使用堆
 pub fn init_heap() {
     unsafe {
         HEAP_ALLOCATOR
+            .heap
             .lock()
             .init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE);
     }
 }
 
+/// 内核堆当前的使用情况
+pub fn heap_stats() -> HeapStats {
+    HEAP_ALLOCATOR.stats()
+}
+
+/// 对象缓存从堆中申请 slab
+pub unsafe fn heap_alloc(layout: Layout) -> *mut u8 {
+    HEAP_ALLOCATOR.alloc_raw(layout)
+}
+
+pub unsafe fn heap_dealloc(ptr: *mut u8, layout: Layout) {
+    HEAP_ALLOCATOR.dealloc_raw(ptr, layout)
+}
+
 // 使用 #[alloc_error_handler] 宏注册错误处理函数，对应 main.rs 的 feature
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: Layout) -> ! {
+    super::report_memory();
     panic!("Heap allocation error, layout = {:?}", layout);
 }
 
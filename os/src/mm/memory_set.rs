@@ -1,11 +1,12 @@
 use core::fmt::Debug;
 
-use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
 use riscv::register::satp;
 use spin::Mutex;
 
 use crate::{
-    config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE},
+    config::{MEMORY_END, MMAP_BASE, MMIO, PAGE_SIZE, TRAMPOLINE, USER_SPACE_END},
+    error::{SysError, SysResult},
     mm::address::StepByOne,
 };
 
@@ -15,7 +16,12 @@ use lazy_static::lazy_static;
 use super::{
     address::{PhysAddr, VPNRange, VirtAddr, VirtPageNum},
     frame_allocator::FrameTracker,
-    page_table::PageTable,
+    page_table::{
+        copy_from_user, copy_to_user, translated_byte_buffer, translated_str, PageTable,
+        UserBuffer,
+    },
+    swap::SwapSlot,
+    tlb::{self, Asid},
 };
 
 // 由 linker 指定，定义内核镜像的符号
@@ -47,6 +53,22 @@ lazy_static! {
     /// 内核地址空间：处于
     pub static ref KERNEL_SPACE: Arc<Mutex<MemorySet>> =
         Arc::new(Mutex::new(MemorySet::new_kernel()));
+    /// 内核页表的 token。内核页表的根页桢不会改变，缓存后获取 token 无需锁住 KERNEL_SPACE：
+    /// 换出页面时块设备驱动要用它翻译地址，而分配页桢的调用方可能正持有 KERNEL_SPACE 的锁
+    static ref KERNEL_TOKEN: usize = KERNEL_SPACE.lock().token();
+}
+
+/// 缺页处理的结果
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PageFault {
+    /// 非法访问
+    Invalid,
+    /// 无需读块设备即处理完成：延迟分配、写时复制，或已被其它核处理
+    Minor,
+    /// 从交换区读回了被换出的页面
+    Major,
+    /// 物理内存与交换区都已耗尽
+    OutOfMemory,
 }
 
 /// 地址空间：描述一个任务的内存分配情况
@@ -56,20 +78,46 @@ pub struct MemorySet {
     page_table: PageTable,
     /// 已映射的逻辑连续段
     areas: Vec<MapArea>,
+    /// 用户堆底，即 elf 各段结束后的第一页，堆逻辑段从这里开始
+    heap_bottom: usize,
+    /// 当前堆顶（program break），由 brk 系统调用调整
+    brk: usize,
+    /// 换出页面时时钟算法的指针，下一次从这一页开始扫描
+    clock_hand: VirtPageNum,
+    /// 地址空间的 ASID，随地址空间释放而回收
+    asid: Asid,
 }
 
 impl MemorySet {
     /// 创建一个空的地址空间
     pub fn new_bare() -> Self {
+        Self::new_bare_with_asid(Asid::alloc())
+    }
+
+    fn new_bare_with_asid(asid: Asid) -> Self {
         Self {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            heap_bottom: 0,
+            brk: 0,
+            clock_hand: VirtPageNum(0),
+            asid,
         }
     }
 
-    /// 返回页表对应的 token
+    /// 返回页表对应的 token，其中包含地址空间的 ASID
     pub fn token(&self) -> usize {
-        self.page_table.token()
+        self.asid.token(self.page_table.token())
+    }
+
+    /// 去掉 [start_vpn, end_vpn) 的映射或权限后刷新各核 TLB 中本地址空间的页表项
+    fn flush_tlb(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) {
+        tlb::shootdown(self.token(), start_vpn, end_vpn);
+    }
+
+    /// 缺页处理完成后刷新本核 TLB 中 vpn 的页表项，其中可能还缓存着出错时的页表项
+    pub fn flush_local_tlb(&self, vpn: VirtPageNum) {
+        tlb::flush_local(self.token(), vpn);
     }
 
     /// 通过 vpn 查找其对应的页表项
@@ -78,19 +126,233 @@ impl MemorySet {
         self.page_table.translate(vpn)
     }
 
-    /// 插入一个 area，使用页方式映射
+    /// 插入一个 area，使用页方式映射。页桢不足时返回 ENOMEM，地址空间保持不变
     pub fn insert_framed_area(
         &mut self,
         start_va: VirtAddr,
         end_va: VirtAddr,
         permission: MapPermission,
+    ) -> SysResult<()> {
+        self.try_push(
+            MapArea::new(start_va, end_va, MapType::Framed, permission),
+            None,
+        )
+    }
+
+    /// 插入一个 area，使用 Lazy 方式映射，页面在第一次访问时才分配并清零
+    pub fn insert_lazy_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
     ) {
         self.push(
-            MapArea::new(start_va, end_va, MapType::Framed, permission),
+            MapArea::new(start_va, end_va, MapType::Lazy, permission),
             None,
         );
     }
 
+    /// 所有逻辑段的大小之和（字节），即 RLIMIT_AS 限制的地址空间大小，延迟分配的页面也计算在内
+    pub fn mapped_size(&self) -> usize {
+        self.areas
+            .iter()
+            .map(|area| (area.vpn_range.get_end().0 - area.vpn_range.get_start().0) * PAGE_SIZE)
+            .sum()
+    }
+
+    /// 虚拟页区间 [start_vpn, end_vpn) 是否与已有的逻辑段重叠
+    pub fn is_overlapping(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        self.areas.iter().any(|area| {
+            area.vpn_range.get_start() < end_vpn && start_vpn < area.vpn_range.get_end()
+        })
+    }
+
+    /// 在 [from, limit) 中从低到高查找第一段能容纳 pages 个连续虚拟页的空闲区间，返回其起始页号
+    pub fn find_free_area(
+        &self,
+        from: VirtPageNum,
+        limit: VirtPageNum,
+        pages: usize,
+    ) -> Option<VirtPageNum> {
+        let mut ranges: Vec<(VirtPageNum, VirtPageNum)> = self
+            .areas
+            .iter()
+            .map(|area| (area.vpn_range.get_start(), area.vpn_range.get_end()))
+            .collect();
+        ranges.sort_by_key(|range| range.0);
+        let mut start = from;
+        for (l, r) in ranges {
+            if r <= start {
+                continue;
+            }
+            if l.0 >= start.0 + pages {
+                break;
+            }
+            start = r;
+        }
+        if start.0 + pages <= limit.0 {
+            Some(start)
+        } else {
+            None
+        }
+    }
+
+    /// 检查 [start_vpn, end_vpn) 是否被用户逻辑段完整覆盖，并将跨越区间边界的逻辑段拆分开，
+    /// 使之后每个逻辑段要么完全处于区间内，要么完全处于区间外。
+    /// 区间存在空洞或者涉及内核私有的逻辑段（如 TrapContext）时返回 false，此时不做任何修改。
+    fn split_range(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        let mut ranges: Vec<(VirtPageNum, VirtPageNum, MapPermission)> = self
+            .areas
+            .iter()
+            .filter(|area| {
+                area.vpn_range.get_start() < end_vpn && start_vpn < area.vpn_range.get_end()
+            })
+            .map(|area| {
+                (
+                    area.vpn_range.get_start(),
+                    area.vpn_range.get_end(),
+                    area.map_perm,
+                )
+            })
+            .collect();
+        ranges.sort_by_key(|range| range.0);
+        let mut covered = start_vpn;
+        for (l, r, perm) in ranges {
+            if l > covered || !perm.contains(MapPermission::U) {
+                return false;
+            }
+            covered = covered.max(r);
+        }
+        if covered < end_vpn {
+            return false;
+        }
+        self.split_at(start_vpn);
+        self.split_at(end_vpn);
+        true
+    }
+
+    /// 将跨越 at 的逻辑段在 at 处拆分为两段
+    fn split_at(&mut self, at: VirtPageNum) {
+        if let Some(area) = self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_start() < at && at < area.vpn_range.get_end())
+        {
+            let upper = area.split_off(at);
+            self.areas.push(upper);
+        }
+    }
+
+    /// 取消 [start_vpn, end_vpn) 的映射并回收其中的页面，用于 munmap。
+    /// 区间必须被用户逻辑段完整覆盖，否则返回 EINVAL
+    pub fn unmap_range(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> SysResult<()> {
+        if !self.split_range(start_vpn, end_vpn) {
+            return Err(SysError::EINVAL);
+        }
+        let mut idx = 0;
+        while idx < self.areas.len() {
+            let area = &self.areas[idx];
+            if start_vpn <= area.vpn_range.get_start() && area.vpn_range.get_end() <= end_vpn {
+                let mut area = self.areas.remove(idx);
+                area.unmap(&mut self.page_table);
+            } else {
+                idx += 1;
+            }
+        }
+        self.flush_tlb(start_vpn, end_vpn);
+        Ok(())
+    }
+
+    /// 修改 [start_vpn, end_vpn) 的访问权限，用于 mprotect。
+    /// 区间必须被用户逻辑段完整覆盖，否则返回 ENOMEM
+    pub fn protect_range(
+        &mut self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+        permission: MapPermission,
+    ) -> SysResult<()> {
+        if !self.split_range(start_vpn, end_vpn) {
+            return Err(SysError::ENOMEM);
+        }
+        for area in self.areas.iter_mut().filter(|area| {
+            start_vpn <= area.vpn_range.get_start() && area.vpn_range.get_end() <= end_vpn
+        }) {
+            area.map_perm = permission;
+            let flags = PTEFlags::from_bits(permission.bits).unwrap();
+            for (vpn, frame) in area.data_frame.iter() {
+                // 仍被写时复制共享的页保持只读，写入时再由缺页处理恢复写权限
+                if Arc::strong_count(frame) > 1 {
+                    self.page_table.set_flags(*vpn, flags - PTEFlags::W);
+                } else {
+                    self.page_table.set_flags(*vpn, flags);
+                }
+            }
+        }
+        self.flush_tlb(start_vpn, end_vpn);
+        Ok(())
+    }
+
+    /// 当前堆顶
+    pub fn brk(&self) -> usize {
+        self.brk
+    }
+
+    /// 将堆顶调整到 new_brk，堆随之扩大或缩小。堆可能已被 mprotect、munmap 拆分成多段：
+    /// 缩小时与 munmap 一样回收 [新堆顶, 旧堆顶) 中的所有页面，不要求区间没有空洞；
+    /// 扩大时只延伸结束于旧堆顶的最高一段（权限须仍为可读写），没有这样的段时新建一段。
+    /// 堆顶不能低于堆底，也不能达到 MMAP_BASE；扩大的部分不能与其它逻辑段重叠，
+    /// 且与上方的逻辑段之间至少留出一个守护页。
+    pub fn set_brk(&mut self, new_brk: usize) -> bool {
+        if new_brk < self.heap_bottom || new_brk >= MMAP_BASE {
+            return false;
+        }
+        let heap_start_vpn = VirtAddr::from(self.heap_bottom).floor();
+        let old_end_vpn = VirtAddr::from(self.brk).ceil();
+        let new_end_vpn = VirtAddr::from(new_brk).ceil();
+        if new_end_vpn < old_end_vpn {
+            self.split_at(new_end_vpn);
+            self.split_at(old_end_vpn);
+            let mut idx = 0;
+            while idx < self.areas.len() {
+                let area = &self.areas[idx];
+                if area.map_perm.contains(MapPermission::U)
+                    && new_end_vpn <= area.vpn_range.get_start()
+                    && area.vpn_range.get_end() <= old_end_vpn
+                {
+                    let mut area = self.areas.remove(idx);
+                    area.unmap(&mut self.page_table);
+                } else {
+                    idx += 1;
+                }
+            }
+            self.flush_tlb(new_end_vpn, old_end_vpn);
+        } else if new_end_vpn > old_end_vpn {
+            if self.is_overlapping(old_end_vpn, VirtPageNum(new_end_vpn.0 + 1)) {
+                return false;
+            }
+            let heap_perm = MapPermission::R | MapPermission::W | MapPermission::U;
+            match self.areas.iter_mut().find(|area| {
+                area.map_type == MapType::Lazy
+                    && area.map_perm == heap_perm
+                    && heap_start_vpn <= area.vpn_range.get_start()
+                    && area.vpn_range.get_end() == old_end_vpn
+            }) {
+                Some(area) => area.set_end(&mut self.page_table, new_end_vpn),
+                None => self.push(
+                    MapArea::new(
+                        old_end_vpn.into(),
+                        new_end_vpn.into(),
+                        MapType::Lazy,
+                        heap_perm,
+                    ),
+                    None,
+                ),
+            }
+        }
+        self.brk = new_brk;
+        true
+    }
+
     /// 释放逻辑段
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
         if let Some((idx, area)) = self
@@ -100,7 +362,9 @@ impl MemorySet {
             .find(|(_, area)| area.vpn_range.get_start() == start_vpn)
         {
             area.unmap(&mut self.page_table);
+            let end_vpn = area.vpn_range.get_end();
             self.areas.remove(idx);
+            self.flush_tlb(start_vpn, end_vpn);
         }
     }
 
@@ -117,17 +381,25 @@ impl MemorySet {
     }
 
     /// 增加一个逻辑段，并使用 data 对其进行初始化。
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    /// Framed 逻辑段分配不到页桢时返回 ENOMEM，已映射的页面被回收，地址空间保持不变
+    fn try_push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) -> SysResult<()> {
+        map_area.map(&mut self.page_table)?;
         if let Some(data) = data {
             map_area.copy_data(&mut self.page_table, data);
         }
         self.areas.push(map_area);
+        Ok(())
+    }
+
+    /// 增加一个恒等映射或 Lazy 逻辑段，它们在插入时不分配页桢，不会失败
+    fn push(&mut self, map_area: MapArea, data: Option<&[u8]>) {
+        assert!(map_area.map_type != MapType::Framed);
+        self.try_push(map_area, data).unwrap();
     }
 
     /// 返回 kernel 的地址空间（不含内核栈）
     pub fn new_kernel() -> Self {
-        let mut memory_set = Self::new_bare();
+        let mut memory_set = Self::new_bare_with_asid(Asid::zero());
         memory_set.map_trampoline();
         // map kernel sections
         println!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
@@ -207,29 +479,43 @@ impl MemorySet {
         memory_set
     }
 
-    /// 根据 elf 文件解析出对应的地址空间
+    /// 根据 elf 文件解析出对应的地址空间，elf 各段都以 Lazy 方式映射
     /// 返回
     /// - 应用的地址空间
-    /// - 用户栈顶: 向下生长，最大尺寸为 USER_STACK_SIZE。位于最高虚拟逻辑段顶部一页后面的空间。
     /// - 应用程序入口
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
+    /// 用户堆从 elf 最高逻辑段的结束处开始，初始为空，向上增长。
+    /// 各线程的用户栈与 TrapContext 不在这里映射，由线程创建时按 tid 分配，见 TaskUserRes。
+    /// elf 文件可能由用户写入，program header 不合法、段的数据超出文件、段超出用户空间
+    /// 或段之间相互重叠时返回 ENOEXEC
+    pub fn from_elf(elf_data: &[u8]) -> SysResult<(Self, usize)> {
         let mut memory_set = Self::new_bare();
         memory_set.map_trampoline();
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
+        let elf = xmas_elf::ElfFile::new(elf_data).map_err(|_| SysError::ENOEXEC)?;
         let elf_header = elf.header;
-        assert_eq!(
-            elf_header.pt1.magic,
-            [0x7f, 0x45, 0x4c, 0x46],
-            "invalid elf"
-        );
+        if elf_header.pt1.magic != [0x7f, 0x45, 0x4c, 0x46] {
+            return Err(SysError::ENOEXEC);
+        }
         let ph_count = elf_header.pt2.ph_count();
         let mut max_end_vpn = VirtPageNum(0);
         // 遍历 program header 数组
         for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
+            let ph = elf.program_header(i).map_err(|_| SysError::ENOEXEC)?;
+            if ph.get_type().map_err(|_| SysError::ENOEXEC)? == xmas_elf::program::Type::Load {
+                let data_end = ph
+                    .offset()
+                    .checked_add(ph.file_size())
+                    .filter(|end| *end <= elf.input.len() as u64 && ph.file_size() <= ph.mem_size())
+                    .ok_or(SysError::ENOEXEC)?;
+                let end = ph
+                    .virtual_addr()
+                    .checked_add(ph.mem_size())
+                    .filter(|end| *end < USER_SPACE_END as u64)
+                    .ok_or(SysError::ENOEXEC)?;
                 let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
+                let end_va: VirtAddr = (end as usize).into();
+                if memory_set.is_overlapping(start_va.floor(), end_va.ceil()) {
+                    return Err(SysError::ENOEXEC);
+                }
                 // 用户空间程序，借助硬件检查
                 let mut map_perm = MapPermission::U;
                 let ph_flags = ph.flags();
@@ -242,76 +528,383 @@ impl MemorySet {
                 if ph_flags.is_write() {
                     map_perm |= MapPermission::W;
                 }
-                let map_area = MapArea::new(
+                let mut map_area = MapArea::new(
                     start_va,
                     end_va,
-                    MapType::Framed, // 用户空间的都不使用恒等映射
+                    MapType::Lazy, // 用户空间的都不使用恒等映射，且在访问时才加载
                     map_perm,
                 );
-                max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                map_area.lazy_data = Some(Arc::new(
+                    elf.input[ph.offset() as usize..data_end as usize].to_vec(),
+                ));
+                max_end_vpn = max_end_vpn.max(map_area.vpn_range.get_end());
+                memory_set.push(map_area, None);
             }
         }
 
-        // 映射用户栈
+        // 映射用户堆，初始为空逻辑段，由 brk 调整大小
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // 每个用户栈底部（栈向下生长）插入一个空虚拟页，用于守护边界，
-        // 避免访问到其它应用的数据。硬件会对地址进行检查，这些空页不会存数据。
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
+        memory_set.heap_bottom = max_end_va.into();
+        memory_set.brk = memory_set.heap_bottom;
         memory_set.push(
             MapArea::new(
-                user_stack_bottom.into(),
-                user_stack_top.into(),
-                MapType::Framed,
+                max_end_va,
+                max_end_va,
+                MapType::Lazy,
                 MapPermission::R | MapPermission::W | MapPermission::U,
             ),
             None,
         );
 
-        // 映射 TrapContext
-        memory_set.push(
-            MapArea::new(
-                TRAP_CONTEXT.into(),
-                TRAMPOLINE.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
-        (
-            memory_set,
-            user_stack_top,
-            elf.header.pt2.entry_point() as usize,
-        )
+        Ok((memory_set, elf.header.pt2.entry_point() as usize))
     }
 
     /// 从现成的用户地址空间复制一份新的。用于 fork.
-    /// 拷贝所有逻辑段（含trap_context/user_stack/数据段），并映射跳板
-    /// todo: 这里没有实现 COW?
-    pub fn from_existed_user(user_space: &Self) -> Self {
+    /// 采用写时复制（COW）：用户逻辑段的物理页桢不做拷贝，由父子地址空间共享，并在双方页表中
+    /// 去掉写权限。之后任意一方写这些页时会触发 StorePageFault，由 handle_page_fault 完成真正的复制。
+    /// 已换出的页面由父子进程共享交换区中的槽位，之后各自换入到自己的页桢中。
+    /// 各线程 TrapContext 所在逻辑段仅内核可访问，且每个任务必须独占，所以仍然直接复制，
+    /// 没有页桢复制时返回 ENOMEM。
+    pub fn from_existed_user(user_space: &mut Self) -> SysResult<Self> {
         let mut memory_set = Self::new_bare();
         // 跳板没有在逻辑段中，所以单独映射
         memory_set.map_trampoline();
-        // copy data sections/trap_context/user_stack
+        memory_set.heap_bottom = user_space.heap_bottom;
+        memory_set.brk = user_space.brk;
         for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
-            // copy data
-            for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
-                // 上面的 push 已完成了映射
-                let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
-                dst_ppn
-                    .get_bytes_array()
-                    .copy_from_slice(src_ppn.get_bytes_array());
+            let mut new_area = MapArea::from_another(area);
+            if !area.map_perm.contains(MapPermission::U) {
+                // copy trap_context
+                memory_set.try_push(new_area, None)?;
+                for vpn in area.vpn_range {
+                    let src_ppn = user_space.translate(vpn).unwrap().ppn();
+                    // 上面的 push 已完成了映射
+                    let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
+                    dst_ppn
+                        .get_bytes_array()
+                        .copy_from_slice(src_ppn.get_bytes_array());
+                }
+                continue;
             }
+            // 共享页在父子双方均只读，可写逻辑段的父进程页表也需要同步去掉写权限
+            let shared_flags = PTEFlags::from_bits(area.map_perm.bits).unwrap() - PTEFlags::W;
+            let writable = area.map_perm.contains(MapPermission::W);
+            for (vpn, frame) in area.data_frame.iter() {
+                if writable {
+                    user_space.page_table.set_flags(*vpn, shared_flags);
+                }
+                memory_set.page_table.map(*vpn, frame.ppn, shared_flags);
+                new_area.data_frame.insert(*vpn, Arc::clone(frame));
+            }
+            // 驻留页面在交换区中保留的副本只对父进程的页表项有效，不复制给子进程
+            for (vpn, slot) in area.swap_slots.iter() {
+                if !area.data_frame.contains_key(vpn) {
+                    memory_set
+                        .page_table
+                        .replace(*vpn, PageTableEntry::swapped(slot.id()));
+                    new_area.swap_slots.insert(*vpn, Arc::clone(slot));
+                }
+            }
+            memory_set.areas.push(new_area);
         }
-        memory_set
+        // 父进程的 TLB 中可能还缓存着可写的页表项
+        user_space.flush_tlb(VirtPageNum(0), VirtAddr::from(USER_SPACE_END).ceil());
+        Ok(memory_set)
+    }
+
+    /// 处理用户地址空间的缺页异常，is_write 表示是否为写访问。可以处理三种情况：
+    /// 1. Lazy 逻辑段中尚未分配的页：分配页桢，并用逻辑段的初始数据填充（没有则为全0）；
+    /// 2. 已换出的页：分配页桢，从交换区读回页面内容；
+    /// 3. 写时复制：vpn 所在逻辑段本身可写，但页表项被 fork 去掉了写权限。此时如果页桢已无
+    ///    其它地址空间共享（引用计数为1），直接恢复写权限；否则分配新的页桢，复制数据后重新映射。
+    /// 分配页桢时物理内存不足，会换出其它页面腾出空间。
+    pub fn handle_page_fault(&mut self, vpn: VirtPageNum, is_write: bool) -> PageFault {
+        let idx = match self
+            .areas
+            .iter()
+            .position(|area| area.vpn_range.contains(vpn))
+        {
+            Some(idx) => idx,
+            None => return PageFault::Invalid,
+        };
+        let area = &self.areas[idx];
+        if area.map_type == MapType::Lazy && !area.data_frame.contains_key(&vpn) {
+            let frame = match self.alloc_frame() {
+                Some(frame) => frame,
+                None => return PageFault::OutOfMemory,
+            };
+            // 换出页面不会增删逻辑段，idx 仍然指向 vpn 所在的逻辑段。
+            // 权限不足的访问会在重新执行时再次陷入，届时按非法访问处理
+            return if self.areas[idx].map_lazy(&mut self.page_table, vpn, frame) {
+                PageFault::Major
+            } else {
+                PageFault::Minor
+            };
+        }
+        if !is_write || !area.map_perm.contains(MapPermission::W) {
+            return PageFault::Invalid;
+        }
+        let pte = match self.page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() => pte,
+            _ => return PageFault::Invalid,
+        };
+        if pte.writable() {
+            // 已被处理过（例如内核提前解除了共享），重新执行指令即可
+            return PageFault::Minor;
+        }
+        let flags = pte.flags() | PTEFlags::W;
+        if Arc::strong_count(area.data_frame.get(&vpn).unwrap()) == 1 {
+            self.page_table.set_flags(vpn, flags);
+        } else {
+            // 共享的页桢引用计数大于1，不会在分配新页桢时被换出
+            let new_frame = match self.alloc_frame() {
+                Some(frame) => frame,
+                None => return PageFault::OutOfMemory,
+            };
+            let area = &mut self.areas[idx];
+            new_frame
+                .ppn
+                .get_bytes_array()
+                .copy_from_slice(area.data_frame.get(&vpn).unwrap().ppn.get_bytes_array());
+            self.page_table.unmap(vpn);
+            self.page_table.map(vpn, new_frame.ppn, flags);
+            area.data_frame.insert(vpn, Arc::new(new_frame));
+            // 交换区中保留的副本对应原来的页桢
+            area.swap_slots.remove(&vpn);
+            // 同一进程的其它线程可能还缓存着指向共享页桢的页表项
+            self.flush_tlb(vpn, VirtPageNum(vpn.0 + 1));
+        }
+        PageFault::Minor
+    }
+
+    /// 为本地址空间分配一个页桢。物理内存耗尽时 frame_alloc 会换出其它进程的页面，
+    /// 仍然失败时换出本地址空间自己的页面；交换区也已满时返回 None
+    fn alloc_frame(&mut self) -> Option<FrameTracker> {
+        loop {
+            if let Some(frame) = frame_alloc() {
+                return Some(frame);
+            }
+            if !self.swap_out_one() {
+                return None;
+            }
+        }
+    }
+
+    /// 用时钟（二次机会）算法选出一页换出到交换区，没有可换出的页面或交换区已满时返回 false。
+    /// 只考虑 Lazy 逻辑段中引用计数为1的页桢：写时复制共享的页桢以及内核正在访问的页桢都不会被换出。
+    /// 指针扫过 A 位为 1 的页面时清除 A 位，给它第二次机会；A、D 位均为 0 且交换区中保留着副本的
+    /// 页面无需写回，遇到时直接换出，否则换出扫描中遇到的第一个 A 位为 0 的页面。
+    /// TLB 中缓存着 A 位为 1 的页表项时，硬件再次访问该页不会重新设置 A 位，所以清除 A 位后
+    /// 要刷新各核 TLB 中的这些页表项。清除的页面可能很多，扫描结束后对其所在范围统一刷新一次
+    pub fn swap_out_one(&mut self) -> bool {
+        let mut candidates: Vec<(VirtPageNum, usize)> = Vec::new();
+        for (idx, area) in self.areas.iter().enumerate() {
+            if area.map_type != MapType::Lazy {
+                continue;
+            }
+            for (vpn, frame) in area.data_frame.iter() {
+                if Arc::strong_count(frame) == 1 {
+                    candidates.push((*vpn, idx));
+                }
+            }
+        }
+        if candidates.is_empty() {
+            return false;
+        }
+        candidates.sort_by_key(|candidate| candidate.0);
+        let n = candidates.len();
+        let start = candidates
+            .iter()
+            .position(|candidate| candidate.0 >= self.clock_hand)
+            .unwrap_or(0);
+        let mut victim = None;
+        // 被清除 A 位的页面范围 [cleared_start, cleared_end)
+        let mut cleared_start = usize::MAX;
+        let mut cleared_end = 0;
+        // 第一圈清除了所有 A 位，最迟在第二圈的第一页选出页面
+        for i in 0..2 * n {
+            let (vpn, idx) = candidates[(start + i) % n];
+            let flags = self.page_table.translate(vpn).unwrap().flags();
+            if flags.contains(PTEFlags::A) {
+                self.page_table.remove_flags(vpn, PTEFlags::A);
+                cleared_start = cleared_start.min(vpn.0);
+                cleared_end = cleared_end.max(vpn.0 + 1);
+                continue;
+            }
+            if !flags.contains(PTEFlags::D) && self.areas[idx].swap_slots.contains_key(&vpn) {
+                victim = Some((vpn, idx));
+                break;
+            }
+            if victim.is_none() {
+                victim = Some((vpn, idx));
+            }
+            if i + 1 >= n {
+                break;
+            }
+        }
+        if cleared_start < cleared_end {
+            self.flush_tlb(VirtPageNum(cleared_start), VirtPageNum(cleared_end));
+        }
+        let (vpn, idx) = victim.unwrap();
+        if !self.swap_out_page(idx, vpn) {
+            return false;
+        }
+        self.clock_hand = VirtPageNum(vpn.0 + 1);
+        true
+    }
+
+    /// 将第 idx 个逻辑段中的驻留页面 vpn 换出到交换区，交换区已满时返回 false，页面保持不变
+    fn swap_out_page(&mut self, idx: usize, vpn: VirtPageNum) -> bool {
+        let token = self.token();
+        let area = &mut self.areas[idx];
+        // 先使页表项失效并刷新各核 TLB，之后 D 位不会再变化。
+        // 其它线程此时访问该页会陷入缺页处理，在进程锁上等待换出完成
+        let old = self.page_table.replace(vpn, PageTableEntry::empty());
+        tlb::shootdown(token, vpn, VirtPageNum(vpn.0 + 1));
+        let ppn = old.ppn();
+        let slot = match area.swap_slots.get(&vpn) {
+            // 换入后没有被修改过，交换区中的副本仍然有效
+            Some(slot) if !old.flags().contains(PTEFlags::D) => Arc::clone(slot),
+            // 副本没有被 fork 出的子进程共享，可以原地覆盖
+            Some(slot) if Arc::strong_count(slot) == 1 => {
+                slot.write(ppn);
+                Arc::clone(slot)
+            }
+            _ => match SwapSlot::alloc() {
+                Some(slot) => {
+                    slot.write(ppn);
+                    Arc::new(slot)
+                }
+                None => {
+                    self.page_table.replace(vpn, old);
+                    return false;
+                }
+            },
+        };
+        self.page_table.replace(vpn, PageTableEntry::swapped(slot.id()));
+        area.swap_slots.insert(vpn, slot);
+        area.data_frame.remove(&vpn);
+        true
+    }
+
+    /// vpn 所在的页桢，页面未驻留时返回 None
+    fn find_frame(&self, vpn: VirtPageNum) -> Option<Arc<FrameTracker>> {
+        self.areas
+            .iter()
+            .find(|area| area.vpn_range.contains(vpn))
+            .and_then(|area| area.data_frame.get(&vpn).cloned())
+    }
+
+    /// vpn 的页表项是否已允许用户态以 flags 方式访问。
+    /// 多核下同一进程的多个线程可能同时在某页上缺页，后处理的一方发现页面已被映射时，
+    /// 用它判断缺页是否已经被其它核处理
+    pub fn is_user_accessible(&self, vpn: VirtPageNum, flags: PTEFlags) -> bool {
+        match self.page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() => pte.flags().contains(flags | PTEFlags::U),
+            _ => false,
+        }
+    }
+
+    /// 内核通过恒等映射直接读写用户页，不经过 MMU，也就不会触发缺页。
+    /// 所以内核访问用户缓冲区 [start_va, start_va + len) 前，需要先完成其中页面的延迟分配与换入；
+    /// 如果要写入，还要解除写时复制共享，否则会把数据写进其它地址空间仍在共享的页桢里，
+    /// 同时代替 MMU 设置 D 位。
+    /// 返回区间内各页的页桢，调用方持有期间这些页面不会被换出。
+    /// 区间越过用户空间、有页面未映射，或用户态不能按 is_write 指定的方式访问时返回 EFAULT，
+    /// 内存耗尽时返回 ENOMEM
+    pub fn fault_in_range(
+        &mut self,
+        start_va: VirtAddr,
+        len: usize,
+        is_write: bool,
+    ) -> SysResult<Vec<Arc<FrameTracker>>> {
+        let mut frames = Vec::new();
+        if len == 0 {
+            return Ok(frames);
+        }
+        match start_va.0.checked_add(len) {
+            Some(end) if end <= USER_SPACE_END => {}
+            _ => return Err(SysError::EFAULT),
+        }
+        let end_va = VirtAddr::from(start_va.0 + len);
+        let mut required = PTEFlags::R;
+        if is_write {
+            required |= PTEFlags::W;
+        }
+        for vpn in VPNRange::new(start_va.floor(), end_va.ceil()) {
+            // 之前的页面已被 frames 固定，为后面的页面分配页桢时不会被换出
+            if self.handle_page_fault(vpn, is_write) == PageFault::OutOfMemory {
+                return Err(SysError::ENOMEM);
+            }
+            if !self.is_user_accessible(vpn, required) {
+                return Err(SysError::EFAULT);
+            }
+            if is_write {
+                self.page_table.insert_flags(vpn, PTEFlags::D);
+            }
+            frames.extend(self.find_frame(vpn));
+        }
+        Ok(frames)
+    }
+
+    /// 与 fault_in_range 类似，用于以 \0 结尾、长度未知的用户字符串
+    pub fn fault_in_str(&mut self, start_va: VirtAddr) -> SysResult<Vec<Arc<FrameTracker>>> {
+        let mut frames = Vec::new();
+        let mut vpn = start_va.floor();
+        let mut offset = start_va.page_offset();
+        loop {
+            if VirtAddr::from(vpn).0 >= USER_SPACE_END {
+                return Err(SysError::EFAULT);
+            }
+            if self.handle_page_fault(vpn, false) == PageFault::OutOfMemory {
+                return Err(SysError::ENOMEM);
+            }
+            let pte = match self.page_table.translate(vpn) {
+                Some(pte) if pte.is_valid() && pte.flags().contains(PTEFlags::U | PTEFlags::R) => {
+                    pte
+                }
+                _ => return Err(SysError::EFAULT),
+            };
+            frames.extend(self.find_frame(vpn));
+            if pte.ppn().get_bytes_array()[offset..].contains(&0) {
+                return Ok(frames);
+            }
+            vpn.step();
+            offset = 0;
+        }
+    }
+
+    /// 从用户地址 ptr 处读取一个 T，T 可以跨越页边界。地址不可读时返回 EFAULT
+    pub fn copy_in<T: Copy>(&mut self, ptr: *const T) -> SysResult<T> {
+        let _frames =
+            self.fault_in_range(VirtAddr::from(ptr as usize), core::mem::size_of::<T>(), false)?;
+        copy_from_user(self.token(), ptr)
+    }
+
+    /// 将 value 写到用户地址 ptr 处，T 可以跨越页边界。地址不可写时返回 EFAULT
+    pub fn copy_out<T: Copy>(&mut self, ptr: *mut T, value: &T) -> SysResult<()> {
+        let _frames =
+            self.fault_in_range(VirtAddr::from(ptr as usize), core::mem::size_of::<T>(), true)?;
+        copy_to_user(self.token(), ptr, value)
+    }
+
+    /// 读取用户地址 ptr 处以 \0 结尾的字符串。字符串所在的页不可读时返回 EFAULT
+    pub fn copy_in_str(&mut self, ptr: *const u8) -> SysResult<String> {
+        let _frames = self.fault_in_str(VirtAddr::from(ptr as usize))?;
+        translated_str(self.token(), ptr)
+    }
+
+    /// 将用户缓冲区 [ptr, ptr + len) 转化为内核可以直接读写的 UserBuffer，
+    /// is_write 表示内核是否要写入。缓冲区不能按该方式访问时返回 EFAULT
+    pub fn user_buffer(
+        &mut self,
+        ptr: *const u8,
+        len: usize,
+        is_write: bool,
+    ) -> SysResult<UserBuffer> {
+        let frames = self.fault_in_range(VirtAddr::from(ptr as usize), len, is_write)?;
+        let buffers = translated_byte_buffer(self.token(), ptr, len, is_write)?;
+        Ok(UserBuffer::new(buffers, frames))
     }
 
     /// 启动地址空间（页表）
@@ -329,6 +922,8 @@ impl MemorySet {
     pub fn recycle_data_pages(&mut self) {
         // 删除列表会触发 drop trait，进而回收所有页面
         self.areas.clear();
+        // 进程的线程都已退出，不会再回到用户态，无需等到回收进程时才回收 ASID
+        self.asid = Asid::zero();
     }
 }
 
@@ -339,6 +934,9 @@ pub enum MapType {
     Identical,
     /// 按页映射，涉及到动态映射
     Framed,
+    /// 延迟映射：创建时只记录逻辑段，页面在第一次访问触发缺页时才分配，
+    /// 并由逻辑段的初始数据（没有则为全0）填充
+    Lazy,
 }
 
 bitflags! {
@@ -362,14 +960,24 @@ pub struct MapArea {
     vpn_range: VPNRange,
 
     /// 本逻辑段内已分配的虚拟页 -> 物理页的 map
-    /// 只在 Framed 方式时有效
-    data_frame: BTreeMap<VirtPageNum, FrameTracker>,
+    /// 只在 Framed 方式时有效。页桢可能因 fork 的写时复制被多个地址空间共享，
+    /// 由 Arc 维护其引用计数，最后一个引用释放时才回收页桢
+    data_frame: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
+
+    /// 只在 Lazy 方式时有效，记录页面在交换区中的槽位。不在 data_frame 中的页面已被换出；
+    /// 同时在 data_frame 中的页面是换入后保留的副本，页表项的 D 位为 0 时与页桢内容一致。
+    /// 槽位可能因 fork 被多个地址空间共享，由 Arc 维护其引用计数
+    swap_slots: BTreeMap<VirtPageNum, Arc<SwapSlot>>,
 
     /// 整个虚拟逻辑段的映射方式，各页面间保持一致
     map_type: MapType,
 
     /// 本逻辑段映射的权限
     map_perm: MapPermission,
+
+    /// Lazy 逻辑段的初始数据，从逻辑段起始页开始存放，超出部分为0。
+    /// 一般是 elf 中对应段的内容，fork 后由父子进程共享
+    lazy_data: Option<Arc<Vec<u8>>>,
 }
 
 impl Debug for MapArea {
@@ -379,6 +987,7 @@ impl Debug for MapArea {
             .field("data_frame", &self.data_frame)
             .field("map_type", &self.map_type)
             .field("map_perm", &self.map_perm)
+            .field("lazy_data_len", &self.lazy_data.as_ref().map(|data| data.len()))
             .finish()
     }
 }
@@ -395,8 +1004,10 @@ impl MapArea {
         Self {
             vpn_range: VPNRange::new(start_vpn, end_vpn),
             data_frame: BTreeMap::new(),
+            swap_slots: BTreeMap::new(),
             map_type,
             map_perm,
+            lazy_data: None,
         }
     }
 
@@ -405,11 +1016,52 @@ impl MapArea {
         Self {
             vpn_range: VPNRange::new(other.vpn_range.get_start(), other.vpn_range.get_end()),
             data_frame: BTreeMap::new(),
+            swap_slots: BTreeMap::new(),
             map_type: other.map_type,
             map_perm: other.map_perm,
+            lazy_data: other.lazy_data.clone(),
         }
     }
 
+    /// 在 at 处将逻辑段一分为二：本逻辑段保留 [start, at)，返回 [at, end) 部分。
+    /// 已分配的页面、交换区槽位和初始数据随虚拟页一起划分
+    pub fn split_off(&mut self, at: VirtPageNum) -> Self {
+        let start = self.vpn_range.get_start();
+        let end = self.vpn_range.get_end();
+        assert!(start < at && at < end, "split {:?} out of {:?}", at, self.vpn_range);
+        let lazy_data = self.lazy_data.as_ref().map(|data| {
+            let offset = (at.0 - start.0) * PAGE_SIZE;
+            if offset < data.len() {
+                Arc::new(data[offset..].to_vec())
+            } else {
+                Arc::new(Vec::new())
+            }
+        });
+        let upper = Self {
+            vpn_range: VPNRange::new(at, end),
+            data_frame: self.data_frame.split_off(&at),
+            swap_slots: self.swap_slots.split_off(&at),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            lazy_data,
+        };
+        self.vpn_range = VPNRange::new(start, at);
+        upper
+    }
+
+    /// 调整 Lazy 逻辑段的结束页，缩小时回收超出部分已分配的页面
+    pub fn set_end(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
+        assert_eq!(self.map_type, MapType::Lazy);
+        let start = self.vpn_range.get_start();
+        let end = self.vpn_range.get_end();
+        if new_end < end {
+            for vpn in VPNRange::new(new_end, end) {
+                self.unmap_one(page_table, vpn);
+            }
+        }
+        self.vpn_range = VPNRange::new(start, new_end);
+    }
+
     /// 将 data 中的数据拷贝到 MapArea 中，且利用 page_table 查询本逻辑段实际的物理页
     pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
         assert_eq!(self.map_type, MapType::Framed);
@@ -437,39 +1089,101 @@ impl MapArea {
     /// 映射一页虚拟页：会根据本 MapArea 的映射类型，确定虚拟页映射到物理页的方法。
     /// 如果是恒等映射，则直接将虚拟页号与物理页号相等即可；
     /// 如果是 framed ，则从 [ekernel, MEMEORY_END) 区间内分配一页
-    /// 并前 vpn:ppn 的关系写到 pte 中
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+    /// 并前 vpn:ppn 的关系写到 pte 中；
+    /// 如果是 lazy，同样分配一页，并填充该页对应的初始数据。
+    /// 分配不到页桢（交换区也已满）时返回 ENOMEM
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> SysResult<()> {
         let ppn: PhysPageNum;
         match self.map_type {
             MapType::Identical => {
                 ppn = PhysPageNum(vpn.0);
             }
             MapType::Framed => {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc().ok_or(SysError::ENOMEM)?;
                 ppn = frame.ppn;
-                self.data_frame.insert(vpn, frame);
+                self.data_frame.insert(vpn, Arc::new(frame));
+            }
+            MapType::Lazy => {
+                let frame = frame_alloc().ok_or(SysError::ENOMEM)?;
+                self.map_lazy(page_table, vpn, frame);
+                return Ok(());
             }
         }
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         page_table.map(vpn, ppn, pte_flags);
+        Ok(())
     }
 
-    /// 去除 vpn 的映射，包括数据页和页表项
+    /// 将 Lazy 逻辑段中的 vpn 映射到 frame：页面被换出过则从交换区读回，否则用初始数据填充。
+    /// 换入后交换区中的副本仍然保留，页面被修改之前再次换出时无需写回。
+    /// 映射时设置 A 位，刚换入的页面不会马上被选中换出。返回是否从交换区读回了页面
+    fn map_lazy(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        frame: FrameTracker,
+    ) -> bool {
+        let major = match self.swap_slots.get(&vpn) {
+            Some(slot) => {
+                slot.read(frame.ppn);
+                true
+            }
+            None => {
+                if let Some(data) = &self.lazy_data {
+                    let start = (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE;
+                    if start < data.len() {
+                        let end = data.len().min(start + PAGE_SIZE);
+                        frame.ppn.get_bytes_array()[..end - start]
+                            .copy_from_slice(&data[start..end]);
+                    }
+                }
+                false
+            }
+        };
+        // 换出标记的 V 位为 0，可以直接覆盖
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap() | PTEFlags::A;
+        page_table.map(vpn, frame.ppn, pte_flags);
+        self.data_frame.insert(vpn, Arc::new(frame));
+        major
+    }
+
+    /// 去除 vpn 的映射，包括数据页、交换区槽位和页表项
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         match self.map_type {
             MapType::Framed => {
                 self.data_frame.remove(&vpn);
             }
+            MapType::Lazy => {
+                let swapped = self.swap_slots.remove(&vpn).is_some();
+                if self.data_frame.remove(&vpn).is_none() {
+                    if swapped {
+                        // 已被换出，清除页表项中的换出标记
+                        page_table.replace(vpn, PageTableEntry::empty());
+                    }
+                    // 否则从未被访问过，没有建立映射
+                    return;
+                }
+            }
             _ => {}
         }
         page_table.unmap(vpn);
     }
 
-    /// 将本逻辑段的连续虚拟页映射到页表中
-    pub fn map(&mut self, page_table: &mut PageTable) {
+    /// 将本逻辑段的连续虚拟页映射到页表中，Lazy 逻辑段推迟到缺页时再映射。
+    /// 分配不到页桢时回收已经映射的页面，返回 ENOMEM
+    pub fn map(&mut self, page_table: &mut PageTable) -> SysResult<()> {
+        if self.map_type == MapType::Lazy {
+            return Ok(());
+        }
         for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+            if let Err(err) = self.map_one(page_table, vpn) {
+                for mapped in VPNRange::new(self.vpn_range.get_start(), vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return Err(err);
+            }
         }
+        Ok(())
     }
 
     /// 取消本逻辑段的页映射
@@ -481,7 +1195,7 @@ impl MapArea {
 }
 
 pub fn kernel_token() -> usize {
-    KERNEL_SPACE.lock().token()
+    *KERNEL_TOKEN
 }
 
 #[allow(unused)]
@@ -3,20 +3,45 @@ mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+mod slab;
+mod swap;
+mod tlb;
 
 pub use address::{StepByOne, VPNRange};
 pub use page_table::{PTEFlags, PageTable};
 
 pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 pub use frame_allocator::{frame_alloc, FrameTracker, frame_dealloc};
+pub use frame_allocator::{frame_alloc_contiguous, frame_allocator_test, frame_stats, FrameStats};
+pub use heap_allocator::{heap_stats, HeapStats};
 pub use memory_set::remap_test;
-pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
+pub use memory_set::{MapPermission, MemorySet, PageFault, KERNEL_SPACE};
 pub use page_table::UserBuffer;
-pub use page_table::{translated_byte_buffer, translated_refmut, translated_str, PageTableEntry, translated_ref};
+pub use slab::{slab_stats, slab_test, CacheStats, SlabBox};
+pub use page_table::{translate_user_va, PageTableEntry};
+pub use page_table::{copy_bytes_to_user, copy_to_user};
 pub use memory_set::kernel_token;
+pub use tlb::{flush_pending, on_kernel_entry, on_user_return, tlb_stats, TlbStats};
 
 pub fn init() {
     heap_allocator::init_heap();
     frame_allocator::init_frame_allocator();
     KERNEL_SPACE.lock().activate();
+    tlb::init();
+    // 缓存内核页表 token，之后获取时不再需要锁住 KERNEL_SPACE
+    kernel_token();
+}
+
+/// 打印页桢、内核堆与各对象缓存的使用情况，内存耗尽时用于排查
+pub fn report_memory() {
+    println!("[kernel] {:?}", frame_stats());
+    println!("[kernel] {:?}", heap_stats());
+    for stats in slab_stats() {
+        println!("[kernel] {:?}", stats);
+    }
+}
+
+/// 非启动核的初始化：堆与页帧分配器由各核共享，只需要启用内核地址空间
+pub fn init_other_hart() {
+    KERNEL_SPACE.lock().activate();
 }
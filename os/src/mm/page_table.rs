@@ -1,8 +1,14 @@
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use alloc::{string::String, vec};
 use bitflags::*;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use super::PhysAddr;
+use crate::{
+    config::{PAGE_SIZE, USER_SPACE_END},
+    error::{SysError, SysResult},
+};
 use super::{
     address::{PhysPageNum, VirtPageNum},
     frame_allocator::{frame_alloc, FrameTracker},
@@ -33,6 +39,10 @@ bitflags! {
     }
 }
 
+/// 页表项中保留给软件使用的 RSW 位之一，标记页面已被换出到交换区。
+/// 此时 V 为 0，10~53 位保存交换区槽位编号
+const PTE_SWAPPED: usize = 1 << 8;
+
 /// 页表项，一项8字节，主要有两部分组成：
 /// 0~7：PTE flags
 /// 10~53：44位物理页号
@@ -54,6 +64,13 @@ impl PageTableEntry {
         Self { bits: 0 }
     }
 
+    /// 已换出到交换区槽位 slot 的页面的页表项
+    pub fn swapped(slot: usize) -> Self {
+        Self {
+            bits: slot << 10 | PTE_SWAPPED,
+        }
+    }
+
     /// 获取物理页号，10~53 共 44 位
     pub fn ppn(&self) -> PhysPageNum {
         (self.bits >> 10 & ((1usize << 44) - 1)).into()
@@ -67,6 +84,10 @@ impl PageTableEntry {
     pub fn is_valid(&self) -> bool {
         self.flags() & PTEFlags::V != PTEFlags::empty()
     }
+    /// 页面是否已被换出
+    pub fn is_swapped(&self) -> bool {
+        !self.is_valid() && self.bits & PTE_SWAPPED != 0
+    }
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
@@ -154,6 +175,32 @@ impl PageTable {
         result
     }
 
+    /// 以原子方式访问 vpn 的页表项。用户页的 A、D 位由 MMU 在其它核上并发设置，
+    /// 读改写这类页表项时需要原子操作，否则可能丢失 MMU 设置的 D 位
+    fn find_pte_atomic(&mut self, vpn: VirtPageNum) -> &AtomicUsize {
+        let pte = self.find_pte_create(vpn).unwrap();
+        unsafe { &*(&mut pte.bits as *mut usize as *const AtomicUsize) }
+    }
+
+    /// 将 vpn 的页表项替换为 pte，返回原来的页表项
+    pub fn replace(&mut self, vpn: VirtPageNum, pte: PageTableEntry) -> PageTableEntry {
+        PageTableEntry {
+            bits: self.find_pte_atomic(vpn).swap(pte.bits, Ordering::SeqCst),
+        }
+    }
+
+    /// 为已映射的 vpn 添加标志位
+    pub fn insert_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        self.find_pte_atomic(vpn)
+            .fetch_or(flags.bits as usize, Ordering::SeqCst);
+    }
+
+    /// 去掉已映射的 vpn 的标志位
+    pub fn remove_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        self.find_pte_atomic(vpn)
+            .fetch_and(!(flags.bits as usize), Ordering::SeqCst);
+    }
+
     /// 在任务页表中建立虚拟页号到物理页号间的映射(最终会被 MMU 消费)
     /// 这里的 ppn 是最终数据页，页表组织树的中间结点在 find_pte_create 中完成
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
@@ -170,6 +217,18 @@ impl PageTable {
         *pte = PageTableEntry::empty();
     }
 
+    /// 修改已映射虚拟页的标志位，其对应的物理页保持不变。
+    /// A、D 位保持原样，换出时依靠 D 位判断页面是否需要写回交换区
+    pub fn set_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        let pte = self.find_pte_atomic(vpn);
+        let _ = pte.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |bits| {
+            let old = PageTableEntry { bits };
+            assert!(old.is_valid(), "vpn {:?} is invalid before setting flags", vpn);
+            let kept = old.flags() & (PTEFlags::A | PTEFlags::D);
+            Some(PageTableEntry::new(old.ppn(), flags | kept | PTEFlags::V).bits)
+        });
+    }
+
     /// 转换虚拟页号对应的页表项。
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.find_pte(vpn).map(|pte| pte.clone())
@@ -186,43 +245,100 @@ impl PageTable {
     }
 }
 
+/// 检查用户态能否以 is_write 指定的方式访问 va 所在的页：页面需要已映射且带有 U、R 标志，
+/// 写入时还需要带有 W 标志。可以访问时返回 va 对应的物理地址，否则返回 EFAULT
+pub fn translate_user_va(token: usize, va: usize, is_write: bool) -> SysResult<PhysAddr> {
+    if va >= USER_SPACE_END {
+        return Err(SysError::EFAULT);
+    }
+    let mut required = PTEFlags::V | PTEFlags::U | PTEFlags::R;
+    if is_write {
+        required |= PTEFlags::W;
+    }
+    let va = VirtAddr::from(va);
+    match PageTable::from_token(token).translate(va.floor()) {
+        Some(pte) if pte.flags().contains(required) => {
+            let aligned_pa: usize = PhysAddr::from(pte.ppn()).into();
+            Ok(PhysAddr::from(aligned_pa + va.page_offset()))
+        }
+        _ => Err(SysError::EFAULT),
+    }
+}
+
 /// 将应用地址空间中一个缓冲区转化为在内核空间中能够直接访问的形式
 /// token: 页表 token
 /// ptr: 应用虚拟地址起点
 /// len: buffer 长度
+/// is_write: 内核是否要写入该缓冲区
 ///
-/// return: 含可访问区域的页列表
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token);
+/// return: 含可访问区域的页列表，每一项不跨页；任何一页不能按 is_write 指定的方式访问时返回 EFAULT
+pub fn translated_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+    is_write: bool,
+) -> SysResult<Vec<&'static mut [u8]>> {
     let mut start = ptr as usize;
-    let end = start + len;
+    let end = start.checked_add(len).ok_or(SysError::EFAULT)?;
     let mut v = Vec::new();
     while start < end {
-        let start_va = VirtAddr::from(start);
-        let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
-        vpn.step();
-        let mut end_va: VirtAddr = vpn.into();
-        end_va = end_va.min(VirtAddr::from(end));
-        if end_va.page_offset() == 0 {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
-        } else {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
-        }
-        start = end_va.into();
+        let pa: usize = translate_user_va(token, start, is_write)?.into();
+        // start 低于 USER_SPACE_END，所在页的结束地址不会溢出
+        let page_end = (start / PAGE_SIZE + 1) * PAGE_SIZE;
+        let chunk_end = page_end.min(end);
+        v.push(unsafe { core::slice::from_raw_parts_mut(pa as *mut u8, chunk_end - start) });
+        start = chunk_end;
     }
-    v
+    Ok(v)
+}
+
+/// 从 token 指向的地址空间中读取 ptr 处的 T，T 可以跨越页边界。地址不可读时返回 EFAULT
+pub fn copy_from_user<T: Copy>(token: usize, ptr: *const T) -> SysResult<T> {
+    let len = core::mem::size_of::<T>();
+    let mut value = core::mem::MaybeUninit::<T>::uninit();
+    let dst = unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, len) };
+    let mut copied = 0;
+    for buffer in translated_byte_buffer(token, ptr as *const u8, len, false)? {
+        dst[copied..copied + buffer.len()].copy_from_slice(buffer);
+        copied += buffer.len();
+    }
+    Ok(unsafe { value.assume_init() })
+}
+
+/// 将 value 写到 token 指向的地址空间中的 ptr 处，T 可以跨越页边界。地址不可写时返回 EFAULT，
+/// 此时不会写入任何数据
+pub fn copy_to_user<T: Copy>(token: usize, ptr: *mut T, value: &T) -> SysResult<()> {
+    let len = core::mem::size_of::<T>();
+    let src = unsafe { core::slice::from_raw_parts(value as *const T as *const u8, len) };
+    let mut copied = 0;
+    for buffer in translated_byte_buffer(token, ptr as *const u8, len, true)? {
+        buffer.copy_from_slice(&src[copied..copied + buffer.len()]);
+        copied += buffer.len();
+    }
+    Ok(())
+}
+
+/// 将 data 写到 token 指向的地址空间中从 ptr 开始的区域，地址不可写时返回 EFAULT
+pub fn copy_bytes_to_user(token: usize, ptr: *mut u8, data: &[u8]) -> SysResult<()> {
+    let mut copied = 0;
+    for buffer in translated_byte_buffer(token, ptr, data.len(), true)? {
+        buffer.copy_from_slice(&data[copied..copied + buffer.len()]);
+        copied += buffer.len();
+    }
+    Ok(())
 }
 
 /// 用户空间数据缓冲区; 位于应用地址空间。
 pub struct UserBuffer {
     /// 数据缓冲区
     pub buffers: Vec<&'static mut [u8]>,
+    /// 缓冲区所在的页桢。持有引用期间页面不会被换出，内核可以在释放进程锁后继续读写
+    frames: Vec<Arc<FrameTracker>>,
 }
 
 impl UserBuffer {
-    pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
-        Self { buffers }
+    pub fn new(buffers: Vec<&'static mut [u8]>, frames: Vec<Arc<FrameTracker>>) -> Self {
+        Self { buffers, frames }
     }
 
     /// 链表中所有片段数据的总长
@@ -243,6 +359,7 @@ impl IntoIterator for UserBuffer {
     fn into_iter(self) -> Self::IntoIter {
         UserBufferIterator {
             buffers: self.buffers,
+            _frames: self.frames,
             current_buffer: 0,
             current_idx: 0,
         }
@@ -251,6 +368,7 @@ impl IntoIterator for UserBuffer {
 
 pub struct UserBufferIterator {
     buffers: Vec<&'static mut [u8]>,
+    _frames: Vec<Arc<FrameTracker>>,
     current_buffer: usize,
     current_idx: usize,
 }
@@ -274,42 +392,25 @@ impl Iterator for UserBufferIterator {
     }
 }
 
-/// 通过 token 指向的地址空间页表，读取 ptr 所指向的字符串
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_token(token);
+/// 通过 token 指向的地址空间页表，读取 ptr 所指向的以 \0 结尾的字符串，字符串可以跨越页边界。
+/// 字符串所在的页不可读时返回 EFAULT
+pub fn translated_str(token: usize, ptr: *const u8) -> SysResult<String> {
     let mut string = String::new();
     let mut va = ptr as usize;
     loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
-        if ch == 0 {
-            // 以 \0 结尾
-            break;
-        } else {
-            string.push(ch as char);
-            va += 1;
+        let pa: usize = translate_user_va(token, va, false)?.into();
+        // 逐页读取，直到遇到 \0
+        let page_end = (va / PAGE_SIZE + 1) * PAGE_SIZE;
+        let bytes = unsafe { core::slice::from_raw_parts(pa as *const u8, page_end - va) };
+        match bytes.iter().position(|ch| *ch == 0) {
+            Some(len) => {
+                string.extend(bytes[..len].iter().map(|ch| *ch as char));
+                return Ok(string);
+            }
+            None => {
+                string.extend(bytes.iter().map(|ch| *ch as char));
+                va = page_end;
+            }
         }
     }
-    string
 }
-
-/// 通过 token 指向的地址空间页表，读取 ptr 所指向的T指针
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
-    let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
-}
-
-pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
-    let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_ref()
-}
\ No newline at end of file
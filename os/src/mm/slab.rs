@@ -0,0 +1,392 @@
+//! 内核对象缓存（slab 分配器）。
+//!
+//! 频繁创建、销毁的内核对象各自使用一个缓存：缓存从内核堆中申请 slab，每个 slab 是一块按自身大小
+//! 对齐的连续内存，开头是 slab 头，之后切分成大小相同的对象。同类对象集中在一起，不会在伙伴堆中
+//! 留下大小不一的碎片，也可以按缓存统计各子系统占用的内核内存。
+//!
+//! 对象由 SlabBox 持有：各类型通过 Cached 指定自己的缓存，在创建对象的地方显式地从缓存分配，
+//! 不经过全局分配器。进程、线程、打开的文件这些对象本身被 Arc 共享，大部分状态放在其中由
+//! SlabBox 持有的可变部分里，Arc 只在堆中留下很小的外壳。
+
+use core::alloc::Layout;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{null_mut, NonNull};
+
+use easy_fs::BLOCK_SZ;
+use spin::Mutex;
+
+use super::heap_allocator::{handle_alloc_error, heap_alloc, heap_dealloc};
+use crate::{
+    config::PAGE_SIZE,
+    fs::{OSInodeInner, PipeRingBuffer},
+    task::{PCBInner, TCBInner},
+};
+
+/// 每个 slab 至少容纳的对象数，对象较大时 slab 相应变大
+const SLAB_MIN_OBJECTS: usize = 8;
+
+// fork、创建线程
+static PCB_CACHE: ObjectCache = ObjectCache::new("pcb", Layout::new::<Mutex<PCBInner>>(), None);
+static TCB_CACHE: ObjectCache = ObjectCache::new("tcb", Layout::new::<Mutex<TCBInner>>(), None);
+// 创建管道
+static PIPE_BUFFER_CACHE: ObjectCache = ObjectCache::new(
+    "pipe_buffer",
+    Layout::new::<Mutex<PipeRingBuffer>>(),
+    Some(pipe_buffer_ctor),
+);
+// 打开文件
+static OS_INODE_CACHE: ObjectCache =
+    ObjectCache::new("os_inode", Layout::new::<Mutex<OSInodeInner>>(), None);
+// BlockCacheManager 换入换出块缓存
+static BLOCK_BUFFER_CACHE: ObjectCache = ObjectCache::new(
+    "block_buffer",
+    Layout::new::<[u8; BLOCK_SZ]>(),
+    Some(block_buffer_ctor),
+);
+
+/// 各子系统的对象缓存
+static CACHES: [&ObjectCache; 5] = [
+    &PCB_CACHE,
+    &TCB_CACHE,
+    &PIPE_BUFFER_CACHE,
+    &OS_INODE_CACHE,
+    &BLOCK_BUFFER_CACHE,
+];
+
+/// 在缓存中就地构造空的管道缓冲区
+fn pipe_buffer_ctor(object: *mut u8) {
+    unsafe { (object as *mut Mutex<PipeRingBuffer>).write(Mutex::new(PipeRingBuffer::new())) }
+}
+
+/// 块缓冲区清零，随后由块设备读入内容
+fn block_buffer_ctor(object: *mut u8) {
+    unsafe { (object as *mut [u8; BLOCK_SZ]).write_bytes(0, 1) }
+}
+
+/// 从对象缓存中分配的类型，cache 返回该类型专用的缓存
+pub trait Cached: Sized {
+    fn cache() -> &'static ObjectCache;
+}
+
+impl Cached for Mutex<PCBInner> {
+    fn cache() -> &'static ObjectCache {
+        &PCB_CACHE
+    }
+}
+
+impl Cached for Mutex<TCBInner> {
+    fn cache() -> &'static ObjectCache {
+        &TCB_CACHE
+    }
+}
+
+impl Cached for Mutex<PipeRingBuffer> {
+    fn cache() -> &'static ObjectCache {
+        &PIPE_BUFFER_CACHE
+    }
+}
+
+impl Cached for Mutex<OSInodeInner> {
+    fn cache() -> &'static ObjectCache {
+        &OS_INODE_CACHE
+    }
+}
+
+impl Cached for [u8; BLOCK_SZ] {
+    fn cache() -> &'static ObjectCache {
+        &BLOCK_BUFFER_CACHE
+    }
+}
+
+/// 从类型 T 的对象缓存中分配的对象，与 Box 类似，离开作用域时析构并归还给缓存
+pub struct SlabBox<T: Cached>(NonNull<T>);
+
+// 与 Box 一样，SlabBox 独占其中的对象
+unsafe impl<T: Cached + Send> Send for SlabBox<T> {}
+unsafe impl<T: Cached + Sync> Sync for SlabBox<T> {}
+
+impl<T: Cached> SlabBox<T> {
+    /// 从缓存中分配内存，内存不足时与 Box 一样交给 handle_alloc_error
+    fn alloc() -> NonNull<T> {
+        let cache = T::cache();
+        match NonNull::new(unsafe { cache.alloc() } as *mut T) {
+            Some(ptr) => ptr,
+            None => handle_alloc_error(cache.layout),
+        }
+    }
+
+    /// 将 value 放入从缓存分配的内存中
+    pub fn new(value: T) -> Self {
+        let ptr = Self::alloc();
+        unsafe { ptr.as_ptr().write(value) };
+        Self(ptr)
+    }
+
+    /// 用缓存的构造函数就地构造一个对象，缓存没有构造函数时 panic
+    pub fn construct() -> Self {
+        let ctor = T::cache().ctor.expect("object cache has no constructor");
+        let ptr = Self::alloc();
+        ctor(ptr.as_ptr() as *mut u8);
+        Self(ptr)
+    }
+
+    /// 交出对象的所有权，之后需要用 from_raw 重新构造 SlabBox 来释放
+    pub fn into_raw(this: Self) -> NonNull<T> {
+        let ptr = this.0;
+        core::mem::forget(this);
+        ptr
+    }
+
+    /// 由 into_raw 返回的指针重新构造 SlabBox
+    pub unsafe fn from_raw(ptr: NonNull<T>) -> Self {
+        Self(ptr)
+    }
+}
+
+impl<T: Cached> Deref for SlabBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.0.as_ref() }
+    }
+}
+
+impl<T: Cached> DerefMut for SlabBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.0.as_mut() }
+    }
+}
+
+impl<T: Cached> Drop for SlabBox<T> {
+    fn drop(&mut self) {
+        unsafe {
+            core::ptr::drop_in_place(self.0.as_ptr());
+            T::cache().dealloc(self.0.as_ptr() as *mut u8);
+        }
+    }
+}
+
+/// slab 头，位于 slab 的起始处
+struct Slab {
+    prev: *mut Slab,
+    next: *mut Slab,
+    /// 空闲对象链表，下一个空闲对象的地址存放在空闲对象的开头
+    free: *mut usize,
+    /// 已分配的对象数
+    inuse: usize,
+}
+
+/// 将 slab 加入链表头部
+unsafe fn list_push(head: &mut *mut Slab, slab: *mut Slab) {
+    (*slab).prev = null_mut();
+    (*slab).next = *head;
+    if !head.is_null() {
+        (**head).prev = slab;
+    }
+    *head = slab;
+}
+
+/// 将 slab 从链表中取出
+unsafe fn list_remove(head: &mut *mut Slab, slab: *mut Slab) {
+    let (prev, next) = ((*slab).prev, (*slab).next);
+    if prev.is_null() {
+        *head = next;
+    } else {
+        (*prev).next = next;
+    }
+    if !next.is_null() {
+        (*next).prev = prev;
+    }
+}
+
+struct CacheInner {
+    /// 还有空闲对象的 slab
+    partial: *mut Slab,
+    /// 对象已全部分配出去的 slab
+    full: *mut Slab,
+    /// slab 数
+    slabs: usize,
+    /// 已分配的对象数
+    inuse: usize,
+}
+
+// slab 链表只在持有缓存的锁时访问
+unsafe impl Send for CacheInner {}
+
+/// 一种内核对象的缓存
+pub struct ObjectCache {
+    name: &'static str,
+    layout: Layout,
+    /// 构造函数，由 SlabBox::construct 在分配到的内存中就地构造对象，
+    /// 适用于有固定初始状态、不必先在栈上构造再复制的对象
+    ctor: Option<fn(*mut u8)>,
+    inner: Mutex<CacheInner>,
+}
+
+/// 一个对象缓存的使用情况
+#[derive(Clone, Copy, Debug)]
+pub struct CacheStats {
+    pub name: &'static str,
+    /// 每个对象占用的字节数
+    pub object_size: usize,
+    /// 已分配的对象数
+    pub inuse: usize,
+    /// 所有 slab 能容纳的对象数
+    pub capacity: usize,
+    /// slab 占用的内核堆字节数
+    pub bytes: usize,
+}
+
+impl ObjectCache {
+    pub const fn new(name: &'static str, layout: Layout, ctor: Option<fn(*mut u8)>) -> Self {
+        Self {
+            name,
+            layout,
+            ctor,
+            inner: Mutex::new(CacheInner {
+                partial: null_mut(),
+                full: null_mut(),
+                slabs: 0,
+                inuse: 0,
+            }),
+        }
+    }
+
+    /// 对象的对齐要求，空闲对象开头存放指针，至少按指针对齐
+    fn align(&self) -> usize {
+        self.layout.align().max(core::mem::align_of::<usize>())
+    }
+
+    /// 对象占用的字节数：至少能放下空闲链表的指针，并保持对齐
+    fn object_size(&self) -> usize {
+        let align = self.align();
+        let size = self.layout.size().max(core::mem::size_of::<usize>());
+        (size + align - 1) / align * align
+    }
+
+    /// slab 头占用的字节数，之后的对象按对象的对齐要求排列
+    fn header_size(&self) -> usize {
+        let align = self.align();
+        (core::mem::size_of::<Slab>() + align - 1) / align * align
+    }
+
+    /// slab 的大小：能容纳 SLAB_MIN_OBJECTS 个对象的最小的 2 的幂，至少一页
+    fn slab_size(&self) -> usize {
+        let mut size = PAGE_SIZE;
+        while (size - self.header_size()) / self.object_size() < SLAB_MIN_OBJECTS {
+            size *= 2;
+        }
+        size
+    }
+
+    fn objects_per_slab(&self) -> usize {
+        (self.slab_size() - self.header_size()) / self.object_size()
+    }
+
+    /// 从内核堆中申请一个 slab，并将其中的对象串成空闲链表，堆无法增长时返回空指针
+    unsafe fn new_slab(&self) -> *mut Slab {
+        let size = self.slab_size();
+        let slab = heap_alloc(Layout::from_size_align_unchecked(size, size)) as *mut Slab;
+        if slab.is_null() {
+            return slab;
+        }
+        let first = slab as usize + self.header_size();
+        // 从后往前串起空闲链表，分配时按地址从低到高取出
+        let mut free: *mut usize = null_mut();
+        for i in (0..self.objects_per_slab()).rev() {
+            let object = (first + i * self.object_size()) as *mut usize;
+            *object = free as usize;
+            free = object;
+        }
+        *slab = Slab {
+            prev: null_mut(),
+            next: null_mut(),
+            free,
+            inuse: 0,
+        };
+        slab
+    }
+
+    /// 分配一个对象的内存，内存不足时返回空指针
+    unsafe fn alloc(&self) -> *mut u8 {
+        let mut inner = self.inner.lock();
+        if inner.partial.is_null() {
+            let slab = self.new_slab();
+            if slab.is_null() {
+                return null_mut();
+            }
+            list_push(&mut inner.partial, slab);
+            inner.slabs += 1;
+        }
+        let slab = inner.partial;
+        let object = (*slab).free;
+        (*slab).free = *object as *mut usize;
+        (*slab).inuse += 1;
+        if (*slab).free.is_null() {
+            list_remove(&mut inner.partial, slab);
+            list_push(&mut inner.full, slab);
+        }
+        inner.inuse += 1;
+        object as *mut u8
+    }
+
+    /// 回收对象 ptr。slab 按自身大小对齐，由 ptr 即可找到所在的 slab。
+    /// slab 中的对象全部回收后将其归还给内核堆，但缓存至少保留一个 slab，避免反复申请与归还
+    unsafe fn dealloc(&self, ptr: *mut u8) {
+        let size = self.slab_size();
+        let slab = (ptr as usize & !(size - 1)) as *mut Slab;
+        let mut inner = self.inner.lock();
+        let was_full = (*slab).free.is_null();
+        *(ptr as *mut usize) = (*slab).free as usize;
+        (*slab).free = ptr as *mut usize;
+        (*slab).inuse -= 1;
+        inner.inuse -= 1;
+        if was_full {
+            list_remove(&mut inner.full, slab);
+            list_push(&mut inner.partial, slab);
+        }
+        if (*slab).inuse == 0 && inner.slabs > 1 {
+            list_remove(&mut inner.partial, slab);
+            inner.slabs -= 1;
+            heap_dealloc(slab as *mut u8, Layout::from_size_align_unchecked(size, size));
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            name: self.name,
+            object_size: self.object_size(),
+            inuse: inner.inuse,
+            capacity: inner.slabs * self.objects_per_slab(),
+            bytes: inner.slabs * self.slab_size(),
+        }
+    }
+}
+
+/// 各对象缓存的使用情况
+pub fn slab_stats() -> impl Iterator<Item = CacheStats> {
+    CACHES.iter().map(|cache| cache.stats())
+}
+
+/// 检查对象缓存：创建管道时从缓存分配缓冲区，回收的对象会被下一次分配重新使用
+#[allow(unused)]
+pub fn slab_test() {
+    let before = PIPE_BUFFER_CACHE.stats();
+    let (read_end, write_end) = crate::fs::make_pipe();
+    let during = PIPE_BUFFER_CACHE.stats();
+    assert_eq!(during.inuse, before.inuse + 1);
+    assert!(during.capacity >= during.inuse);
+    drop(write_end);
+    drop(read_end);
+    assert_eq!(PIPE_BUFFER_CACHE.stats().inuse, before.inuse);
+    // 空闲链表后进先出，刚回收的对象最先被重新分配
+    let buffer = SlabBox::<Mutex<PipeRingBuffer>>::construct();
+    let freed = &*buffer as *const _ as usize;
+    drop(buffer);
+    let buffer = SlabBox::<Mutex<PipeRingBuffer>>::construct();
+    assert_eq!(&*buffer as *const _ as usize, freed);
+    assert_eq!(buffer.lock().available_read(), 0);
+    drop(buffer);
+    println!("slab_test passed!");
+}
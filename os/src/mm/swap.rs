@@ -0,0 +1,106 @@
+//! 交换区：物理页桢耗尽时，把用户页面换出到块设备上预留的区域，缺页时再换入。
+//!
+//! 交换区位于块设备上文件系统之后，从 SWAP_START_BLOCK 开始，每个槽位存放一页，
+//! 占连续的 PAGE_SIZE / BLOCK_SZ 个块。槽位数为 SWAP_PAGES 与块设备实际剩余容量中较小的一个，
+//! 镜像太小时交换区相应变小，不会写到设备末尾之后。
+
+use alloc::vec::Vec;
+use easy_fs::BLOCK_SZ;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use super::PhysPageNum;
+use crate::{
+    config::{PAGE_SIZE, SWAP_PAGES, SWAP_START_BLOCK},
+    drivers::{block_device_capacity, BLOCK_DEVICE},
+};
+
+/// 每个槽位占用的块数
+const BLOCKS_PER_SLOT: usize = PAGE_SIZE / BLOCK_SZ;
+
+/// 交换区槽位分配器，与栈式页桢分配器的做法相同
+struct SwapAllocator {
+    /// 槽位区间 [current, end) 此前均从未被分配过
+    current: usize,
+    end: usize,
+    /// 被回收的槽位
+    recycled: Vec<usize>,
+}
+
+impl SwapAllocator {
+    fn alloc(&mut self) -> Option<usize> {
+        if let Some(id) = self.recycled.pop() {
+            Some(id)
+        } else if self.current == self.end {
+            None
+        } else {
+            self.current += 1;
+            Some(self.current - 1)
+        }
+    }
+
+    fn dealloc(&mut self, id: usize) {
+        assert!(id < self.current, "swap slot {} has not been allocated!", id);
+        self.recycled.push(id);
+    }
+}
+
+lazy_static! {
+    static ref SWAP_ALLOCATOR: Mutex<SwapAllocator> = Mutex::new(SwapAllocator {
+        current: 0,
+        end: swap_pages(),
+        recycled: Vec::new(),
+    });
+}
+
+/// 块设备上能容纳的槽位数
+fn swap_pages() -> usize {
+    let blocks = block_device_capacity().saturating_sub(SWAP_START_BLOCK);
+    let pages = SWAP_PAGES.min(blocks / BLOCKS_PER_SLOT);
+    if pages < SWAP_PAGES {
+        println!("[kernel] block device too small, only {} swap pages", pages);
+    }
+    pages
+}
+
+/// 交换区中的一个槽位，与 FrameTracker 一样利用 RAII 在释放时回收
+pub struct SwapSlot(usize);
+
+impl SwapSlot {
+    /// 分配一个槽位，交换区已满时返回 None
+    pub fn alloc() -> Option<Self> {
+        SWAP_ALLOCATOR.lock().alloc().map(Self)
+    }
+
+    /// 槽位编号，记录在被换出页面的页表项中
+    pub fn id(&self) -> usize {
+        self.0
+    }
+
+    /// 将物理页 ppn 的内容写入槽位
+    pub fn write(&self, ppn: PhysPageNum) {
+        let bytes = ppn.get_bytes_array();
+        for (i, block) in bytes.chunks(BLOCK_SZ).enumerate() {
+            BLOCK_DEVICE.write_block(self.block_id(i), block);
+        }
+    }
+
+    /// 将槽位的内容读到物理页 ppn 中
+    pub fn read(&self, ppn: PhysPageNum) {
+        let bytes = ppn.get_bytes_array();
+        for (i, block) in bytes.chunks_mut(BLOCK_SZ).enumerate() {
+            BLOCK_DEVICE.read_block(self.block_id(i), block);
+        }
+    }
+
+    /// 槽位中第 i 块在块设备上的块号
+    fn block_id(&self, i: usize) -> usize {
+        SWAP_START_BLOCK + self.0 * BLOCKS_PER_SLOT + i
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        SWAP_ALLOCATOR.lock().dealloc(self.0);
+    }
+}
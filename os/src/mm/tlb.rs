@@ -0,0 +1,275 @@
+//! 多核下的 TLB 一致性与 ASID 管理。
+//!
+//! 每个用户地址空间分配一个 ASID 并写入 satp，TLB 中的页表项按 ASID 区分，陷入内核与返回用户态
+//! 切换 satp 时不再需要清空 TLB。内核地址空间使用 ASID 0；ASID 用完或硬件不支持时，用户地址空间
+//! 也使用 ASID 0，这时 __alltraps、__restore 在切换 satp 后仍会清空本核 TLB。
+//!
+//! 修改页表、去掉映射或权限后，本核按页刷新对应的页表项。其它核的 TLB 中也可能缓存着这个 ASID
+//! 的页表项，给它们记下待刷新的 ASID，在下次返回用户态或切换任务之前刷新；正在用户态运行这个
+//! 地址空间的核则通过核间中断让其陷入内核一次，等待其离开用户态（TLB shootdown）。
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use lazy_static::lazy_static;
+use riscv::register::satp;
+use spin::Mutex;
+
+use super::{kernel_token, VirtAddr, VirtPageNum};
+use crate::{config::MAX_HART_NUM, sbi::send_ipi, smp::hart_id};
+
+/// ASID 在 satp 中的位置：Sv39 下为 44~59 位
+const ASID_SHIFT: usize = 44;
+const ASID_MASK: usize = 0xffff;
+
+/// 没有待刷新的页表项
+const PENDING_NONE: usize = usize::MAX;
+/// 待刷新多个 ASID，直接清空整个 TLB
+const PENDING_ALL: usize = usize::MAX - 1;
+
+/// 一次刷新的页数超过这个值时刷新整个 ASID，而不是逐页刷新
+const FLUSH_PAGES_LIMIT: usize = 64;
+
+lazy_static! {
+    /// 各核正在用户态运行的地址空间 token，在内核态时为 0
+    static ref USER_TOKEN: Vec<AtomicUsize> =
+        (0..MAX_HART_NUM).map(|_| AtomicUsize::new(0)).collect();
+    /// 各核从用户态陷入内核的次数。次数变化说明该核已经离开用户态
+    static ref TRAP_COUNT: Vec<AtomicUsize> =
+        (0..MAX_HART_NUM).map(|_| AtomicUsize::new(0)).collect();
+    /// 各核待刷新的 ASID，取值也可以是 PENDING_NONE 或 PENDING_ALL
+    static ref PENDING: Vec<AtomicUsize> =
+        (0..MAX_HART_NUM).map(|_| AtomicUsize::new(PENDING_NONE)).collect();
+    static ref ASID_ALLOCATOR: Mutex<AsidAllocator> = Mutex::new(AsidAllocator {
+        current: 1,
+        end: 1,
+        recycled: Vec::new(),
+    });
+}
+
+/// TLB 刷新的统计，内存布局与用户库一致
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TlbStats {
+    /// 返回用户态的次数
+    pub user_returns: usize,
+    /// 清空整个 TLB 的次数。以 ASID 0 返回用户态计两次：__restore 与之后陷入时的 __alltraps
+    pub full_flushes: usize,
+    /// 刷新一个 ASID 全部页表项的次数
+    pub asid_flushes: usize,
+    /// 逐页刷新的页数
+    pub page_flushes: usize,
+    /// 发送核间中断的次数
+    pub shootdowns: usize,
+}
+
+static USER_RETURNS: AtomicUsize = AtomicUsize::new(0);
+static FULL_FLUSHES: AtomicUsize = AtomicUsize::new(0);
+static ASID_FLUSHES: AtomicUsize = AtomicUsize::new(0);
+static PAGE_FLUSHES: AtomicUsize = AtomicUsize::new(0);
+static SHOOTDOWNS: AtomicUsize = AtomicUsize::new(0);
+
+/// 开机以来的 TLB 刷新统计
+pub fn tlb_stats() -> TlbStats {
+    TlbStats {
+        user_returns: USER_RETURNS.load(Ordering::Relaxed),
+        full_flushes: FULL_FLUSHES.load(Ordering::Relaxed),
+        asid_flushes: ASID_FLUSHES.load(Ordering::Relaxed),
+        page_flushes: PAGE_FLUSHES.load(Ordering::Relaxed),
+        shootdowns: SHOOTDOWNS.load(Ordering::Relaxed),
+    }
+}
+
+/// 清空本核整个 TLB
+fn flush_all() {
+    FULL_FLUSHES.fetch_add(1, Ordering::Relaxed);
+    unsafe {
+        llvm_asm!("sfence.vma" :::: "volatile");
+    }
+}
+
+/// 刷新本核 TLB 中 ASID 为 asid 的页表项
+fn flush_asid(asid: usize) {
+    ASID_FLUSHES.fetch_add(1, Ordering::Relaxed);
+    unsafe {
+        llvm_asm!("sfence.vma zero, $0" :: "r"(asid) :: "volatile");
+    }
+}
+
+/// 刷新本核 TLB 中 ASID 为 asid、虚拟页为 vpn 的页表项
+fn flush_page(vpn: VirtPageNum, asid: usize) {
+    PAGE_FLUSHES.fetch_add(1, Ordering::Relaxed);
+    let va: usize = VirtAddr::from(vpn).into();
+    unsafe {
+        llvm_asm!("sfence.vma $0, $1" :: "r"(va), "r"(asid) :: "volatile");
+    }
+}
+
+/// 刷新本核 TLB 中地址空间 token 的 vpn 页
+pub fn flush_local(token: usize, vpn: VirtPageNum) {
+    flush_page(vpn, token_asid(token));
+}
+
+/// token 中的 ASID
+fn token_asid(token: usize) -> usize {
+    (token >> ASID_SHIFT) & ASID_MASK
+}
+
+/// ASID 分配器，与交换区槽位分配器的做法相同
+struct AsidAllocator {
+    /// ASID 区间 [current, end) 此前均从未被分配过
+    current: usize,
+    end: usize,
+    /// 被回收的 ASID
+    recycled: Vec<usize>,
+}
+
+impl AsidAllocator {
+    fn alloc(&mut self) -> Option<usize> {
+        if let Some(asid) = self.recycled.pop() {
+            Some(asid)
+        } else if self.current == self.end {
+            None
+        } else {
+            self.current += 1;
+            Some(self.current - 1)
+        }
+    }
+
+    fn dealloc(&mut self, asid: usize) {
+        assert!(asid < self.current, "asid {} has not been allocated!", asid);
+        self.recycled.push(asid);
+    }
+}
+
+/// 探测硬件支持的 ASID 位数：向 satp 的 ASID 字段写入全 1，读回后保留下来的位即为可用的位。
+/// 需要在启用内核地址空间之后调用
+pub fn init() {
+    let token = satp::read().bits();
+    let asid_bits = unsafe {
+        satp::write(token | ASID_MASK << ASID_SHIFT);
+        let bits = token_asid(satp::read().bits()).count_ones();
+        satp::write(token);
+        bits
+    };
+    flush_all();
+    ASID_ALLOCATOR.lock().end = 1 << asid_bits;
+    println!("[kernel] {} asid bits", asid_bits);
+}
+
+/// 地址空间的 ASID，与 FrameTracker 一样利用 RAII 在释放时回收。
+/// ASID 为 0 表示没有分配到 ASID，与内核地址空间共用
+pub struct Asid(usize);
+
+impl Asid {
+    /// 分配一个 ASID，用完时返回 ASID 0
+    pub fn alloc() -> Self {
+        Self(ASID_ALLOCATOR.lock().alloc().unwrap_or(0))
+    }
+
+    /// ASID 0，用于内核地址空间以及已经不再运行的用户地址空间
+    pub fn zero() -> Self {
+        Self(0)
+    }
+
+    /// 在页表 token 中填入 ASID
+    pub fn token(&self, token: usize) -> usize {
+        token | self.0 << ASID_SHIFT
+    }
+}
+
+impl Drop for Asid {
+    /// 各核 TLB 中可能还有这个 ASID 的页表项，需要在重新分配出去之前刷新
+    fn drop(&mut self) {
+        if self.0 == 0 {
+            return;
+        }
+        flush_asid(self.0);
+        let current = hart_id();
+        for hart in (0..MAX_HART_NUM).filter(|hart| *hart != current) {
+            request_flush(hart, self.0);
+        }
+        ASID_ALLOCATOR.lock().dealloc(self.0);
+    }
+}
+
+/// 记下 hart 待刷新 asid
+fn request_flush(hart: usize, asid: usize) {
+    PENDING[hart]
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+            Some(if pending == PENDING_NONE || pending == asid {
+                asid
+            } else {
+                PENDING_ALL
+            })
+        })
+        .unwrap();
+}
+
+/// 刷新其它核为本核记下的待刷新页表项。
+/// 返回用户态与切换任务之前调用：此后才会用到用户地址空间与新任务内核栈的页表项
+pub fn flush_pending() {
+    match PENDING[hart_id()].swap(PENDING_NONE, Ordering::SeqCst) {
+        PENDING_NONE => {}
+        PENDING_ALL => flush_all(),
+        asid => flush_asid(asid),
+    }
+}
+
+/// 从用户态陷入内核时调用
+pub fn on_kernel_entry() {
+    let hart = hart_id();
+    TRAP_COUNT[hart].fetch_add(1, Ordering::SeqCst);
+    USER_TOKEN[hart].store(0, Ordering::SeqCst);
+}
+
+/// 即将以地址空间 token 返回用户态时调用。
+/// 先发布 token 再检查待刷新的页表项，与 shootdown 的顺序相反，二者至少有一方能看到对方的修改
+pub fn on_user_return(token: usize) {
+    USER_TOKEN[hart_id()].store(token, Ordering::SeqCst);
+    flush_pending();
+    USER_RETURNS.fetch_add(1, Ordering::Relaxed);
+    if token_asid(token) == 0 {
+        FULL_FLUSHES.fetch_add(2, Ordering::Relaxed);
+    }
+}
+
+/// 页表中 [start, end) 的映射被去掉或修改后，刷新地址空间 token 在各核上的 TLB，
+/// 返回时其它核已不再使用过期的页表项。
+/// 其它核在陷入内核时就会确认，不需要获取任何锁，所以调用方可以持有进程锁
+pub fn shootdown(token: usize, start: VirtPageNum, end: VirtPageNum) {
+    let asid = token_asid(token);
+    if end.0 - start.0 > FLUSH_PAGES_LIMIT {
+        flush_asid(asid);
+    } else {
+        for vpn in start.0..end.0 {
+            flush_page(VirtPageNum(vpn), asid);
+        }
+    }
+    // 没有 ASID 的用户地址空间每次切换 satp 都会清空 TLB，只有正在运行它的核缓存着其页表项；
+    // 内核地址空间的页表项则一直保留在各核的 TLB 中
+    let pending = asid != 0 || token == kernel_token();
+    let current = hart_id();
+    let mut hart_mask = 0;
+    let mut trap_count = [0; MAX_HART_NUM];
+    for hart in (0..MAX_HART_NUM).filter(|hart| *hart != current) {
+        if pending {
+            request_flush(hart, asid);
+        }
+        // 先读取陷入次数再检查 token：如果对方在两次读取之间陷入过内核，次数已经变化，不会等待
+        trap_count[hart] = TRAP_COUNT[hart].load(Ordering::SeqCst);
+        if USER_TOKEN[hart].load(Ordering::SeqCst) == token {
+            hart_mask |= 1 << hart;
+        }
+    }
+    if hart_mask == 0 {
+        return;
+    }
+    SHOOTDOWNS.fetch_add(1, Ordering::Relaxed);
+    send_ipi(hart_mask);
+    for hart in (0..MAX_HART_NUM).filter(|hart| hart_mask & (1 << hart) != 0) {
+        while TRAP_COUNT[hart].load(Ordering::SeqCst) == trap_count[hart] {
+            core::hint::spin_loop();
+        }
+    }
+}
@@ -3,6 +3,7 @@
 const SBI_SET_TIMER: usize = 0;
 const SBI_CONSOLE_PUTCHAR: usize = 1;
 const SBI_CONSOLE_GETCHAR: usize = 2;
+const SBI_SEND_IPI: usize = 4;
 pub(crate) const SBI_SHUTDOWN: usize = 8;
 
 //
@@ -21,6 +22,31 @@ pub(crate) fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) ->us
     ret
 }
 
+/// SBI v0.2 之后的扩展调用，which 由 a7 指定扩展编号（EID），a6 指定扩展中的功能编号（FID）
+const SBI_EXT_HSM: usize = 0x48534D;
+const SBI_EXT_HSM_HART_START: usize = 0;
+const SBI_EXT_IPI: usize = 0x735049;
+const SBI_EXT_IPI_SEND_IPI: usize = 0;
+
+/// 扩展不存在时返回的错误码
+const SBI_ERR_NOT_SUPPORTED: isize = -2;
+
+/// 调用 SBI 扩展，返回 SBI 规定的错误码，0 表示成功
+#[inline(always)]
+fn sbi_call_ext(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> isize {
+    let error: isize;
+    let _value: usize;
+    unsafe {
+        llvm_asm!("ecall"
+            : "={x10}" (error), "={x11}" (_value)
+            : "{x10}" (arg0), "{x11}" (arg1), "{x12}" (arg2), "{x16}" (fid), "{x17}" (eid)
+            : "memory"
+            : "volatile"
+        );
+    }
+    error
+}
+
 pub fn shutdown() -> ! {
     sbi_call(SBI_SHUTDOWN, 0, 0, 0);
     panic!("It should shutdown!");
@@ -38,3 +64,18 @@ pub fn console_getchar() -> usize {
 pub fn set_timer(timer: usize) {
     sbi_call(SBI_SET_TIMER, timer, 0, 0);
 }
+
+/// 通过 HSM 扩展启动 hart，被启动的 hart 以 a0 = hartid、a1 = opaque 从 start_addr 开始
+/// 在 S 模式下运行。返回 SBI 错误码
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> isize {
+    sbi_call_ext(SBI_EXT_HSM, SBI_EXT_HSM_HART_START, hartid, start_addr, opaque)
+}
+
+/// 向 hart_mask 中的各 hart 发送核间中断，目标 hart 会收到 S 模式软件中断。
+/// SBI 没有实现 IPI 扩展时使用旧版接口
+pub fn send_ipi(hart_mask: usize) {
+    if sbi_call_ext(SBI_EXT_IPI, SBI_EXT_IPI_SEND_IPI, hart_mask, 0, 0) == SBI_ERR_NOT_SUPPORTED {
+        // 旧版接口的参数是 hart 掩码的地址
+        sbi_call(SBI_SEND_IPI, &hart_mask as *const usize as usize, 0, 0);
+    }
+}
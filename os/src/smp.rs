@@ -0,0 +1,50 @@
+//! 多核支持：获取当前 hart 编号，以及由启动核唤醒其它核
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::{config::MAX_HART_NUM, sbi::hart_start};
+
+/// 负责初始化内核的启动核编号。
+/// 初始值不为 0，所以放在 .data 段，不会被启动核清零 .bss 时覆盖
+static BOOT_HART: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// 启动核是否已完成全局初始化（堆、页帧分配器、内核地址空间等）
+static BOOTED: AtomicBool = AtomicBool::new(false);
+
+/// 当前 hart 的编号。进入内核时由 entry.asm 或 __alltraps 写入 tp 寄存器
+#[inline(always)]
+pub fn hart_id() -> usize {
+    let id: usize;
+    unsafe {
+        llvm_asm!("mv $0, tp" : "=r"(id) ::: "volatile");
+    }
+    id
+}
+
+/// 竞争成为启动核，只有第一个进入内核的 hart 返回 true。
+/// 有的 SBI 实现会让所有 hart 同时跳转到内核入口，所以不能假定启动核是 0 号
+pub fn is_boot_hart() -> bool {
+    BOOT_HART
+        .compare_exchange(usize::MAX, hart_id(), Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
+}
+
+/// 启动核完成全局初始化后调用，唤醒其它 hart 从内核入口开始执行
+pub fn start_other_harts() {
+    extern "C" {
+        fn _start();
+    }
+    BOOTED.store(true, Ordering::SeqCst);
+    let boot_hart = hart_id();
+    for hart in (0..MAX_HART_NUM).filter(|hart| *hart != boot_hart) {
+        // 不存在或已经启动的 hart 会返回错误，直接忽略
+        hart_start(hart, _start as usize, 0);
+    }
+}
+
+/// 非启动核等待启动核完成全局初始化
+pub fn wait_for_boot_hart() {
+    while !BOOTED.load(Ordering::SeqCst) {
+        core::hint::spin_loop();
+    }
+}
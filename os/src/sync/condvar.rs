@@ -0,0 +1,43 @@
+use alloc::sync::Arc;
+
+use spin::Mutex as SpinLock;
+
+use super::Mutex;
+use crate::task::{block_current_and_run_next, current_task, WaitQueue};
+
+/// 条件变量，需要与互斥锁配合使用
+pub struct Condvar {
+    /// 等待条件的线程
+    wait_queue: SpinLock<WaitQueue>,
+}
+
+impl Condvar {
+    pub fn new() -> Self {
+        Self {
+            wait_queue: SpinLock::new(WaitQueue::new()),
+        }
+    }
+
+    /// 唤醒一个等待的线程
+    pub fn signal(&self) {
+        self.wait_queue.lock().wake_one();
+    }
+
+    /// 释放 mutex 并阻塞，被唤醒后重新获取 mutex。
+    /// 调用方需要持有 mutex，并在返回后重新检查条件（可能是虚假唤醒）。
+    /// 重新获取 mutex 时被信号打断则返回 false，此时没有持有 mutex
+    pub fn wait(&self, mutex: Arc<dyn Mutex>) -> bool {
+        let mut wait_queue = self.wait_queue.lock();
+        // 有信号时不阻塞，直接返回，相当于一次虚假唤醒
+        if !wait_queue.block_current() {
+            return true;
+        }
+        drop(wait_queue);
+        // 先标记为阻塞再释放锁，释放锁后其它线程的 signal 不会被错过
+        mutex.unlock();
+        block_current_and_run_next();
+        // 可能是被信号唤醒的
+        self.wait_queue.lock().remove(&current_task().unwrap());
+        mutex.lock()
+    }
+}
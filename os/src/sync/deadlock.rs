@@ -0,0 +1,123 @@
+use alloc::{vec, vec::Vec};
+
+/// 死锁检测器，对一类资源（互斥锁或信号量）记录银行家算法所需的矩阵，下标分别为 tid 与资源 id。
+/// 只要有线程申请资源就进行安全性检查：若加上这次申请后无法让所有线程都顺利结束，则认为会发生死锁。
+/// 检测只依据已发生的申请，信号量用于线程间通知（由不持有资源的线程 up）时可能误报
+pub struct DeadlockDetector {
+    /// 每种资源的剩余数量
+    available: Vec<usize>,
+    /// 每个线程已持有的资源数量
+    allocation: Vec<Vec<usize>>,
+    /// 每个线程正在申请（等待）的资源数量
+    need: Vec<Vec<usize>>,
+}
+
+impl DeadlockDetector {
+    pub fn new() -> Self {
+        Self {
+            available: Vec::new(),
+            allocation: Vec::new(),
+            need: Vec::new(),
+        }
+    }
+
+    /// 新建资源 id，初始数量为 count
+    pub fn add_resource(&mut self, id: usize, count: usize) {
+        if self.available.len() <= id {
+            self.available.resize(id + 1, 0);
+        }
+        self.available[id] = count;
+    }
+
+    /// 线程 tid 申请一个资源 id。check 为 true 时检查申请后是否仍处于安全状态，
+    /// 不安全则撤销这次申请并返回 false
+    pub fn request(&mut self, tid: usize, id: usize, check: bool) -> bool {
+        *Self::entry(&mut self.need, tid, id) += 1;
+        if check && !self.is_safe() {
+            self.need[tid][id] -= 1;
+            return false;
+        }
+        true
+    }
+
+    /// 线程 tid 放弃了对资源 id 的申请，例如等待被信号打断
+    pub fn cancel(&mut self, tid: usize, id: usize) {
+        let need = Self::entry(&mut self.need, tid, id);
+        *need = need.saturating_sub(1);
+    }
+
+    /// 线程 tid 获得了申请的资源 id
+    pub fn acquire(&mut self, tid: usize, id: usize) {
+        self.cancel(tid, id);
+        *Self::entry(&mut self.allocation, tid, id) += 1;
+        self.available[id] = self.available[id].saturating_sub(1);
+    }
+
+    /// 线程 tid 释放一个资源 id，只有确实持有时才归还。
+    /// 互斥锁被不持有它的线程解锁时不能增加剩余数量，否则之后的检查都会失效
+    pub fn release(&mut self, tid: usize, id: usize) {
+        let allocation = Self::entry(&mut self.allocation, tid, id);
+        if *allocation > 0 {
+            *allocation -= 1;
+            self.available[id] += 1;
+        }
+    }
+
+    /// 线程 tid 释放一个资源 id，不要求持有。信号量可以由不持有它的线程释放
+    pub fn release_any(&mut self, tid: usize, id: usize) {
+        let allocation = Self::entry(&mut self.allocation, tid, id);
+        *allocation = allocation.saturating_sub(1);
+        self.available[id] += 1;
+    }
+
+    /// 线程 tid 退出，归还其持有的资源并清除其申请。tid 之后可能分配给新线程，不能继承这些记录
+    pub fn remove_thread(&mut self, tid: usize) {
+        if let Some(allocation) = self.allocation.get_mut(tid) {
+            for (available, allocation) in self.available.iter_mut().zip(allocation.iter()) {
+                *available += allocation;
+            }
+            allocation.clear();
+        }
+        if let Some(need) = self.need.get_mut(tid) {
+            need.clear();
+        }
+    }
+
+    /// 安全性检查：不断找出申请能被满足的线程，假设它结束并归还持有的资源，
+    /// 最终所有线程都能结束则处于安全状态
+    fn is_safe(&self) -> bool {
+        let mut work = self.available.clone();
+        let mut finish = vec![false; self.need.len()];
+        loop {
+            let mut progress = false;
+            for tid in 0..self.need.len() {
+                if finish[tid] || !self.need[tid].iter().zip(&work).all(|(need, work)| need <= work)
+                {
+                    continue;
+                }
+                if let Some(allocation) = self.allocation.get(tid) {
+                    for (work, allocation) in work.iter_mut().zip(allocation) {
+                        *work += allocation;
+                    }
+                }
+                finish[tid] = true;
+                progress = true;
+            }
+            if !progress {
+                return finish.iter().all(|finish| *finish);
+            }
+        }
+    }
+
+    /// 获取矩阵中的一项，行或列不存在时先扩展
+    fn entry(matrix: &mut Vec<Vec<usize>>, tid: usize, id: usize) -> &mut usize {
+        if matrix.len() <= tid {
+            matrix.resize(tid + 1, Vec::new());
+        }
+        let row = &mut matrix[tid];
+        if row.len() <= id {
+            row.resize(id + 1, 0);
+        }
+        &mut row[id]
+    }
+}
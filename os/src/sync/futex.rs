@@ -0,0 +1,84 @@
+use alloc::{collections::BTreeMap, sync::Arc};
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::{
+    error::{SysError, SysResult},
+    task::{block_current_and_run_next, current_task, WaitQueue},
+    timer::{add_timer, cancel_timer, get_time_ms},
+};
+
+lazy_static! {
+    /// 各 futex 的等待队列，以用户字所在的物理地址为键，
+    /// 这样映射到同一物理页的不同进程也能通过同一个 futex 同步
+    static ref FUTEX_QUEUES: Mutex<BTreeMap<usize, WaitQueue>> = Mutex::new(BTreeMap::new());
+}
+
+/// 如果物理地址 pa 处的值仍为 val，则阻塞当前线程，直到被 futex_wake 唤醒或经过 timeout_ms 毫秒
+/// （为 0 时不超时）。
+/// 值已不是 val 时返回 EAGAIN，被信号打断返回 EINTR，超时返回 ETIMEDOUT
+pub fn futex_wait(pa: usize, val: u32, timeout_ms: usize) -> SysResult<()> {
+    let mut queues = FUTEX_QUEUES.lock();
+    // 持有表锁时检查值，futex_wake 也需要这把锁，所以修改值后的唤醒不会被错过
+    if unsafe { (pa as *const u32).read_volatile() } != val {
+        return Err(SysError::EAGAIN);
+    }
+    let queue = queues.entry(pa).or_insert_with(WaitQueue::new);
+    if !queue.block_current() {
+        if queue.is_empty() {
+            queues.remove(&pa);
+        }
+        return Err(SysError::EINTR);
+    }
+    let task = current_task().unwrap();
+    let timer = if timeout_ms > 0 {
+        let expire_ms = get_time_ms() + timeout_ms;
+        Some((expire_ms, add_timer(expire_ms, Arc::clone(&task))))
+    } else {
+        None
+    };
+    drop(queues);
+    block_current_and_run_next();
+    // 被 futex_wake 或信号唤醒时定时器还在队列中，取消它以免之后误唤醒
+    if let Some((_, id)) = timer {
+        cancel_timer(id);
+    }
+
+    let mut queues = FUTEX_QUEUES.lock();
+    // 还在队列中说明不是被 futex_wake 唤醒的，而是超时或者有信号
+    let still_waiting = match queues.get_mut(&pa) {
+        Some(queue) => {
+            let still_waiting = queue.remove(&task);
+            if queue.is_empty() {
+                queues.remove(&pa);
+            }
+            still_waiting
+        }
+        None => false,
+    };
+    if !still_waiting {
+        Ok(())
+    } else if timer.map_or(false, |(expire_ms, _)| get_time_ms() >= expire_ms) {
+        Err(SysError::ETIMEDOUT)
+    } else {
+        Err(SysError::EINTR)
+    }
+}
+
+/// 最多唤醒 count 个在物理地址 pa 上等待的线程，返回唤醒的线程数
+pub fn futex_wake(pa: usize, count: usize) -> usize {
+    let mut queues = FUTEX_QUEUES.lock();
+    let queue = match queues.get_mut(&pa) {
+        Some(queue) => queue,
+        None => return 0,
+    };
+    let mut woken = 0;
+    while woken < count && queue.wake_one() {
+        woken += 1;
+    }
+    if queue.is_empty() {
+        queues.remove(&pa);
+    }
+    woken
+}
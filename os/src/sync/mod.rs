@@ -0,0 +1,15 @@
+//! 提供给用户程序的同步原语：互斥锁、信号量与条件变量。
+//! 它们由进程持有，用户程序通过系统调用使用，以在表中的下标作为标识。
+//! 另外提供 futex，供用户程序在用户态实现只在发生竞争时才陷入内核的锁
+
+mod condvar;
+mod deadlock;
+mod futex;
+mod mutex;
+mod semaphore;
+
+pub use condvar::Condvar;
+pub use deadlock::DeadlockDetector;
+pub use futex::{futex_wait, futex_wake};
+pub use mutex::{Mutex, MutexBlocking, MutexSpin};
+pub use semaphore::Semaphore;
@@ -0,0 +1,94 @@
+use spin::Mutex as SpinLock;
+
+use crate::task::{
+    block_current_and_run_next, current_task, suspend_current_and_run_next, WaitQueue,
+};
+
+/// 互斥锁。lock 被信号打断时返回 false，此时没有获得锁
+pub trait Mutex: Send + Sync {
+    fn lock(&self) -> bool;
+    fn unlock(&self);
+}
+
+/// 自旋互斥锁：获取不到锁时让出 CPU，之后再重试。适合临界区很短的场景
+pub struct MutexSpin {
+    locked: SpinLock<bool>,
+}
+
+impl MutexSpin {
+    pub fn new() -> Self {
+        Self {
+            locked: SpinLock::new(false),
+        }
+    }
+}
+
+impl Mutex for MutexSpin {
+    fn lock(&self) -> bool {
+        loop {
+            let mut locked = self.locked.lock();
+            if !*locked {
+                *locked = true;
+                return true;
+            }
+            drop(locked);
+            // 持有锁的线程可能已经不会再释放锁（例如进程被杀死），有信号时先返回用户态处理
+            if current_task().unwrap().acquire_inner_lock().has_pending_signal() {
+                return false;
+            }
+            suspend_current_and_run_next();
+        }
+    }
+
+    fn unlock(&self) {
+        *self.locked.lock() = false;
+    }
+}
+
+struct MutexBlockingInner {
+    locked: bool,
+    /// 等待获取锁的线程
+    wait_queue: WaitQueue,
+}
+
+/// 阻塞互斥锁：获取不到锁时阻塞在等待队列上，释放锁时唤醒其中一个线程
+pub struct MutexBlocking {
+    inner: SpinLock<MutexBlockingInner>,
+}
+
+impl MutexBlocking {
+    pub fn new() -> Self {
+        Self {
+            inner: SpinLock::new(MutexBlockingInner {
+                locked: false,
+                wait_queue: WaitQueue::new(),
+            }),
+        }
+    }
+}
+
+impl Mutex for MutexBlocking {
+    fn lock(&self) -> bool {
+        loop {
+            let mut inner = self.inner.lock();
+            if !inner.locked {
+                inner.locked = true;
+                // 可能是被信号唤醒后抢到了锁，去掉队列中的记录
+                inner.wait_queue.remove(&current_task().unwrap());
+                return true;
+            }
+            if !inner.wait_queue.block_current() {
+                return false;
+            }
+            drop(inner);
+            block_current_and_run_next();
+        }
+    }
+
+    /// 被唤醒的线程需要重新竞争锁，锁不会直接交给它
+    fn unlock(&self) {
+        let mut inner = self.inner.lock();
+        inner.locked = false;
+        inner.wait_queue.wake_one();
+    }
+}
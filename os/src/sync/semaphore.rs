@@ -0,0 +1,50 @@
+use spin::Mutex;
+
+use crate::task::{block_current_and_run_next, current_task, WaitQueue};
+
+struct SemaphoreInner {
+    /// 剩余的资源数
+    count: usize,
+    /// 等待资源的线程
+    wait_queue: WaitQueue,
+}
+
+/// 计数信号量
+pub struct Semaphore {
+    inner: Mutex<SemaphoreInner>,
+}
+
+impl Semaphore {
+    pub fn new(res_count: usize) -> Self {
+        Self {
+            inner: Mutex::new(SemaphoreInner {
+                count: res_count,
+                wait_queue: WaitQueue::new(),
+            }),
+        }
+    }
+
+    /// 释放一个资源，并唤醒一个等待的线程
+    pub fn up(&self) {
+        let mut inner = self.inner.lock();
+        inner.count += 1;
+        inner.wait_queue.wake_one();
+    }
+
+    /// 获取一个资源，没有剩余资源时阻塞。被信号打断时返回 false，此时没有获得资源
+    pub fn down(&self) -> bool {
+        loop {
+            let mut inner = self.inner.lock();
+            if inner.count > 0 {
+                inner.count -= 1;
+                inner.wait_queue.remove(&current_task().unwrap());
+                return true;
+            }
+            if !inner.wait_queue.block_current() {
+                return false;
+            }
+            drop(inner);
+            block_current_and_run_next();
+        }
+    }
+}
@@ -1,104 +1,160 @@
-use core::ops::Add;
-
 use alloc::sync::Arc;
 
 use crate::{
-    fs::{make_pipe, open_file, OpenFlags},
-    mm::{translated_byte_buffer, translated_refmut, translated_str, UserBuffer},
-    sbi::console_getchar,
-    task::{current_task, current_user_token, suspend_current_and_run_next},
+    error::{SysError, SysResult},
+    fs::{foreground_pgid, make_pipe, open_file, set_foreground_pgid, File, OpenFlags},
+    task::{current_process, pgid2processes},
 };
 
-pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    let task = current_task().unwrap();
-    let token = current_user_token();
-    let inner = task.acquire_inner_lock();
-    if fd >= inner.fd_table.len() {
-        return -1;
-    }
-    if let Some(file) = &inner.fd_table[fd] {
-        let file = file.clone();
-        drop(inner); // 释放锁
+/// ioctl 命令：获取终端的前台进程组
+const TIOCGPGRP: usize = 0x540f;
+/// ioctl 命令：设置终端的前台进程组
+const TIOCSPGRP: usize = 0x5410;
 
-        file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
-    } else {
-        -1
-    }
+/// 获取当前进程 fd 对应的文件，fd 不合法时返回 EBADF
+fn get_file(fd: usize) -> SysResult<Arc<dyn File + Send + Sync>> {
+    let process = current_process();
+    let inner = process.acquire_inner_lock();
+    inner
+        .fd_table
+        .get(fd)
+        .and_then(|file| file.clone())
+        .ok_or(SysError::EBADF)
+}
+
+/// 功能：将用户缓冲区 [buf, buf + len) 中的数据写入文件 fd。
+/// 返回值：成功写入的字节数；fd 不合法或不可写返回 EBADF；缓冲区不可读返回 EFAULT。
+/// syscall ID：64
+pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> SysResult {
+    let file = get_file(fd)?;
+    let user_buf = current_process()
+        .acquire_inner_lock()
+        .memory_set
+        .user_buffer(buf, len, false)?;
+    file.write(user_buf)
+}
+
+/// 功能：从文件 fd 读取数据到用户缓冲区 [buf, buf + len)。
+/// 返回值：读到的字节数；fd 不合法或不可读返回 EBADF；缓冲区不可写返回 EFAULT。
+/// syscall ID：63
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> SysResult {
+    let file = get_file(fd)?;
+    // 内核要直接写入用户缓冲区，先补上延迟分配的页面并解除写时复制共享
+    let user_buf = current_process()
+        .acquire_inner_lock()
+        .memory_set
+        .user_buffer(buf, len, true)?;
+    file.read(user_buf)
 }
 
-pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
-    let task = current_task().unwrap();
-    let token = current_user_token();
-    let inner = task.acquire_inner_lock();
-    if fd >= inner.fd_table.len() {
-        return -1;
+/// 功能：对终端 fd 执行控制命令，目前只支持读取（TIOCGPGRP）与设置（TIOCSPGRP）前台进程组。
+/// 参数：arg 指向一个 i32，TIOCGPGRP 时写入前台进程组 id，TIOCSPGRP 时从中读取新的前台进程组 id。
+/// 返回值：成功返回 0；fd 不合法返回 EBADF；fd 不是终端返回 ENOTTY；命令或进程组 id 不合法返回 EINVAL；
+/// 进程组不存在返回 ESRCH；进程组不在当前会话中返回 EPERM；地址不合法返回 EFAULT。
+/// syscall ID：29
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> SysResult {
+    let file = get_file(fd)?;
+    if !file.is_tty() {
+        return Err(SysError::ENOTTY);
     }
-    if let Some(file) = &inner.fd_table[fd] {
-        let file = file.clone();
-        drop(inner);
-        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
-    } else {
-        -1
+    let process = current_process();
+    match cmd {
+        TIOCGPGRP => {
+            let pgid = foreground_pgid() as i32;
+            process
+                .acquire_inner_lock()
+                .memory_set
+                .copy_out(arg as *mut i32, &pgid)?;
+        }
+        TIOCSPGRP => {
+            let mut inner = process.acquire_inner_lock();
+            let pgid = inner.memory_set.copy_in(arg as *const i32)?;
+            let sid = inner.sid;
+            drop(inner);
+            if pgid <= 0 {
+                return Err(SysError::EINVAL);
+            }
+            let group = pgid2processes(pgid as usize);
+            if group.is_empty() {
+                return Err(SysError::ESRCH);
+            }
+            if group
+                .iter()
+                .any(|member| member.acquire_inner_lock().sid != sid)
+            {
+                return Err(SysError::EPERM);
+            }
+            set_foreground_pgid(pgid as usize);
+        }
+        _ => return Err(SysError::EINVAL),
     }
+    Ok(0)
 }
 
-pub fn sys_close(fd: usize) -> isize {
-    let task = current_task().unwrap();
-    let mut inner = task.acquire_inner_lock();
-    if fd >= inner.fd_table.len() {
-        return -1;
-    }
-    if inner.fd_table[fd].is_none() {
-        return -1;
+pub fn sys_close(fd: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    match inner.fd_table.get_mut(fd) {
+        Some(file) if file.is_some() => {
+            file.take();
+            Ok(0)
+        }
+        _ => Err(SysError::EBADF),
     }
-    inner.fd_table[fd].take();
-    0
 }
 
 /// 功能：为当前进程打开一个管道。
 /// 参数：pipe 表示应用地址空间中的一个长度为 2 的 usize 数组的起始地址，内核需要按顺序将管道读端
 /// 和写端的文件描述符写入到数组中。
-/// 返回值：如果出现了错误则返回 -1，否则返回 0 。可能的错误原因是：传入的地址不合法。
+/// 返回值：成功返回 0；传入的地址不合法时返回 EFAULT；文件描述符达到 RLIMIT_NOFILE 时返回 EMFILE。
 /// syscall ID：59
-pub fn sys_pipe(pipe: *mut usize) -> isize {
-    let task = current_task().unwrap();
-    let token = current_user_token();
-    let mut inner = task.acquire_inner_lock();
+pub fn sys_pipe(pipe: *mut usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
     let (pipe_read, pipe_write) = make_pipe();
-    let read_fd = inner.alloc_fd();
+    let read_fd = inner.alloc_fd()?;
     inner.fd_table[read_fd] = Some(pipe_read);
-    let write_fd = inner.alloc_fd();
+    let write_fd = match inner.alloc_fd() {
+        Ok(fd) => fd,
+        Err(err) => {
+            inner.fd_table[read_fd] = None;
+            return Err(err);
+        }
+    };
     inner.fd_table[write_fd] = Some(pipe_write);
-    *translated_refmut(token, pipe) = read_fd;
-    *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
-    0
+    if let Err(err) = inner
+        .memory_set
+        .copy_out(pipe as *mut [usize; 2], &[read_fd, write_fd])
+    {
+        // 地址不合法时不占用文件描述符
+        inner.fd_table[read_fd] = None;
+        inner.fd_table[write_fd] = None;
+        return Err(err);
+    }
+    Ok(0)
 }
 
-/// 打开文件
-pub fn sys_open(path: *const u8, flags: u32) -> isize {
-    let task = current_task().unwrap();
-    let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
-        let mut inner = task.acquire_inner_lock();
-        let fd = inner.alloc_fd();
-        inner.fd_table[fd] = Some(inode);
-        fd as isize
-    } else {
-        -1
-    }
+/// 打开文件。flags 不合法时返回 EINVAL，文件不存在时返回 ENOENT，
+/// 文件描述符达到 RLIMIT_NOFILE 时返回 EMFILE
+pub fn sys_open(path: *const u8, flags: u32) -> SysResult {
+    let process = current_process();
+    let path = process
+        .acquire_inner_lock()
+        .memory_set
+        .copy_in_str(path)?;
+    let flags = OpenFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
+    let inode = open_file(path.as_str(), flags)?;
+    let mut inner = process.acquire_inner_lock();
+    let fd = inner.alloc_fd()?;
+    inner.fd_table[fd] = Some(inode);
+    Ok(fd)
 }
 
-pub fn sys_dup(fd: usize) -> isize {
-    let task = current_task().unwrap();
-    let mut inner = task.acquire_inner_lock();
-    if fd >= inner.fd_table.len() {
-        return -1;
-    }
-    if inner.fd_table[fd].is_none() {
-        return -1;
-    }
-    let new_fd = inner.alloc_fd();
-    inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
-    new_fd as _
+pub fn sys_dup(fd: usize) -> SysResult {
+    let file = get_file(fd)?;
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    let new_fd = inner.alloc_fd()?;
+    inner.fd_table[new_fd] = Some(file);
+    Ok(new_fd)
 }
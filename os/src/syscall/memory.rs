@@ -0,0 +1,147 @@
+use crate::{
+    config::{MMAP_BASE, MMAP_END, PAGE_SIZE},
+    error::{SysError, SysResult},
+    mm::{tlb_stats, MapPermission, TlbStats, VirtAddr},
+    task::{current_process, RLIMIT_AS},
+};
+
+/// 将用户传入的 prot（bit0: 可读，bit1: 可写，bit2: 可执行）转换为逻辑段权限。
+/// 不允许无任何权限的映射，这样的页表项在 Sv39 中会被当作下一级页表。
+/// Sv39 保留了可写不可读的页表项，与 Linux 一样，可写的映射同时可读
+fn prot_to_permission(prot: usize) -> Option<MapPermission> {
+    if prot & !0x7 != 0 || prot & 0x7 == 0 {
+        return None;
+    }
+    let mut perm = MapPermission::from_bits((prot << 1) as u8)? | MapPermission::U;
+    if perm.contains(MapPermission::W) {
+        perm |= MapPermission::R;
+    }
+    Some(perm)
+}
+
+/// 检查 [start, start + len) 是否是合法的用户地址区间：页对齐、长度非0、不越过 MMAP_END
+fn check_user_range(start: usize, len: usize) -> bool {
+    start % PAGE_SIZE == 0 && len != 0 && start < MMAP_END && len <= MMAP_END - start
+}
+
+/// 功能：调整当前进程的堆顶（program break）。用户堆位于 elf 各段之后，向上增长。
+/// 参数：addr 为新的堆顶，为 0 时只查询当前堆顶。
+/// 返回值：调整后的堆顶。调整失败（含地址空间超过 RLIMIT_AS、堆顶不低于 MMAP_BASE）时
+/// 堆顶保持不变，调用方可以通过比较返回值判断是否成功。
+/// syscall ID：214
+pub fn sys_brk(addr: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    // 堆位于用户栈与 mmap 区域下方，先排除过高的地址，之后按页取整不会溢出
+    if addr != 0 && addr < MMAP_BASE {
+        let old_end_vpn = VirtAddr::from(inner.memory_set.brk()).ceil();
+        let new_end_vpn = VirtAddr::from(addr).ceil();
+        let grow = new_end_vpn.0.saturating_sub(old_end_vpn.0) * PAGE_SIZE;
+        let within_limit = inner
+            .memory_set
+            .mapped_size()
+            .checked_add(grow)
+            .map_or(false, |size| size <= inner.rlimits.cur(RLIMIT_AS));
+        if within_limit {
+            inner.memory_set.set_brk(addr);
+        }
+    }
+    Ok(inner.memory_set.brk())
+}
+
+/// 功能：为当前进程映射一段匿名内存，页面在第一次访问时才会分配并清零。
+/// 参数：start 为映射起始地址，必须按页对齐；为 0 时由内核在 [MMAP_BASE, MMAP_END) 中选择。
+/// len 为映射长度，会向上按页取整。prot 为访问权限，bit0/1/2 分别表示可读/可写/可执行。
+/// 返回值：成功返回映射的起始地址。地址未对齐、长度为0、prot 不合法时返回 EINVAL；
+/// 与已有映射重叠时返回 EEXIST；没有足够大的空闲区间或地址空间将超过 RLIMIT_AS 时返回 ENOMEM。
+/// syscall ID：222
+pub fn sys_mmap(start: usize, len: usize, prot: usize) -> SysResult {
+    let permission = prot_to_permission(prot).ok_or(SysError::EINVAL)?;
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+    let start_vpn = if start == 0 {
+        if len == 0 {
+            return Err(SysError::EINVAL);
+        }
+        if len > MMAP_END - MMAP_BASE {
+            return Err(SysError::ENOMEM);
+        }
+        inner
+            .memory_set
+            .find_free_area(
+                VirtAddr::from(MMAP_BASE).floor(),
+                VirtAddr::from(MMAP_END).floor(),
+                pages,
+            )
+            .ok_or(SysError::ENOMEM)?
+    } else {
+        if !check_user_range(start, len) {
+            return Err(SysError::EINVAL);
+        }
+        let start_vpn = VirtAddr::from(start).floor();
+        if inner
+            .memory_set
+            .is_overlapping(start_vpn, (start_vpn.0 + pages).into())
+        {
+            return Err(SysError::EEXIST);
+        }
+        start_vpn
+    };
+    if inner.memory_set.mapped_size() + pages * PAGE_SIZE > inner.rlimits.cur(RLIMIT_AS) {
+        return Err(SysError::ENOMEM);
+    }
+    let start_va: VirtAddr = start_vpn.into();
+    let end_va: VirtAddr = VirtAddr::from(start_va.0 + pages * PAGE_SIZE);
+    inner
+        .memory_set
+        .insert_lazy_area(start_va, end_va, permission);
+    Ok(start_va.0)
+}
+
+/// 功能：取消 [start, start + len) 的映射并回收其中的页面。
+/// 返回值：成功返回 0；地址未对齐、长度为0、区间中存在未映射的页时返回 EINVAL。
+/// syscall ID：215
+pub fn sys_munmap(start: usize, len: usize) -> SysResult {
+    if !check_user_range(start, len) {
+        return Err(SysError::EINVAL);
+    }
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    let start_va = VirtAddr::from(start);
+    let end_va = VirtAddr::from(start + len);
+    inner
+        .memory_set
+        .unmap_range(start_va.floor(), end_va.ceil())?;
+    Ok(0)
+}
+
+/// 功能：修改 [start, start + len) 的访问权限，prot 的含义同 mmap。
+/// 返回值：成功返回 0；地址未对齐、长度为0、prot 不合法时返回 EINVAL；
+/// 区间中存在未映射的页时返回 ENOMEM。
+/// syscall ID：226
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> SysResult {
+    let permission = prot_to_permission(prot).ok_or(SysError::EINVAL)?;
+    if !check_user_range(start, len) {
+        return Err(SysError::EINVAL);
+    }
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    let start_va = VirtAddr::from(start);
+    let end_va = VirtAddr::from(start + len);
+    inner
+        .memory_set
+        .protect_range(start_va.floor(), end_va.ceil(), permission)?;
+    Ok(0)
+}
+
+/// 功能：获取开机以来的 TLB 刷新统计，写入 stats，用于比较不同负载下刷新 TLB 的开销。
+/// 返回值：成功返回 0；地址不合法返回 EFAULT。
+/// syscall ID：470
+pub fn sys_tlb_stats(stats: *mut TlbStats) -> SysResult {
+    current_process()
+        .acquire_inner_lock()
+        .memory_set
+        .copy_out(stats, &tlb_stats())?;
+    Ok(0)
+}
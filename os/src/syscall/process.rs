@@ -1,101 +1,445 @@
 use alloc::{string::String, sync::Arc, vec::Vec};
 
 use crate::{
-    fs::{open_file, File, OpenFlags},
-    mm::{translated_ref, translated_refmut, translated_str},
+    error::{SysError, SysResult},
+    fs::{open_file, OpenFlags},
+    mm::MemorySet,
     println,
     task::{
-        add_task, current_task, current_user_token, exit_current_and_run_next,
-        suspend_current_and_run_next,
+        add_task, block_current_and_run_next, current_process, current_task,
+        exit_current_and_run_next, pgid2processes, pid2process, process_count,
+        send_signal_to_group, send_signal_to_process, signal_return, suspend_current_and_run_next,
+        ticks_to_ms, CpuUsage, RLimit, Rusage, SignalAction, SignalFlags, Tms, PCB, RLIMIT_NPROC,
     },
-    timer::{get_time, get_time_ms},
+    timer::{block_current_until, get_time, get_time_ms},
 };
 
-pub fn sys_exit(exit_code: i32) -> isize {
+pub fn sys_exit(exit_code: i32) -> SysResult {
     println!("[kernel] Application exited with code {}", exit_code);
     // 退出则处理下一个任务
     exit_current_and_run_next(exit_code);
     panic!("never here");
 }
 
-pub fn sys_yield() -> isize {
+pub fn sys_yield() -> SysResult {
     suspend_current_and_run_next();
-    0
+    Ok(0)
 }
 
-pub fn sys_get_time() -> isize {
-    get_time_ms() as isize
+/// 功能：让当前线程睡眠 period_ms 毫秒，期间不占用 CPU。
+/// 返回值：睡眠结束返回 0；被信号打断时返回 EINTR，用户库在信号处理完后继续睡眠剩余的时间。
+/// syscall ID：101
+pub fn sys_sleep(period_ms: usize) -> SysResult {
+    let expire_ms = get_time_ms() + period_ms;
+    while get_time_ms() < expire_ms {
+        if !block_current_until(expire_ms) {
+            return Err(SysError::EINTR);
+        }
+    }
+    Ok(0)
+}
+
+pub fn sys_get_time() -> SysResult {
+    Ok(get_time_ms())
+}
+
+/// getrusage 的统计对象：当前进程
+const RUSAGE_SELF: isize = 0;
+/// getrusage 的统计对象：已回收的子进程
+const RUSAGE_CHILDREN: isize = -1;
+/// getrusage 的统计对象：当前线程
+const RUSAGE_THREAD: isize = 1;
+
+/// 当前进程与已回收子进程的资源使用。先结算当前线程正在进行的内核态时间
+fn current_usage() -> (CpuUsage, CpuUsage, CpuUsage) {
+    let task = current_task().unwrap();
+    let process = current_process();
+    let inner = process.acquire_inner_lock();
+    let mut task_inner = task.acquire_inner_lock();
+    task_inner.account_time(false);
+    let thread_usage = task_inner.usage;
+    drop(task_inner);
+    (inner.usage(), inner.children_usage, thread_usage)
+}
+
+/// 功能：获取当前进程及其已回收子进程的 CPU 时间，单位为毫秒。tms 为空指针时只返回当前时间。
+/// 返回值：开机以来的毫秒数；地址不合法返回 EFAULT。
+/// syscall ID：153
+pub fn sys_times(tms: *mut Tms) -> SysResult {
+    if tms.is_null() {
+        return Ok(get_time_ms());
+    }
+    let (usage, children_usage, _) = current_usage();
+    let times = Tms {
+        utime: ticks_to_ms(usage.utime),
+        stime: ticks_to_ms(usage.stime),
+        cutime: ticks_to_ms(children_usage.utime),
+        cstime: ticks_to_ms(children_usage.stime),
+    };
+    current_process()
+        .acquire_inner_lock()
+        .memory_set
+        .copy_out(tms, &times)?;
+    Ok(get_time_ms())
+}
+
+/// 功能：获取资源使用情况。who 为 RUSAGE_SELF(0) 时统计当前进程的所有线程，
+/// RUSAGE_CHILDREN(-1) 时统计已回收的子进程，RUSAGE_THREAD(1) 时只统计当前线程。
+/// 返回值：成功返回 0；who 不合法返回 EINVAL；地址不合法返回 EFAULT。
+/// syscall ID：165
+pub fn sys_getrusage(who: isize, rusage: *mut Rusage) -> SysResult {
+    let (usage, children_usage, thread_usage) = current_usage();
+    let usage = match who {
+        RUSAGE_SELF => usage,
+        RUSAGE_CHILDREN => children_usage,
+        RUSAGE_THREAD => thread_usage,
+        _ => return Err(SysError::EINVAL),
+    };
+    current_process()
+        .acquire_inner_lock()
+        .memory_set
+        .copy_out(rusage, &usage.to_rusage())?;
+    Ok(0)
+}
+
+/// 功能：获取当前进程对资源 resource 的限制，写入 rlimit。
+/// 返回值：成功返回 0；resource 不合法返回 EINVAL；地址不合法返回 EFAULT。
+/// syscall ID：163
+pub fn sys_getrlimit(resource: usize, rlimit: *mut RLimit) -> SysResult {
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    let limit = inner.rlimits.get(resource)?;
+    inner.memory_set.copy_out(rlimit, &limit)?;
+    Ok(0)
+}
+
+/// 功能：设置当前进程对资源 resource 的限制，之后 fork 出的子进程继承新的限制。
+/// 已经分配的资源不受影响，如已打开的文件、已有线程的用户栈。
+/// 返回值：成功返回 0；resource 不合法或软限制大于硬限制时返回 EINVAL；提高硬限制时返回 EPERM；
+/// 地址不合法返回 EFAULT。
+/// syscall ID：164
+pub fn sys_setrlimit(resource: usize, rlimit: *const RLimit) -> SysResult {
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    let limit = inner.memory_set.copy_in(rlimit)?;
+    inner.rlimits.set(resource, limit)?;
+    Ok(0)
 }
 
-pub fn sys_getpid() -> isize {
-    current_task().unwrap().getpid() as isize
+pub fn sys_getpid() -> SysResult {
+    Ok(current_process().getpid())
 }
 
-pub fn sys_fork() -> isize {
-    let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
-    let new_pid = new_task.pid.0;
+/// 多线程进程不能 fork，返回 EINVAL；系统中的进程数达到 RLIMIT_NPROC 时返回 EAGAIN；
+/// 页桢不足时返回 ENOMEM
+pub fn sys_fork() -> SysResult {
+    let current_process = current_process();
+    let inner = current_process.acquire_inner_lock();
+    if inner.thread_count() != 1 {
+        return Err(SysError::EINVAL);
+    }
+    let nproc_limit = inner.rlimits.cur(RLIMIT_NPROC);
+    drop(inner);
+    if process_count() >= nproc_limit {
+        return Err(SysError::EAGAIN);
+    }
+    let new_process = current_process.fork()?;
+    let new_pid = new_process.pid.0;
+    let new_task = new_process.acquire_inner_lock().get_task(0);
     // modify trap context of new_task, because it returns immediately after switching
     let trap_cx = new_task.acquire_inner_lock().get_trap_cx();
     // we do not have to move to next instruction since we have done it before
     // for child process, fork returns
     trap_cx.x[10] = 0; // 子进程的 pid 置为0
     add_task(new_task);
-    new_pid as isize
+    Ok(new_pid)
+}
+
+/// 读取用户空间中以空指针结尾的字符串指针数组，ptr 为空指针时视为空数组
+fn copy_in_str_array(memory_set: &mut MemorySet, mut ptr: *const usize) -> SysResult<Vec<String>> {
+    let mut strings = Vec::new();
+    if ptr.is_null() {
+        return Ok(strings);
+    }
+    loop {
+        let str_ptr = memory_set.copy_in(ptr)?;
+        if str_ptr == 0 {
+            return Ok(strings);
+        }
+        strings.push(memory_set.copy_in_str(str_ptr as *const u8)?);
+        ptr = ptr.wrapping_add(1);
+    }
 }
 
-pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
+/// 功能：执行程序 path，用新程序替换当前进程的地址空间。
+/// 参数：args、envp 分别为以空指针结尾的参数、环境变量（"KEY=VALUE" 形式）字符串指针数组，
+/// envp 为空指针时新程序没有环境变量，只传递两个参数的旧程序也因此可以正常调用。
+/// 返回值：成功时不会返回到调用处。多线程进程不能 exec，返回 EINVAL；地址不合法返回 EFAULT；
+/// 文件不存在返回 ENOENT；不是可执行文件返回 ENOEXEC；参数与环境变量过长返回 E2BIG。
+/// syscall ID：221
+pub fn sys_execve(path: *const u8, args: *const usize, envp: *const usize) -> SysResult {
     // 用户空间到内核空间，需要使用当前任务的地址空间对字符串进行解释
-    let token = current_user_token();
-    let path = translated_str(token, path);
-    let mut args_vec: Vec<String> = Vec::new();
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    if inner.thread_count() != 1 {
+        return Err(SysError::EINVAL);
+    }
+    let path = inner.memory_set.copy_in_str(path)?;
+    let args_vec = copy_in_str_array(&mut inner.memory_set, args)?;
+    let envs_vec = copy_in_str_array(&mut inner.memory_set, envp)?;
+    drop(inner);
+    let app_inode = open_file(path.as_str(), OpenFlags::RDONLY)?;
+    let all_data = app_inode.read_all();
+    let argc = args_vec.len();
+    process.exec(all_data.as_slice(), args_vec, envs_vec)?;
+    // return argc because cx.x[10] will be covered with it later
+    Ok(argc)
+}
+
+/// waitpid 的选项：子进程都还在运行时不阻塞，直接返回 0
+const WNOHANG: usize = 1;
+/// waitpid 的选项：同时报告因信号暂停的子进程
+const WUNTRACED: usize = 2;
+
+/// 功能：等待子进程退出并回收。pid==-1，表示任意子进程。
+/// 子进程都还在运行时阻塞，直到有子进程退出；options 包含 WNOHANG 时不阻塞。
+/// options 包含 WUNTRACED 时，子进程因信号暂停也会返回（不回收），每次暂停只报告一次，
+/// 此时写回的退出码与 Linux 的编码一致：(signum << 8) | 0x7f。
+/// rusage 不为空指针时写回子进程及其已回收后代的资源使用，回收的子进程的统计同时计入当前进程的
+/// children_usage（getrusage(RUSAGE_CHILDREN)、times 的 cutime/cstime）。
+/// 返回值：回收（或暂停）的子进程 pid；指定了 WNOHANG 且子进程还在运行时返回 0；
+/// 没有符合条件的子进程返回 ECHILD；地址不合法返回 EFAULT；
+/// 等待被信号打断时返回 EINTR，用户库在信号处理完后重新等待。
+/// syscall ID：260
+pub fn sys_waitpid(
+    pid: isize,
+    exit_code_ptr: *mut i32,
+    options: usize,
+    rusage: *mut Rusage,
+) -> SysResult {
+    let process = current_process();
     loop {
-        let arg_str_ptr = *translated_ref(token, args);
-        if arg_str_ptr == 0 {
-            break;
+        // ---- 请求当前 PCB 锁
+        let mut inner = process.acquire_inner_lock();
+        if inner
+            .children
+            .iter()
+            .find(|p| pid == -1 || pid as usize == p.getpid())
+            .is_none()
+        {
+            return Err(SysError::ECHILD);
+        }
+        let pair = inner.children.iter().enumerate().find(|(_, p)| {
+            p.acquire_inner_lock().is_zombie && (pid == -1 || pid as usize == p.getpid())
+        });
+        if let Some((idx, _)) = pair {
+            // 先写回退出码与资源使用，地址不合法时子进程留待下次回收
+            let (exit_code, usage) = {
+                let child_inner = inner.children[idx].acquire_inner_lock();
+                let mut usage = child_inner.usage();
+                usage.add(&child_inner.children_usage);
+                (child_inner.exit_code, usage)
+            };
+            inner.memory_set.copy_out(exit_code_ptr, &exit_code)?;
+            if !rusage.is_null() {
+                inner.memory_set.copy_out(rusage, &usage.to_rusage())?;
+            }
+            inner.children_usage.add(&usage);
+            // 子进程最后退出的线程可能还在其它核上运行并持有引用，
+            // 所有引用释放后子进程才被回收
+            let child = inner.children.remove(idx);
+            return Ok(child.getpid());
+        }
+        if options & WUNTRACED != 0 {
+            let stopped = inner.children.iter().find(|p| {
+                p.acquire_inner_lock().stop_signal.is_some()
+                    && (pid == -1 || pid as usize == p.getpid())
+            });
+            if let Some(child) = stopped.cloned() {
+                let mut child_inner = child.acquire_inner_lock();
+                // 加锁前子进程可能已被 SIGCONT 唤醒
+                if let Some(signum) = child_inner.stop_signal {
+                    let status = ((signum << 8) | 0x7f) as i32;
+                    inner.memory_set.copy_out(exit_code_ptr, &status)?;
+                    if !rusage.is_null() {
+                        let mut usage = child_inner.usage();
+                        usage.add(&child_inner.children_usage);
+                        inner.memory_set.copy_out(rusage, &usage.to_rusage())?;
+                    }
+                    child_inner.stop_signal = None;
+                    return Ok(child.getpid());
+                }
+            }
+        }
+        if options & WNOHANG != 0 {
+            return Ok(0);
         }
-        args_vec.push(translated_str(token, arg_str_ptr as _));
-        unsafe {
-            args = args.add(1);
+        // 持有进程锁时加入等待队列，子进程退出时要先获取本进程锁才能唤醒，不会错过唤醒
+        if !inner.wait_queue.block_current() {
+            return Err(SysError::EINTR);
         }
+        drop(inner);
+        // ---- 释放当前 PCB 锁
+        block_current_and_run_next();
     }
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
-        let all_data = app_inode.read_all();
-        let task = current_task().unwrap();
-        let argc = args_vec.len();
-        task.exec(all_data.as_slice(), args_vec);
-        // return argc because cx.x[10] will be covered with it later
-        argc as isize
+}
+
+/// 功能：发送信号 signum。pid 大于 0 时发给进程 pid；pid 为 0 时发给当前进程所在的进程组；
+/// pid 小于 -1 时发给进程组 -pid。
+/// 返回值：成功返回 0；信号编号不合法或 pid 为 -1 时返回 EINVAL；进程（组）不存在（或已退出）返回 ESRCH。
+/// syscall ID：129
+pub fn sys_kill(pid: isize, signum: i32) -> SysResult {
+    let signal = SignalFlags::from_signum(signum as usize).ok_or(SysError::EINVAL)?;
+    match pid {
+        -1 => return Err(SysError::EINVAL),
+        0 => {
+            let pgid = current_process().acquire_inner_lock().pgid;
+            send_signal_to_group(pgid, signal)?;
+        }
+        pid if pid < 0 => send_signal_to_group((-pid) as usize, signal)?,
+        pid => {
+            let process = pid2process(pid as usize).ok_or(SysError::ESRCH)?;
+            send_signal_to_process(&process, signal)?;
+        }
+    }
+    Ok(0)
+}
+
+/// pid 为 0 时返回当前进程，否则按 pid 查找进程，不存在时返回 ESRCH
+fn pid_or_current(pid: usize) -> SysResult<Arc<PCB>> {
+    if pid == 0 {
+        Ok(current_process())
+    } else {
+        pid2process(pid).ok_or(SysError::ESRCH)
+    }
+}
+
+/// 功能：将进程 pid 加入进程组 pgid。pid 为 0 表示当前进程，pgid 为 0 表示以 pid 为 id 新建进程组。
+/// 只能设置当前进程或其子进程，加入已有的进程组时，该进程组需要与当前进程在同一会话中。
+/// 返回值：成功返回 0；pid 不是当前进程或其子进程时返回 ESRCH；
+/// 目标进程是会话首进程、与当前进程不在同一会话，或进程组属于其它会话（或不存在）时返回 EPERM。
+/// syscall ID：154
+pub fn sys_setpgid(pid: usize, pgid: usize) -> SysResult {
+    let process = current_process();
+    let inner = process.acquire_inner_lock();
+    let target = if pid == 0 || pid == process.getpid() {
+        Arc::clone(&process)
     } else {
-        -1
+        inner
+            .children
+            .iter()
+            .find(|child| child.getpid() == pid)
+            .cloned()
+            .ok_or(SysError::ESRCH)?
+    };
+    let sid = inner.sid;
+    drop(inner);
+    let target_pid = target.getpid();
+    let pgid = if pgid == 0 { target_pid } else { pgid };
+    let target_inner = target.acquire_inner_lock();
+    if target_inner.is_zombie {
+        return Err(SysError::ESRCH);
+    }
+    if target_inner.sid != sid || target_pid == sid {
+        return Err(SysError::EPERM);
+    }
+    drop(target_inner);
+    if pgid != target_pid
+        && pgid2processes(pgid)
+            .iter()
+            .all(|member| member.acquire_inner_lock().sid != sid)
+    {
+        return Err(SysError::EPERM);
+    }
+    target.acquire_inner_lock().pgid = pgid;
+    Ok(0)
+}
+
+/// 功能：获取进程 pid 的进程组 id，pid 为 0 表示当前进程。
+/// 返回值：进程组 id；进程不存在返回 ESRCH。
+/// syscall ID：155
+pub fn sys_getpgid(pid: usize) -> SysResult {
+    Ok(pid_or_current(pid)?.acquire_inner_lock().pgid)
+}
+
+/// 功能：获取进程 pid 的会话 id，pid 为 0 表示当前进程。
+/// 返回值：会话 id；进程不存在返回 ESRCH。
+/// syscall ID：156
+pub fn sys_getsid(pid: usize) -> SysResult {
+    Ok(pid_or_current(pid)?.acquire_inner_lock().sid)
+}
+
+/// 功能：新建会话，当前进程成为会话首进程，并成为会话中唯一进程组的组长。
+/// 返回值：新会话的 id，即当前进程的 pid；当前进程已经是进程组组长时返回 EPERM。
+/// syscall ID：157
+pub fn sys_setsid() -> SysResult {
+    let process = current_process();
+    let pid = process.getpid();
+    if !pgid2processes(pid).is_empty() {
+        return Err(SysError::EPERM);
+    }
+    let mut inner = process.acquire_inner_lock();
+    inner.sid = pid;
+    inner.pgid = pid;
+    Ok(pid)
+}
+
+/// 功能：设置进程收到信号 signum 时的处理动作。
+/// 参数：action 为新的处理动作，old_action 用于返回原来的处理动作，两者都可以为空指针。
+/// 返回值：成功返回 0；信号编号不合法、试图修改 SIGKILL/SIGSTOP 的动作时返回 EINVAL；
+/// 地址不合法时返回 EFAULT。
+/// syscall ID：134
+pub fn sys_sigaction(
+    signum: i32,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> SysResult {
+    let signal = SignalFlags::from_signum(signum as usize).ok_or(SysError::EINVAL)?;
+    if signal == SignalFlags::SIGKILL || signal == SignalFlags::SIGSTOP {
+        return Err(SysError::EINVAL);
     }
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    if !old_action.is_null() {
+        let old = inner.signal_actions.table[signum as usize];
+        inner.memory_set.copy_out(old_action, &old)?;
+    }
+    if !action.is_null() {
+        let mut new = inner.memory_set.copy_in(action)?;
+        new.mask = SignalFlags::from_bits_truncate(new.mask.bits())
+            - (SignalFlags::SIGKILL | SignalFlags::SIGSTOP);
+        inner.signal_actions.table[signum as usize] = new;
+    }
+    Ok(0)
 }
 
-/// pid==-1，表示任意子进程。pid 不存在返回 -1；如果子程序还在跑，则返回 -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// 功能：设置当前线程的信号屏蔽字为 mask，SIGKILL 和 SIGSTOP 不能被屏蔽。
+/// 返回值：原来的屏蔽字。
+/// syscall ID：135
+pub fn sys_sigprocmask(mask: u32) -> SysResult {
     let task = current_task().unwrap();
-    // ---- 请求当前 PCB 锁
     let mut inner = task.acquire_inner_lock();
-    if inner
-        .children
-        .iter()
-        .find(|p| pid == -1 || pid as usize == p.getpid())
-        .is_none()
-    {
-        return -1;
-    }
-    let pair = inner.children.iter().enumerate().find(|(_, p)| {
-        p.acquire_inner_lock().is_zombie() && (pid == -1 || pid as usize == p.getpid())
-    });
-    if let Some((idx, _)) = pair {
-        let child = inner.children.remove(idx);
-        // confirm that child will be deallocated after removing from children list
-        assert_eq!(Arc::strong_count(&child), 1);
-        let found_pid = child.getpid();
-        let exit_code = child.acquire_inner_lock().exit_code;
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
-    } else {
-        -2
+    let old_mask = inner.signal_mask;
+    inner.signal_mask = SignalFlags::from_bits_truncate(mask)
+        - (SignalFlags::SIGKILL | SignalFlags::SIGSTOP);
+    Ok(old_mask.bits() as usize)
+}
+
+/// 功能：从信号处理函数返回，恢复被信号打断前的执行流与信号屏蔽字。
+/// 返回值：不在信号处理函数中时返回 EINVAL，否则不会返回到调用处。
+/// syscall ID：139
+pub fn sys_sigreturn() -> SysResult {
+    signal_return()
+}
+
+/// 功能：设置当前线程的优先级，优先级越高获得的 CPU 时间越多，只在步长调度下生效。
+/// 参数：prio 为新的优先级，不能小于 2。
+/// 返回值：成功返回设置的优先级，优先级不合法返回 EINVAL。
+/// syscall ID：140
+pub fn sys_set_priority(prio: isize) -> SysResult {
+    if prio < 2 {
+        return Err(SysError::EINVAL);
     }
+    current_task().unwrap().acquire_inner_lock().priority = prio as usize;
+    Ok(prio as usize)
 }
@@ -0,0 +1,266 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    error::{SysError, SysResult},
+    mm::{translate_user_va, VirtAddr},
+    sync::{futex_wait, futex_wake, Condvar, Mutex, MutexBlocking, MutexSpin, Semaphore},
+    task::{current_process, current_task},
+};
+
+/// 将 item 放入表中的空位，没有空位时追加到末尾，返回其下标
+fn insert_into<T>(list: &mut Vec<Option<T>>, item: T) -> usize {
+    if let Some(id) = (0..list.len()).find(|&id| list[id].is_none()) {
+        list[id] = Some(item);
+        id
+    } else {
+        list.push(Some(item));
+        list.len() - 1
+    }
+}
+
+/// 功能：为当前进程创建一个互斥锁。
+/// 参数：blocking 为 true 时创建阻塞互斥锁，否则创建自旋互斥锁（获取不到锁时让出 CPU 后重试）。
+/// 返回值：互斥锁的 id。
+/// syscall ID：1010
+pub fn sys_mutex_create(blocking: bool) -> SysResult {
+    let process = current_process();
+    let mutex: Arc<dyn Mutex> = if blocking {
+        Arc::new(MutexBlocking::new())
+    } else {
+        Arc::new(MutexSpin::new())
+    };
+    let mut process_inner = process.acquire_inner_lock();
+    let mutex_id = insert_into(&mut process_inner.mutex_list, mutex);
+    process_inner.mutex_detector.add_resource(mutex_id, 1);
+    Ok(mutex_id)
+}
+
+/// 功能：获取互斥锁，锁被占用时等待。
+/// 返回值：成功返回 0；互斥锁不存在返回 EINVAL；被信号打断时返回 EINTR，此时没有获得锁；
+/// 开启了死锁检测且获取会导致死锁时返回 EDEADLK，不会等待。
+/// syscall ID：1011
+pub fn sys_mutex_lock(mutex_id: usize) -> SysResult {
+    let mutex = get_mutex(mutex_id)?;
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.acquire_inner_lock();
+    let check = process_inner.deadlock_detect;
+    if !process_inner.mutex_detector.request(tid, mutex_id, check) {
+        return Err(SysError::EDEADLK);
+    }
+    drop(process_inner);
+    let locked = mutex.lock();
+    let mut process_inner = process.acquire_inner_lock();
+    if locked {
+        process_inner.mutex_detector.acquire(tid, mutex_id);
+        Ok(0)
+    } else {
+        process_inner.mutex_detector.cancel(tid, mutex_id);
+        Err(SysError::EINTR)
+    }
+}
+
+/// 功能：释放互斥锁。
+/// 返回值：成功返回 0；互斥锁不存在返回 EINVAL。
+/// syscall ID：1012
+pub fn sys_mutex_unlock(mutex_id: usize) -> SysResult {
+    let mutex = get_mutex(mutex_id)?;
+    let tid = current_tid();
+    // 先记录释放再真正释放，避免检测时把已经空闲的锁当作被占用
+    current_process()
+        .acquire_inner_lock()
+        .mutex_detector
+        .release(tid, mutex_id);
+    mutex.unlock();
+    Ok(0)
+}
+
+/// 功能：为当前进程创建一个信号量。
+/// 参数：res_count 为初始资源数。
+/// 返回值：信号量的 id。
+/// syscall ID：1020
+pub fn sys_semaphore_create(res_count: usize) -> SysResult {
+    let process = current_process();
+    let semaphore = Arc::new(Semaphore::new(res_count));
+    let mut process_inner = process.acquire_inner_lock();
+    let sem_id = insert_into(&mut process_inner.semaphore_list, semaphore);
+    process_inner
+        .semaphore_detector
+        .add_resource(sem_id, res_count);
+    Ok(sem_id)
+}
+
+/// 功能：释放信号量的一个资源（V 操作）。
+/// 返回值：成功返回 0；信号量不存在返回 EINVAL。
+/// syscall ID：1021
+pub fn sys_semaphore_up(sem_id: usize) -> SysResult {
+    let semaphore = get_semaphore(sem_id)?;
+    let tid = current_tid();
+    current_process()
+        .acquire_inner_lock()
+        .semaphore_detector
+        .release_any(tid, sem_id);
+    semaphore.up();
+    Ok(0)
+}
+
+/// 功能：获取信号量的一个资源（P 操作），没有资源时等待。
+/// 返回值：成功返回 0；信号量不存在返回 EINVAL；被信号打断时返回 EINTR，此时没有获得资源；
+/// 开启了死锁检测且获取会导致死锁时返回 EDEADLK，不会等待。
+/// syscall ID：1022
+pub fn sys_semaphore_down(sem_id: usize) -> SysResult {
+    let semaphore = get_semaphore(sem_id)?;
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.acquire_inner_lock();
+    let check = process_inner.deadlock_detect;
+    if !process_inner.semaphore_detector.request(tid, sem_id, check) {
+        return Err(SysError::EDEADLK);
+    }
+    drop(process_inner);
+    let acquired = semaphore.down();
+    let mut process_inner = process.acquire_inner_lock();
+    if acquired {
+        process_inner.semaphore_detector.acquire(tid, sem_id);
+        Ok(0)
+    } else {
+        process_inner.semaphore_detector.cancel(tid, sem_id);
+        Err(SysError::EINTR)
+    }
+}
+
+/// 功能：为当前进程创建一个条件变量。
+/// 返回值：条件变量的 id。
+/// syscall ID：1030
+pub fn sys_condvar_create() -> SysResult {
+    let process = current_process();
+    let condvar = Arc::new(Condvar::new());
+    let mut process_inner = process.acquire_inner_lock();
+    Ok(insert_into(&mut process_inner.condvar_list, condvar))
+}
+
+/// 功能：唤醒一个在条件变量上等待的线程。
+/// 返回值：成功返回 0；条件变量不存在返回 EINVAL。
+/// syscall ID：1031
+pub fn sys_condvar_signal(condvar_id: usize) -> SysResult {
+    get_condvar(condvar_id)?.signal();
+    Ok(0)
+}
+
+/// 功能：释放互斥锁并在条件变量上等待，被唤醒后重新获取互斥锁。调用前需要持有该互斥锁。
+/// 返回值：成功返回 0；条件变量或互斥锁不存在返回 EINVAL；
+/// 重新获取互斥锁时被信号打断返回 EINTR，此时没有持有互斥锁，需要重新获取。
+/// syscall ID：1032
+pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> SysResult {
+    let condvar = get_condvar(condvar_id)?;
+    let mutex = get_mutex(mutex_id)?;
+    let tid = current_tid();
+    let process = current_process();
+    // 等待期间不持有互斥锁
+    process
+        .acquire_inner_lock()
+        .mutex_detector
+        .release(tid, mutex_id);
+    if !condvar.wait(mutex) {
+        return Err(SysError::EINTR);
+    }
+    let mut process_inner = process.acquire_inner_lock();
+    process_inner.mutex_detector.request(tid, mutex_id, false);
+    process_inner.mutex_detector.acquire(tid, mutex_id);
+    Ok(0)
+}
+
+/// 功能：开启或关闭当前进程的死锁检测。开启后，获取互斥锁或信号量前会用银行家算法检查，
+/// 会导致死锁的获取操作直接返回 EDEADLK。
+/// 参数：enabled 为 1 时开启，为 0 时关闭。
+/// 返回值：成功返回 0；参数不合法返回 EINVAL。
+/// syscall ID：469
+pub fn sys_enable_deadlock_detect(enabled: usize) -> SysResult {
+    let enabled = match enabled {
+        0 => false,
+        1 => true,
+        _ => return Err(SysError::EINVAL),
+    };
+    current_process().acquire_inner_lock().deadlock_detect = enabled;
+    Ok(0)
+}
+
+/// futex 操作：值未改变时等待
+const FUTEX_WAIT: usize = 0;
+/// futex 操作：唤醒等待者
+const FUTEX_WAKE: usize = 1;
+
+/// 功能：对用户地址 addr 处的 32 位字进行 futex 操作，用于在用户态实现锁，只在发生竞争时陷入内核。
+/// 等待者以该字所在的物理地址区分，共享同一物理页的进程之间也可以使用。
+/// 参数：addr 需要按 4 字节对齐；op 为 FUTEX_WAIT(0) 或 FUTEX_WAKE(1)。
+/// FUTEX_WAIT：addr 处的值等于 val 时阻塞，直到被唤醒或经过 timeout 毫秒（为 0 时不超时）。
+/// FUTEX_WAKE：最多唤醒 val 个等待者。
+/// 返回值：addr 未对齐或 op 不合法返回 EINVAL，地址不可访问返回 EFAULT。
+/// FUTEX_WAIT 被唤醒返回 0，值不等于 val 返回 EAGAIN，被信号打断返回 EINTR，超时返回 ETIMEDOUT；
+/// FUTEX_WAKE 返回唤醒的线程数。
+/// syscall ID：98
+pub fn sys_futex(addr: usize, op: usize, val: u32, timeout: usize) -> SysResult {
+    if addr % core::mem::size_of::<u32>() != 0 {
+        return Err(SysError::EINVAL);
+    }
+    let process = current_process();
+    let mut process_inner = process.acquire_inner_lock();
+    // 按写访问处理缺页，写时复制的页面会在这里复制，之后的物理地址保持不变。
+    // 等待期间持有页桢，页面不会被换出，唤醒方与等待方看到的物理地址一致
+    let _frames = process_inner.memory_set.fault_in_range(
+        VirtAddr::from(addr),
+        core::mem::size_of::<u32>(),
+        true,
+    )?;
+    let token = process_inner.get_user_token();
+    drop(process_inner);
+    let pa: usize = translate_user_va(token, addr, true)?.into();
+    match op {
+        FUTEX_WAIT => futex_wait(pa, val, timeout).map(|_| 0),
+        FUTEX_WAKE => Ok(futex_wake(pa, val as usize)),
+        _ => Err(SysError::EINVAL),
+    }
+}
+
+/// 当前线程的 tid
+fn current_tid() -> usize {
+    current_task()
+        .unwrap()
+        .acquire_inner_lock()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid
+}
+
+// 等待时不能持有进程锁，所以先取出同步原语的引用，释放进程锁后再使用
+
+fn get_mutex(mutex_id: usize) -> SysResult<Arc<dyn Mutex>> {
+    let process = current_process();
+    let process_inner = process.acquire_inner_lock();
+    process_inner
+        .mutex_list
+        .get(mutex_id)
+        .and_then(|mutex| mutex.clone())
+        .ok_or(SysError::EINVAL)
+}
+
+fn get_semaphore(sem_id: usize) -> SysResult<Arc<Semaphore>> {
+    let process = current_process();
+    let process_inner = process.acquire_inner_lock();
+    process_inner
+        .semaphore_list
+        .get(sem_id)
+        .and_then(|semaphore| semaphore.clone())
+        .ok_or(SysError::EINVAL)
+}
+
+fn get_condvar(condvar_id: usize) -> SysResult<Arc<Condvar>> {
+    let process = current_process();
+    let process_inner = process.acquire_inner_lock();
+    process_inner
+        .condvar_list
+        .get(condvar_id)
+        .and_then(|condvar| condvar.clone())
+        .ok_or(SysError::EINVAL)
+}
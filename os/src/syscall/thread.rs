@@ -0,0 +1,94 @@
+use alloc::sync::Arc;
+
+use crate::{
+    config::PAGE_SIZE,
+    error::{SysError, SysResult},
+    mm::kernel_token,
+    task::{add_task, current_task, RLIMIT_AS, TCB},
+    trap::{trap_handler, TrapContext},
+};
+
+/// 功能：在当前进程中创建一个新线程，新线程从 entry 开始执行，用户栈由内核分配。
+/// 参数：entry 为线程入口函数地址，arg 为传给入口函数的参数（通过 a0 传递）。
+/// 入口函数不能返回，需要调用 exit 结束线程。
+/// 返回值：新线程的 tid；加上新线程的用户栈和 TrapContext 后地址空间超过 RLIMIT_AS，
+/// 或页桢不足时返回 ENOMEM。
+/// syscall ID：1000
+pub fn sys_thread_create(entry: usize, arg: usize) -> SysResult {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let inner = process.acquire_inner_lock();
+    let new_size = inner.memory_set.mapped_size() + inner.rlimits.stack_size() + PAGE_SIZE;
+    if new_size > inner.rlimits.cur(RLIMIT_AS) {
+        return Err(SysError::ENOMEM);
+    }
+    drop(inner);
+    // 分配 tid、用户栈、TrapContext 及内核栈
+    let new_task = Arc::new(TCB::new(Arc::clone(&process), true)?);
+    let mut new_task_inner = new_task.acquire_inner_lock();
+    let new_task_res = new_task_inner.res.as_ref().unwrap();
+    let new_task_tid = new_task_res.tid;
+    let new_task_trap_cx = new_task_inner.get_trap_cx();
+    *new_task_trap_cx = TrapContext::app_init_context(
+        entry,
+        new_task_res.ustack_top(),
+        kernel_token(),
+        new_task.kernel_stack.get_top(),
+        trap_handler as usize,
+    );
+    new_task_trap_cx.x[10] = arg;
+    // 新线程继承创建者的优先级
+    new_task_inner.priority = task.acquire_inner_lock().priority;
+    drop(new_task_inner);
+    // 以 tid 为下标记录到进程中
+    let mut process_inner = process.acquire_inner_lock();
+    let tasks = &mut process_inner.tasks;
+    while tasks.len() < new_task_tid + 1 {
+        tasks.push(None);
+    }
+    tasks[new_task_tid] = Some(Arc::clone(&new_task));
+    drop(process_inner);
+    add_task(new_task);
+    Ok(new_task_tid)
+}
+
+/// 功能：获取当前线程的 tid。
+/// syscall ID：1001
+pub fn sys_gettid() -> SysResult {
+    Ok(current_task()
+        .unwrap()
+        .acquire_inner_lock()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid)
+}
+
+/// 功能：等待当前进程内的线程 tid 退出，并回收其 tid 与内核栈。
+/// 参数：exit_code_ptr 用于返回线程的退出码，为空指针时不返回。
+/// 退出码可能为负数，放在返回值中会与错误码混淆，所以通过指针返回。
+/// 返回值：成功返回 tid；等待的是自己时返回 EDEADLK；线程不存在返回 ESRCH；
+/// 线程还未退出返回 EAGAIN；地址不合法返回 EFAULT。
+/// syscall ID：1002
+pub fn sys_waittid(tid: usize, exit_code_ptr: *mut i32) -> SysResult {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    // 线程不能等待自己
+    if task.acquire_inner_lock().res.as_ref().unwrap().tid == tid {
+        return Err(SysError::EDEADLK);
+    }
+    let mut process_inner = process.acquire_inner_lock();
+    let exit_code = match process_inner.tasks.get(tid) {
+        Some(Some(waited_task)) => waited_task.acquire_inner_lock().exit_code,
+        _ => return Err(SysError::ESRCH),
+    };
+    let exit_code = exit_code.ok_or(SysError::EAGAIN)?;
+    if !exit_code_ptr.is_null() {
+        process_inner.memory_set.copy_out(exit_code_ptr, &exit_code)?;
+    }
+    let waited_task = process_inner.tasks[tid].take();
+    drop(process_inner);
+    // 释放 TCB 时会回收 tid，需要进程锁，所以在释放锁之后进行
+    drop(waited_task);
+    Ok(tid)
+}
@@ -1,39 +1,31 @@
-use alloc::{collections::VecDeque, sync::Arc};
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use spin::Mutex;
 
-use super::task::TCB;
+#[cfg(not(feature = "sched_stride"))]
+use super::scheduler::RRScheduler;
+#[cfg(feature = "sched_stride")]
+use super::scheduler::StrideScheduler;
+use super::{process::PCB, scheduler::Scheduler, task::TCB};
 use lazy_static::lazy_static;
 
-pub struct TaskManager {
-    /// 就绪队列
-    /// 使用 Arc 是为了减少对 TCB 结构的数据拷贝开销；在一些情况下会更方便
-    ready_queue: VecDeque<Arc<TCB>>,
-}
-
-impl TaskManager {
-    pub fn new() -> Self {
-        Self {
-            ready_queue: VecDeque::new(),
-        }
-    }
-
-    /// 添加可运行 TCB
-    pub fn add(&mut self, task: Arc<TCB>) {
-        self.ready_queue.push_back(task);
-    }
-
-    /// 从就绪列表中获取第一个 TCB
-    pub fn fetch(&mut self) -> Option<Arc<TCB>> {
-        self.ready_queue.pop_front()
-    }
-}
+/// 调度算法，通过 cargo feature 选择：启用 sched_stride 时使用步长调度，否则使用时间片轮转
+#[cfg(not(feature = "sched_stride"))]
+type SchedulerImpl = RRScheduler;
+#[cfg(feature = "sched_stride")]
+type SchedulerImpl = StrideScheduler;
 
 lazy_static! {
-    /// 全局任务管理器，这种实现只支持多核共享一个 TaskManager，其它实现可能是
-    /// 每个核独占一个任务管理器
-    pub static ref TASK_MANAGER: Mutex<TaskManager> = Mutex::new(TaskManager::new());
+    /// 全局任务管理器，这种实现只支持多核共享一个调度器，其它实现可能是
+    /// 每个核独占一个调度器
+    pub static ref TASK_MANAGER: Mutex<SchedulerImpl> = Mutex::new(SchedulerImpl::new());
+    /// pid 到进程的映射，用于按 pid 查找进程（如 kill）。进程退出时移除
+    pub static ref PID2PCB: Mutex<BTreeMap<usize, Arc<PCB>>> = Mutex::new(BTreeMap::new());
 }
 
+/// swap_out_page 下一次从第几个进程开始尝试换出
+static SWAP_CURSOR: AtomicUsize = AtomicUsize::new(0);
+
 /// 添加一个就绪任务
 pub fn add_task(task: Arc<TCB>) {
     TASK_MANAGER.lock().add(task)
@@ -43,3 +35,49 @@ pub fn add_task(task: Arc<TCB>) {
 pub fn fetch_task() -> Option<Arc<TCB>> {
     TASK_MANAGER.lock().fetch()
 }
+
+/// 按 pid 查找尚未退出的进程
+pub fn pid2process(pid: usize) -> Option<Arc<PCB>> {
+    PID2PCB.lock().get(&pid).map(Arc::clone)
+}
+
+/// 记录新进程
+pub fn insert_into_pid2process(pid: usize, process: Arc<PCB>) {
+    PID2PCB.lock().insert(pid, process);
+}
+
+/// 进程退出时移除
+pub fn remove_from_pid2process(pid: usize) {
+    PID2PCB.lock().remove(&pid);
+}
+
+/// 尚未退出的进程数
+pub fn process_count() -> usize {
+    PID2PCB.lock().len()
+}
+
+/// 物理内存耗尽时，从各进程中轮流选出一个换出一页，成功时返回 true。
+/// 调用方可能持有某些进程的锁，所以只尝试获取进程锁，跳过已被锁住的进程
+pub fn swap_out_page() -> bool {
+    let processes: Vec<Arc<PCB>> = PID2PCB.lock().values().cloned().collect();
+    let start = SWAP_CURSOR.fetch_add(1, Ordering::Relaxed);
+    for i in 0..processes.len() {
+        let process = &processes[(start + i) % processes.len()];
+        if let Some(mut inner) = process.try_acquire_inner_lock() {
+            if inner.memory_set.swap_out_one() {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// 进程组 pgid 中尚未退出的进程，调用方不能持有任何进程锁。
+/// 进程退出时会在持有进程锁的情况下移除映射，所以先复制出所有进程、释放映射的锁后再逐个检查
+pub fn pgid2processes(pgid: usize) -> Vec<Arc<PCB>> {
+    let processes: Vec<Arc<PCB>> = PID2PCB.lock().values().cloned().collect();
+    processes
+        .into_iter()
+        .filter(|process| process.acquire_inner_lock().pgid == pgid)
+        .collect()
+}
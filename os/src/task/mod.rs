@@ -10,57 +10,124 @@ use alloc::{sync::Arc, vec::Vec};
 pub use context::TaskContext;
 use lazy_static::lazy_static;
 use switch::__switch;
-use task::{TaskStatus, TCB};
+use manager::remove_from_pid2process;
+use task::TaskStatus;
 
-pub use self::{
-    manager::add_task,
-    processor::{schedule, take_current_task},
-};
+pub use self::{manager::add_task, processor::schedule};
 
-pub use processor::{current_user_token, current_trap_cx, run_tasks, current_task};
+pub use manager::{pgid2processes, pid2process, process_count, swap_out_page};
+pub use process::{PCBInner, PCB};
+pub use signal::{
+    handle_signals, send_fault_signal, send_signal_to_group, send_signal_to_process,
+    signal_return, SignalAction, SignalFlags, MAX_SIG, SIG_IGN,
+};
+pub use rlimit::{check_cpu_limit, RLimit, RLIMIT_AS, RLIMIT_NPROC};
+pub use processor::{
+    current_process, current_task, current_trap_cx, current_trap_cx_user_va, current_user_token,
+    run_tasks,
+};
+pub use task::{TCBInner, TCB};
+pub use usage::{ticks_to_ms, CpuUsage, Rusage, Tms};
+pub use wait_queue::WaitQueue;
 
 mod context;
 mod manager;
 mod pid;
+mod process;
 mod processor;
+mod rlimit;
+mod scheduler;
+mod signal;
 mod switch;
 mod task;
+mod usage;
+mod wait_queue;
 
 lazy_static! {
-    pub static ref INITPROC: Arc<TCB> =
-        Arc::new(TCB::new(get_app_data_by_name("initproc").unwrap()));
+    pub static ref INITPROC: Arc<PCB> = PCB::new(get_app_data_by_name("initproc").unwrap())
+        .expect("initproc is not a valid elf");
 }
 
 /// 内核初始化后调用，生成第一个用户程序。
 pub fn add_initproc() {
-    add_task(INITPROC.clone());
+    add_task(INITPROC.acquire_inner_lock().get_task(0));
 }
 
+/// 退出当前线程。主线程（tid 为 0）退出时，整个进程随之退出：其它线程可能正在别的核上运行，
+/// 向它们发送 SIGKILL，让它们在返回用户态前自行退出。
+/// 最后一个退出的线程负责回收进程资源，只留下 PCB 等待父进程 waitpid。
 pub fn exit_current_and_run_next(exit_code: i32) {
-    // 从 Processor 中弹出当前任务
-    let task = take_current_task().unwrap();
+    // 当前任务留在 Processor 中，切换到 idle 控制流后才释放，因为此时还在使用它的内核栈
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    // 同时持有两者时总是先获取进程锁，再获取线程锁
     // **** hold current PCB lock
-    let mut inner = task.acquire_inner_lock();
-    //
-    inner.task_status = TaskStatus::Zombie;
-    inner.exit_code = exit_code;
+    let mut inner = process.acquire_inner_lock();
+    let mut task_inner = task.acquire_inner_lock();
+    let tid = task_inner.res.as_ref().unwrap().tid;
+    task_inner.exit_code = Some(exit_code);
+    task_inner.task_status = TaskStatus::Exited;
+    // 退出线程的资源统计并入进程，之后不再计入
+    task_inner.account_time(false);
+    inner.exited_usage.add(&task_inner.usage);
+    // 用户栈与 TrapContext 可以立即回收；tid 与内核栈要等 waittid 取走退出码后才回收
+    task_inner
+        .res
+        .as_ref()
+        .unwrap()
+        .dealloc_user_res_in(&mut inner.memory_set);
+    // tid 之后可能被新线程重用，不能继承死锁检测中退出线程的记录
+    inner.mutex_detector.remove_thread(tid);
+    inner.semaphore_detector.remove_thread(tid);
+    drop(task_inner);
+    drop(task); // 释放当前任务引用数
 
-    // 所有子任务都挂到 initproc 上去
-    {
+    if tid == 0 {
+        inner.exit_code = exit_code;
+        for task in inner.tasks.iter().filter_map(|task| task.as_ref()) {
+            let mut task_inner = task.acquire_inner_lock();
+            if task_inner.task_status != TaskStatus::Exited {
+                task_inner.signals |= SignalFlags::SIGKILL;
+            }
+            drop(task_inner);
+            // 阻塞中的线程需要唤醒后才能处理信号
+            wakeup_task(Arc::clone(task));
+        }
+    }
+    let all_exited = inner
+        .tasks
+        .iter()
+        .filter_map(|task| task.as_ref())
+        .all(|task| task.acquire_inner_lock().task_status == TaskStatus::Exited);
+    if all_exited {
+        inner.is_zombie = true;
+        remove_from_pid2process(process.getpid());
+        let parent = inner.parent.as_ref().and_then(|parent| parent.upgrade());
+        let children = core::mem::take(&mut inner.children);
+        inner.fd_table.clear();
+        // 回收用户空间数据页
+        inner.memory_set.recycle_data_pages();
+        drop(inner);
+        // **** release current PCB lock
+
+        // 所有子进程都挂到 initproc 上去。
+        // initproc 的 waitpid 会先锁 initproc 再锁子进程，所以要在释放当前进程锁之后进行
         let mut initproc_inner = INITPROC.acquire_inner_lock();
-        for child in inner.children.iter() {
+        if !children.is_empty() {
+            // 挂过去的子进程可能已经退出了
+            initproc_inner.wait_queue.wake_all();
+        }
+        for child in children {
             child.acquire_inner_lock().parent = Some(Arc::downgrade(&INITPROC));
-            initproc_inner.children.push(child.clone());
+            initproc_inner.children.push(child);
+        }
+        drop(initproc_inner);
+        // 唤醒在 waitpid 中等待的父进程
+        if let Some(parent) = parent {
+            parent.acquire_inner_lock().wait_queue.wake_all();
         }
-        // 释放 initproc 锁
     }
-    inner.children.clear();
-    // 回收用户空间数据页
-    inner.memory_set.recycle_data_pages();
-    drop(inner); // todo? 为什么要显式 drop 这个 锁?
-                 // **** release current PCB lock
-
-    drop(task); // 释放当前任务引用数
+    drop(process);
     // we do not have to save task context
     // 由于上一任务已经退出，切换时就不需要再保存 taskContext 了。这里将其指定为0
     let _unused: usize = 0;
@@ -68,18 +135,75 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     schedule(&_unused as *const _);
 }
 
+/// 将当前线程标记为阻塞，返回当前线程，由调用方记录到等待队列等结构中，以便之后唤醒。
+/// 线程有待处理的信号时不阻塞，返回 None
+pub fn mark_current_blocked() -> Option<Arc<TCB>> {
+    let task = current_task().unwrap();
+    let mut task_inner = task.acquire_inner_lock();
+    if task_inner.has_pending_signal() {
+        return None;
+    }
+    task_inner.task_status = TaskStatus::Blocked;
+    drop(task_inner);
+    Some(task)
+}
+
+/// 阻塞当前线程并切换到其它任务。调用前需要先通过 mark_current_blocked（或 WaitQueue::block_current）
+/// 标记为阻塞并记录下来，然后释放所有的锁；被唤醒后从这里返回。
+pub fn block_current_and_run_next() {
+    let task = current_task().unwrap();
+    let mut task_inner = task.acquire_inner_lock();
+    task_inner.usage.nvcsw += 1;
+    let task_cx_ptr2 = task_inner.get_task_cx_ptr2();
+    drop(task_inner);
+    drop(task);
+    // 状态已被设为 Blocked，idle 控制流不会将其放回就绪队列
+    schedule(task_cx_ptr2);
+}
+
+/// 唤醒阻塞的线程，线程不处于阻塞状态时不做任何事
+pub fn wakeup_task(task: Arc<TCB>) {
+    let mut task_inner = task.acquire_inner_lock();
+    if task_inner.task_status != TaskStatus::Blocked {
+        return;
+    }
+    task_inner.task_status = TaskStatus::Ready;
+    let on_cpu = task_inner.on_cpu;
+    drop(task_inner);
+    // 还没有完全切换出去的线程由其所在核的 idle 控制流放回就绪队列
+    if !on_cpu {
+        add_task(task);
+    }
+}
+
+/// 当前线程主动让出 CPU，回到就绪队列。
 /// *注意*: 这个函数会切换上下文，对持有锁的函数，调用这个函数需要考虑手动释放，避免死锁。
 pub fn suspend_current_and_run_next() {
+    switch_out_current(true)
+}
+
+/// 时间片用完，当前线程被抢占，回到就绪队列。与 suspend_current_and_run_next 的区别
+/// 只在于统计为非自愿的上下文切换
+pub fn preempt_current_and_run_next() {
+    switch_out_current(false)
+}
+
+fn switch_out_current(voluntary: bool) {
     // 由于是暂停，所以必然有一个正在运行的任务
-    let task = take_current_task().unwrap();
+    let task = current_task().unwrap();
 
-    // hold current PCB lock
+    // hold current TCB lock
     let mut task_inner = task.acquire_inner_lock();
+    if voluntary {
+        task_inner.usage.nvcsw += 1;
+    } else {
+        task_inner.usage.nivcsw += 1;
+    }
     let task_cx_ptr2 = task_inner.get_task_cx_ptr2();
     task_inner.task_status = TaskStatus::Ready;
-    drop(task_inner); // 释放 PCB 锁
-
-    add_task(task);
+    drop(task_inner); // 释放 TCB 锁
+    drop(task);
 
+    // 切换完成后由 idle 控制流放回就绪队列
     schedule(task_cx_ptr2);
 }
@@ -1,15 +1,23 @@
-use alloc::vec::Vec;
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 use lazy_static::lazy_static;
 use spin::Mutex;
 
-use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE};
-use crate::mm::{KERNEL_SPACE, MapPermission, VirtAddr};
+use crate::config::{
+    KERNEL_STACK_SIZE, MAX_USER_STACK_SIZE, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_TOP,
+};
+use crate::error::SysResult;
+use crate::mm::{flush_pending, MapPermission, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
+
+use super::process::PCB;
 
 /// pid 的 RAII 模式
 pub struct PidHandle(pub usize);
 
-/// pid 分配器
-pub struct PidAllocator {
+/// 可回收的编号分配器，用于分配 pid、内核栈编号以及进程内的线程 tid
+pub struct RecycleAllocator {
     current: usize,
     recycled: Vec<usize>,
 }
@@ -20,7 +28,7 @@ impl Drop for PidHandle {
     }
 }
 
-impl PidAllocator {
+impl RecycleAllocator {
     pub fn new() -> Self {
         Self {
             current: 0,
@@ -28,66 +36,73 @@ impl PidAllocator {
         }
     }
 
-    /// 分配新的 pid
-    pub fn alloc(&mut self) -> PidHandle {
-        if let Some(pid) = self.recycled.pop() {
-            PidHandle(pid)
+    /// 分配新的编号，优先使用回收的编号
+    pub fn alloc(&mut self) -> usize {
+        if let Some(id) = self.recycled.pop() {
+            id
         } else {
             self.current += 1;
-            PidHandle(self.current - 1)
+            self.current - 1
         }
     }
 
-    /// 回收 pid
-    pub fn dealloc(&mut self, pid: usize) {
-        assert!(pid < self.current);
+    /// 回收编号
+    pub fn dealloc(&mut self, id: usize) {
+        assert!(id < self.current);
         assert!(
-            self.recycled.iter().find(|ppid| **ppid == pid).is_none(),
-            "pid {} has been deallocated!",
-            pid
+            self.recycled.iter().find(|i| **i == id).is_none(),
+            "id {} has been deallocated!",
+            id
         );
-        self.recycled.push(pid);
+        self.recycled.push(id);
     }
 }
 
 lazy_static! {
     /// 全局 PID 分配器
-    static ref PID_ALLOCATOR: Mutex<PidAllocator> = Mutex::new(PidAllocator::new());
+    static ref PID_ALLOCATOR: Mutex<RecycleAllocator> = Mutex::new(RecycleAllocator::new());
+    /// 全局内核栈编号分配器，每个线程拥有一个内核栈
+    static ref KSTACK_ALLOCATOR: Mutex<RecycleAllocator> = Mutex::new(RecycleAllocator::new());
 }
 
 /// 分配新 pid
 pub fn pid_alloc() -> PidHandle {
-    PID_ALLOCATOR.lock().alloc()
+    PidHandle(PID_ALLOCATOR.lock().alloc())
 }
 
-/// 返回应用在内核中的内核栈(虚拟地址)位置，[bottom, top)
-pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
-    // 程序内核栈间留一个 "Page" 的 gap，防止写到其它程序的数据上。
-    let top = TRAMPOLINE - app_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
+/// 返回内核栈(虚拟地址)位置，[bottom, top)
+pub fn kernel_stack_position(kstack_id: usize) -> (usize, usize) {
+    // 内核栈间留一个 "Page" 的 gap，防止写到其它线程的数据上。
+    let top = TRAMPOLINE - kstack_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
     let bottom = top - KERNEL_STACK_SIZE;
     (bottom, top)
 }
 
-/// 应用进程在内核的栈，目前按 app_id *(KERNEL_STACK_SIZE + PAGE_SIZE) 的
+/// 线程在内核的栈，按 kstack_id *(KERNEL_STACK_SIZE + PAGE_SIZE) 的
 /// 步长放在跳板下面，主要存放 TaskContext
 pub struct KernelStack {
-    /// 进程 PID
-    pid: usize,
+    /// 内核栈编号
+    kstack_id: usize,
 }
 
-impl KernelStack {
-    /// 根据 pid 新建一个 KernelStack，
-    pub fn new(pid_handle: &PidHandle) -> Self {
-        let pid = pid_handle.0;
-        let (bottom, top) = kernel_stack_position(pid);
-        KERNEL_SPACE.lock().insert_framed_area(
-            bottom.into(),
-            top.into(),
-            MapPermission::R | MapPermission::W,
-        );
-        KernelStack { pid }
-    }
+/// 分配一个新的内核栈，页桢不足时返回 ENOMEM
+pub fn kstack_alloc() -> SysResult<KernelStack> {
+    // 先构造 KernelStack，映射失败时由它的 drop 回收编号
+    let kernel_stack = KernelStack {
+        kstack_id: KSTACK_ALLOCATOR.lock().alloc(),
+    };
+    let (bottom, top) = kernel_stack_position(kernel_stack.kstack_id);
+    KERNEL_SPACE.lock().insert_framed_area(
+        bottom.into(),
+        top.into(),
+        MapPermission::R | MapPermission::W,
+    )?;
+    // 本核 TLB 中可能还缓存着此前使用同一编号的内核栈的映射，回收时已为各核记下待刷新
+    flush_pending();
+    Ok(kernel_stack)
+}
 
+impl KernelStack {
     /// 在栈顶插入类型为 T 的数据, 并返回其指针
     pub fn push_on_top<T>(&self, value: T) -> *mut T
     where
@@ -101,19 +116,143 @@ impl KernelStack {
         ptr_mut
     }
 
-    /// 返回进程对应的内核栈栈顶
+    /// 返回内核栈栈顶
     pub fn get_top(&self) -> usize {
-        let (_, top) = kernel_stack_position(self.pid);
+        let (_, top) = kernel_stack_position(self.kstack_id);
         top
     }
 }
 
 impl Drop for KernelStack {
     fn drop(&mut self) {
-        let (kernel_stack_bottom, _) = kernel_stack_position(self.pid);
+        let (kernel_stack_bottom, _) = kernel_stack_position(self.kstack_id);
         let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
         KERNEL_SPACE
             .lock()
             .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
+        KSTACK_ALLOCATOR.lock().dealloc(self.kstack_id);
+    }
+}
+
+/// 线程 TrapContext 所在页的虚拟地址，从 TRAP_CONTEXT 开始按 tid 向下排列
+pub fn trap_cx_bottom_from_tid(tid: usize) -> usize {
+    TRAP_CONTEXT - tid * PAGE_SIZE
+}
+
+/// 线程用户栈顶，从 USER_STACK_TOP 开始按 tid 向下排列。每个线程预留 MAX_USER_STACK_SIZE 的栈空间，
+/// 栈的实际大小由 RLIMIT_STACK 决定，栈之间至少留一个守护页
+pub fn ustack_top_from_tid(tid: usize) -> usize {
+    USER_STACK_TOP - tid * (MAX_USER_STACK_SIZE + PAGE_SIZE)
+}
+
+/// 线程在所属进程地址空间中占用的资源：tid、用户栈以及 TrapContext 页。
+/// tid 在本结构释放时回收；用户栈和 TrapContext 可以在线程退出时通过
+/// dealloc_user_res 提前回收，而 tid 要保留到 waittid 取走退出码为止。
+pub struct TaskUserRes {
+    pub tid: usize,
+    pub process: Weak<PCB>,
+    /// 用户栈大小，在映射用户栈时按所属进程的 RLIMIT_STACK 确定
+    pub ustack_size: usize,
+}
+
+impl TaskUserRes {
+    /// 在 process 中为新线程分配 tid，alloc_user_res 为 true 时同时映射用户栈和 TrapContext；
+    /// fork 出的线程沿用从父进程复制来的地址空间，不需要重新映射，但需要调用方设置 ustack_size。
+    /// 页桢不足时返回 ENOMEM，已分配的 tid 随之回收
+    pub fn new(process: Arc<PCB>, alloc_user_res: bool) -> SysResult<Self> {
+        let tid = process.acquire_inner_lock().alloc_tid();
+        let mut task_user_res = Self {
+            tid,
+            process: Arc::downgrade(&process),
+            ustack_size: 0,
+        };
+        if alloc_user_res {
+            task_user_res.alloc_user_res()?;
+        }
+        Ok(task_user_res)
+    }
+
+    /// 在所属进程的地址空间中映射本线程的用户栈（延迟分配）和 TrapContext，页桢不足时返回 ENOMEM
+    pub fn alloc_user_res(&mut self) -> SysResult<()> {
+        let process = self.process.upgrade().unwrap();
+        let mut process_inner = process.acquire_inner_lock();
+        let ustack_size = process_inner.rlimits.stack_size();
+        self.map_user_res(&mut process_inner.memory_set, ustack_size)
+    }
+
+    /// 在 memory_set 中映射大小为 ustack_size 的用户栈和 TrapContext。
+    /// 页桢不足时返回 ENOMEM，memory_set 与 ustack_size 保持不变
+    pub fn map_user_res(
+        &mut self,
+        memory_set: &mut MemorySet,
+        ustack_size: usize,
+    ) -> SysResult<()> {
+        // 只有 TrapContext 需要立即分配页桢，先映射它，失败时无需回退
+        let trap_cx_bottom = trap_cx_bottom_from_tid(self.tid);
+        memory_set.insert_framed_area(
+            trap_cx_bottom.into(),
+            (trap_cx_bottom + PAGE_SIZE).into(),
+            MapPermission::R | MapPermission::W,
+        )?;
+        let ustack_top = self.ustack_top();
+        memory_set.insert_lazy_area(
+            (ustack_top - ustack_size).into(),
+            ustack_top.into(),
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        );
+        self.ustack_size = ustack_size;
+        Ok(())
+    }
+
+    /// 取消用户栈和 TrapContext 的映射。已经取消过时不做任何事
+    pub fn dealloc_user_res(&self) {
+        if let Some(process) = self.process.upgrade() {
+            self.dealloc_user_res_in(&mut process.acquire_inner_lock().memory_set);
+        }
+    }
+
+    /// 同 dealloc_user_res，用于调用方已持有进程锁的情况，memory_set 为所属进程的地址空间
+    pub fn dealloc_user_res_in(&self, memory_set: &mut MemorySet) {
+        let ustack_bottom_va: VirtAddr = self.ustack_bottom().into();
+        memory_set.remove_area_with_start_vpn(ustack_bottom_va.into());
+        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(self.tid).into();
+        memory_set.remove_area_with_start_vpn(trap_cx_bottom_va.into());
+    }
+
+    /// TrapContext 在用户地址空间中的虚拟地址
+    pub fn trap_cx_user_va(&self) -> usize {
+        trap_cx_bottom_from_tid(self.tid)
+    }
+
+    /// TrapContext 所在的物理页，内核通过它访问线程的 TrapContext
+    pub fn trap_cx_ppn(&self) -> PhysPageNum {
+        let process = self.process.upgrade().unwrap();
+        let process_inner = process.acquire_inner_lock();
+        let trap_cx_bottom_va: VirtAddr = self.trap_cx_user_va().into();
+        process_inner
+            .memory_set
+            .translate(trap_cx_bottom_va.into())
+            .unwrap()
+            .ppn()
     }
-}
\ No newline at end of file
+
+    /// 用户栈顶
+    pub fn ustack_top(&self) -> usize {
+        ustack_top_from_tid(self.tid)
+    }
+
+    /// 用户栈底
+    fn ustack_bottom(&self) -> usize {
+        self.ustack_top() - self.ustack_size
+    }
+}
+
+impl Drop for TaskUserRes {
+    fn drop(&mut self) {
+        // 进程已被回收时，其地址空间和 tid 分配器也一并释放了
+        if let Some(process) = self.process.upgrade() {
+            self.dealloc_user_res();
+            process.acquire_inner_lock().dealloc_tid(self.tid);
+        }
+    }
+}
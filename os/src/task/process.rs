@@ -0,0 +1,472 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use spin::{Mutex, MutexGuard};
+
+use crate::fs::{File, Stdin, Stdout};
+use crate::mm::{copy_bytes_to_user, copy_to_user, SlabBox, VirtAddr};
+use crate::error::{SysError, SysResult};
+use crate::sync::{Condvar, DeadlockDetector, Mutex as UserMutex, Semaphore};
+use crate::{
+    config::PAGE_SIZE,
+    mm::{MemorySet, KERNEL_SPACE},
+    timer::get_time,
+    trap::{trap_handler, TrapContext},
+};
+
+use super::{
+    manager::insert_into_pid2process,
+    pid::{pid_alloc, PidHandle, RecycleAllocator},
+    rlimit::{ResourceLimits, RLIMIT_AS, RLIMIT_NOFILE},
+    signal::SignalActions,
+    task::{TaskStatus, TCB},
+    usage::CpuUsage,
+    WaitQueue,
+};
+
+pub struct PCBInner {
+    /// 进程是否已退出（主线程调用 exit），但还没有被父进程回收
+    pub is_zombie: bool,
+    /// 进程的地址空间，由所有线程共享
+    pub memory_set: MemorySet,
+    /// 父进程的 PCB 结构
+    pub parent: Option<Weak<PCB>>,
+    /// 子进程列表的 PCB 结构
+    pub children: Vec<Arc<PCB>>,
+    /// 退出码，即主线程的退出码
+    pub exit_code: i32,
+    /// 进程组 id，等于组长进程的 pid
+    pub pgid: usize,
+    /// 会话 id，等于会话首进程的 pid
+    pub sid: usize,
+    /// 进程因信号 signum 暂停且还没有被父进程通过 waitpid(WUNTRACED) 获知时为 Some(signum)
+    pub stop_signal: Option<usize>,
+    /// 已退出线程的资源使用之和
+    pub exited_usage: CpuUsage,
+    /// 已回收的子进程（含它们回收的后代）的资源使用之和
+    pub children_usage: CpuUsage,
+    /// 在 waitpid 中等待子进程退出的线程
+    pub wait_queue: WaitQueue,
+
+    // 资源相关
+    /// 资源限制
+    pub rlimits: ResourceLimits,
+    /// 文件描述符表，进程打开的文件的描述符列表。
+    //
+    // Vec：表为动态长度，即固定文件数限制
+    // Option: 可利用 None 标志文件描述符是否在使用
+    // Arc: 提供并发共享能力，可被多线程同时使用；内容放在堆上，可不在编译期确定大小
+    // dyn: 表示运行时多态，即在运行时才知道是什么类型
+    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+
+    /// 进程内的线程，下标即 tid。线程退出后仍保留在这里，直到被 waittid 回收
+    pub tasks: Vec<Option<Arc<TCB>>>,
+    /// 进程内的 tid 分配器
+    pub task_res_allocator: RecycleAllocator,
+    /// 信号处理动作表
+    pub signal_actions: SignalActions,
+
+    // 同步原语，下标即用户程序使用的 id
+    /// 互斥锁表
+    pub mutex_list: Vec<Option<Arc<dyn UserMutex>>>,
+    /// 信号量表
+    pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
+    /// 条件变量表
+    pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    /// 是否在获取互斥锁、信号量时进行死锁检测
+    pub deadlock_detect: bool,
+    /// 互斥锁的分配情况，不开启检测时也会记录
+    pub mutex_detector: DeadlockDetector,
+    /// 信号量的分配情况
+    pub semaphore_detector: DeadlockDetector,
+}
+
+impl PCBInner {
+    /// 获取地址空间的 token
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+
+    /// 在当前进程文件描述符表中分配一个空闲的文件描述符，描述符达到 RLIMIT_NOFILE 时返回 EMFILE
+    pub fn alloc_fd(&mut self) -> SysResult<usize> {
+        let limit = self.rlimits.cur(RLIMIT_NOFILE);
+        if let Some(fd) = (0..self.fd_table.len()).find(|&fd| self.fd_table[fd].is_none()) {
+            if fd < limit {
+                return Ok(fd);
+            }
+        } else if self.fd_table.len() < limit {
+            self.fd_table.push(None);
+            return Ok(self.fd_table.len() - 1);
+        }
+        Err(SysError::EMFILE)
+    }
+
+    /// 分配一个线程 tid
+    pub fn alloc_tid(&mut self) -> usize {
+        self.task_res_allocator.alloc()
+    }
+
+    /// 回收线程 tid
+    pub fn dealloc_tid(&mut self, tid: usize) {
+        self.task_res_allocator.dealloc(tid)
+    }
+
+    /// 尚未被回收的线程数，包含已退出但还没有被 waittid 的线程
+    pub fn thread_count(&self) -> usize {
+        self.tasks.iter().filter(|task| task.is_some()).count()
+    }
+
+    /// 获取 tid 对应的线程
+    pub fn get_task(&self, tid: usize) -> Arc<TCB> {
+        self.tasks[tid].as_ref().unwrap().clone()
+    }
+
+    /// 进程的资源使用，即所有线程之和。已退出线程的统计已经并入 exited_usage
+    pub fn usage(&self) -> CpuUsage {
+        let mut usage = self.exited_usage;
+        for task in self.tasks.iter().filter_map(|task| task.as_ref()) {
+            let task_inner = task.acquire_inner_lock();
+            if task_inner.task_status != TaskStatus::Exited {
+                usage.add(&task_inner.usage);
+            }
+        }
+        usage
+    }
+}
+
+/// 进程控制块，记录进程内所有线程共享的资源：地址空间、文件描述符表、父子关系等。
+/// 线程的执行状态记录在 TCB 中
+pub struct PCB {
+    // 不可变数据放外面
+    pub pid: PidHandle,
+    /// 可变数据，从 pcb 对象缓存中分配
+    inner: SlabBox<Mutex<PCBInner>>,
+}
+
+impl PCB {
+    /// 获取内部可变数据。
+    pub fn acquire_inner_lock(&self) -> MutexGuard<PCBInner> {
+        self.inner.lock()
+    }
+
+    /// 尝试获取内部可变数据，已被锁住时返回 None
+    pub fn try_acquire_inner_lock(&self) -> Option<MutexGuard<PCBInner>> {
+        self.inner.try_lock()
+    }
+
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+
+    /// 获取 elf_data(应用镜像入口) 指针，返回新建的进程控制块。
+    /// 新进程只有一个主线程（tid 为 0），需要调用方将其加入任务管理器。
+    /// elf_data 不是合法的 elf 文件时返回 ENOEXEC
+    pub fn new(elf_data: &[u8]) -> SysResult<Arc<Self>> {
+        // memory_set with elf program headers/trampoline
+        let (memory_set, entry_point) = MemorySet::from_elf(elf_data)?;
+        let pid = pid_alloc();
+        // 第一个进程自成一个会话与进程组
+        let pid_value = pid.0;
+        let process = Arc::new(Self {
+            pid,
+            inner: SlabBox::new(Mutex::new(PCBInner {
+                is_zombie: false,
+                memory_set,
+                parent: None,
+                children: Vec::new(),
+                exit_code: 0,
+                pgid: pid_value,
+                sid: pid_value,
+                stop_signal: None,
+                exited_usage: CpuUsage::default(),
+                children_usage: CpuUsage::default(),
+                wait_queue: WaitQueue::new(),
+                rlimits: ResourceLimits::default(),
+                fd_table: vec![
+                    // 标准输入 0
+                    Some(Arc::new(Stdin)),
+                    // 标准输出 1
+                    Some(Arc::new(Stdout)),
+                    // 错误输出 2
+                    Some(Arc::new(Stdout)),
+                ],
+                tasks: Vec::new(),
+                task_res_allocator: RecycleAllocator::new(),
+                signal_actions: SignalActions::default(),
+                mutex_list: Vec::new(),
+                semaphore_list: Vec::new(),
+                condvar_list: Vec::new(),
+                deadlock_detect: false,
+                mutex_detector: DeadlockDetector::new(),
+                semaphore_detector: DeadlockDetector::new(),
+            })),
+        });
+        // 创建主线程，并分配其用户栈与 TrapContext
+        let task = Arc::new(TCB::new(Arc::clone(&process), true)?);
+        let task_inner = task.acquire_inner_lock();
+        let trap_cx = task_inner.get_trap_cx();
+        let ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
+        drop(task_inner);
+        // 初始化用户空间的 TrapContext
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            ustack_top,
+            KERNEL_SPACE.lock().token(),
+            task.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        process.acquire_inner_lock().tasks.push(Some(task));
+        insert_into_pid2process(process.getpid(), Arc::clone(&process));
+        Ok(process)
+    }
+
+    /// 加载一个 elf 到当前进程，只允许在进程只剩下一个线程时调用。
+    /// 新的地址空间中只为当前线程重新分配用户栈和 TrapContext，tid 保持不变。
+    /// 参数 args 与环境变量 envs 按 System V ABI 的布局放在用户栈上（见 push_exec_info）。
+    /// elf_data 不是合法的 elf 文件时返回 ENOEXEC，参数放不进用户栈时返回 E2BIG，
+    /// 新的地址空间超过 RLIMIT_AS 或页桢不足时返回 ENOMEM，当前进程保持不变
+    pub fn exec(
+        self: &Arc<Self>,
+        elf_data: &[u8],
+        args: Vec<String>,
+        envs: Vec<String>,
+    ) -> SysResult<()> {
+        let inner = self.acquire_inner_lock();
+        let stack_size = inner.rlimits.stack_size();
+        let as_limit = inner.rlimits.cur(RLIMIT_AS);
+        let task = inner.tasks.iter().find_map(|task| task.clone()).unwrap();
+        drop(inner);
+        let info_size = exec_info_size(&args, &envs);
+        if info_size > stack_size {
+            return Err(SysError::E2BIG);
+        }
+        let (mut memory_set, entry_point) = MemorySet::from_elf(elf_data)?;
+        // 新的地址空间中还要放主线程的用户栈和 TrapContext
+        if memory_set.mapped_size() + stack_size + PAGE_SIZE > as_limit {
+            return Err(SysError::ENOMEM);
+        }
+        // 在新的地址空间中重新分配当前线程的用户资源，失败时原地址空间保持不变
+        let mut task_inner = task.acquire_inner_lock();
+        task_inner
+            .res
+            .as_mut()
+            .unwrap()
+            .map_user_res(&mut memory_set, stack_size)?;
+        let mut inner = self.acquire_inner_lock();
+        assert_eq!(inner.thread_count(), 1);
+        inner.memory_set = memory_set;
+        // 原来的信号处理函数已不存在，恢复为默认动作
+        inner.signal_actions = SignalActions::default();
+        // 新程序不会使用原来的同步原语
+        inner.mutex_list.clear();
+        inner.semaphore_list.clear();
+        inner.condvar_list.clear();
+        inner.deadlock_detect = false;
+        inner.mutex_detector = DeadlockDetector::new();
+        inner.semaphore_detector = DeadlockDetector::new();
+        drop(inner);
+
+        task_inner.signal_frame = 0;
+        task_inner.trap_cx_ppn = task_inner.res.as_ref().unwrap().trap_cx_ppn();
+        let ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
+
+        let mut inner = self.acquire_inner_lock();
+        // 用户栈是延迟分配的，内核写入参数前需要先分配参数所在的页面，
+        // 并在写入完成前持有这些页桢，防止它们被换出
+        let _frames = inner
+            .memory_set
+            .fault_in_range(VirtAddr::from(ustack_top - info_size), info_size, true)
+            .unwrap();
+        let token = inner.get_user_token();
+        drop(inner);
+        let info = push_exec_info(token, ustack_top, entry_point, &args, &envs);
+
+        let mut trap_cx = TrapContext::app_init_context(
+            entry_point,
+            info.user_sp,
+            KERNEL_SPACE.lock().token(),
+            task.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        // 除了栈上的 argc，还通过 a0~a2 传递 argc、argv、envp，方便用户库直接使用
+        trap_cx.x[10] = args.len();
+        trap_cx.x[11] = info.argv_base;
+        trap_cx.x[12] = info.envp_base;
+        *task_inner.get_trap_cx() = trap_cx;
+        Ok(())
+    }
+
+    /// 从当前进程 fork 一个新进程，只允许在进程只剩下一个线程时调用。
+    /// 经过 fork 后，相同的有：
+    /// 1. 所有虚拟逻辑段地址
+    /// 不同的：
+    /// 1. pid
+    /// 2. 子进程唯一的线程拥有自己的内核栈及 taskContext（放在 KernelStack 栈顶）
+    /// 3. trapContext 所在的 ppn
+    /// 用户数据页以写时复制的方式与父进程共享，直到某一方写入时才真正分配新页。
+    /// 子进程的线程需要调用方加入任务管理器。页桢不足时返回 ENOMEM，父进程保持不变
+    pub fn fork(self: &Arc<Self>) -> SysResult<Arc<Self>> {
+        let mut parent_inner = self.acquire_inner_lock();
+        assert_eq!(parent_inner.thread_count(), 1);
+        let memory_set = MemorySet::from_existed_user(&mut parent_inner.memory_set)?;
+        let pid = pid_alloc();
+        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
+        for fd in parent_inner.fd_table.iter() {
+            if let Some(file) = fd {
+                new_fd_table.push(Some(file.clone()));
+            } else {
+                new_fd_table.push(None);
+            }
+        }
+        let child = Arc::new(Self {
+            pid,
+            inner: SlabBox::new(Mutex::new(PCBInner {
+                is_zombie: false,
+                memory_set,
+                parent: Some(Arc::downgrade(self)),
+                children: Vec::new(),
+                exit_code: 0,
+                // 子进程与父进程属于同一进程组与会话
+                pgid: parent_inner.pgid,
+                sid: parent_inner.sid,
+                stop_signal: None,
+                exited_usage: CpuUsage::default(),
+                children_usage: CpuUsage::default(),
+                wait_queue: WaitQueue::new(),
+                rlimits: parent_inner.rlimits.inherit(),
+                fd_table: new_fd_table,
+                tasks: Vec::new(),
+                task_res_allocator: RecycleAllocator::new(),
+                signal_actions: parent_inner.signal_actions.clone(),
+                // 同步原语用于进程内线程间的同步，不与子进程共享
+                mutex_list: Vec::new(),
+                semaphore_list: Vec::new(),
+                condvar_list: Vec::new(),
+                deadlock_detect: false,
+                mutex_detector: DeadlockDetector::new(),
+                semaphore_detector: DeadlockDetector::new(),
+            })),
+        });
+        let parent_task = parent_inner.tasks.iter().find_map(|task| task.clone()).unwrap();
+        drop(parent_inner);
+        // 子进程的线程沿用复制来的用户栈与 TrapContext，只需要新的内核栈。
+        // 失败时子进程还未挂到父进程下，随 child 一起释放
+        let task = Arc::new(TCB::new(Arc::clone(&child), false)?);
+        self.acquire_inner_lock().children.push(child.clone());
+        let mut task_inner = task.acquire_inner_lock();
+        // 继承信号屏蔽字、信号栈帧（fork 可能在信号处理函数中调用）及优先级，待处理的信号不继承
+        let parent_task_inner = parent_task.acquire_inner_lock();
+        task_inner.signal_mask = parent_task_inner.signal_mask;
+        task_inner.signal_frame = parent_task_inner.signal_frame;
+        task_inner.priority = parent_task_inner.priority;
+        // 复制来的用户栈与父进程线程的大小相同
+        task_inner.res.as_mut().unwrap().ustack_size =
+            parent_task_inner.res.as_ref().unwrap().ustack_size;
+        drop(parent_task_inner);
+        let trap_cx = task_inner.get_trap_cx();
+        trap_cx.kernel_sp = task.kernel_stack.get_top();
+        drop(task_inner);
+        child.acquire_inner_lock().tasks.push(Some(task));
+        insert_into_pid2process(child.getpid(), Arc::clone(&child));
+        Ok(child)
+    }
+}
+
+/// 辅助向量（auxv）的类型，取值与 Linux 一致
+const AT_NULL: usize = 0;
+const AT_PAGESZ: usize = 6;
+const AT_ENTRY: usize = 9;
+const AT_RANDOM: usize = 25;
+/// 辅助向量的项数（含结束标志 AT_NULL），每项为 (类型, 值) 两个 usize
+const AUXV_LEN: usize = 4;
+/// AT_RANDOM 指向的随机字节数
+const RANDOM_BYTES: usize = 16;
+
+/// exec 放到用户栈上的信息在栈上的位置
+struct ExecInfo {
+    /// 新程序的初始栈顶，指向 argc
+    user_sp: usize,
+    /// argv 指针数组的地址
+    argv_base: usize,
+    /// envp 指针数组的地址
+    envp_base: usize,
+}
+
+/// exec 放到用户栈上的信息占用的最大字节数，含对齐时的填充
+fn exec_info_size(args: &[String], envs: &[String]) -> usize {
+    let usize_len = core::mem::size_of::<usize>();
+    let strings_size: usize = args.iter().chain(envs).map(|s| s.len() + 1).sum();
+    // argc、argv 及其结束的 0、envp 及其结束的 0、auxv
+    let table_size = (1 + args.len() + 1 + envs.len() + 1 + 2 * AUXV_LEN) * usize_len;
+    RANDOM_BYTES + strings_size + table_size + 15
+}
+
+/// 按 System V ABI 的布局将参数、环境变量与辅助向量放到 ustack_top 以下，从高地址到低地址依次为：
+/// AT_RANDOM 的随机字节、各个以 \0 结尾的字符串、（16 字节对齐）auxv、envp[]、argv[]、argc。
+/// 调用前需要保证这部分栈已经映射
+fn push_exec_info(
+    token: usize,
+    ustack_top: usize,
+    entry_point: usize,
+    args: &[String],
+    envs: &[String],
+) -> ExecInfo {
+    let usize_len = core::mem::size_of::<usize>();
+    let mut user_sp = ustack_top;
+    // 写入的区域已经映射，不会失败
+    user_sp -= RANDOM_BYTES;
+    let random_ptr = user_sp;
+    copy_bytes_to_user(token, random_ptr as *mut u8, &random_bytes()).unwrap();
+    let mut push_str = |s: &String| {
+        user_sp -= s.len() + 1;
+        copy_bytes_to_user(token, user_sp as *mut u8, s.as_bytes()).unwrap();
+        copy_bytes_to_user(token, (user_sp + s.len()) as *mut u8, &[0]).unwrap();
+        user_sp
+    };
+    let arg_ptrs: Vec<usize> = args.iter().map(&mut push_str).collect();
+    let env_ptrs: Vec<usize> = envs.iter().map(&mut push_str).collect();
+
+    let mut table = vec![args.len()];
+    table.extend(arg_ptrs);
+    table.push(0);
+    table.extend(env_ptrs);
+    table.push(0);
+    table.extend_from_slice(&[
+        AT_PAGESZ,
+        PAGE_SIZE,
+        AT_ENTRY,
+        entry_point,
+        AT_RANDOM,
+        random_ptr,
+        AT_NULL,
+        0,
+    ]);
+    user_sp -= table.len() * usize_len;
+    // RISC-V 要求栈指针按 16 字节对齐
+    user_sp -= user_sp % 16;
+    for (i, value) in table.iter().enumerate() {
+        copy_to_user(token, (user_sp + i * usize_len) as *mut usize, value).unwrap();
+    }
+    let argv_base = user_sp + usize_len;
+    ExecInfo {
+        user_sp,
+        argv_base,
+        envp_base: argv_base + (args.len() + 1) * usize_len,
+    }
+}
+
+/// 生成 AT_RANDOM 使用的随机字节。没有硬件随机数源，用时钟计数作为种子的 xorshift 生成
+fn random_bytes() -> [u8; RANDOM_BYTES] {
+    let mut state = get_time() as u64 | 1;
+    let mut bytes = [0u8; RANDOM_BYTES];
+    for chunk in bytes.chunks_mut(8) {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        chunk.copy_from_slice(&state.to_le_bytes()[..chunk.len()]);
+    }
+    bytes
+}
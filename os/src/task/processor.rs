@@ -1,13 +1,21 @@
 use core::cell::RefCell;
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
 
 use lazy_static::lazy_static;
 
-use crate::trap::TrapContext;
+use crate::{
+    config::MAX_HART_NUM,
+    fs::poll_console,
+    mm::flush_pending,
+    smp::hart_id,
+    timer::{check_timer, get_time},
+    trap::TrapContext,
+};
 
 use super::{
-    manager::fetch_task,
+    manager::{add_task, fetch_task},
+    process::PCB,
     switch::__switch,
     task::{TaskStatus, TCB},
 };
@@ -18,8 +26,8 @@ pub struct Processor {
     inner: RefCell<ProcessorInner>,
 }
 
-/// Processor 是每个核有一个，不管在多核还是单核模式下访问都不会有数据竞争问题，
-/// 所以可以标为 Sync
+/// Processor 是每个核有一个，各核只访问自己的 Processor，且内核态不响应中断，
+/// 所以不会有数据竞争问题，可以标为 Sync
 unsafe impl Sync for Processor {}
 
 struct ProcessorInner {
@@ -68,42 +76,70 @@ impl Processor {
                 let mut task_inner = task.acquire_inner_lock();
                 let next_task_cx_ptr2 = task_inner.get_task_cx_ptr2();
                 task_inner.task_status = TaskStatus::Running;
+                task_inner.on_cpu = true;
+                // 在就绪队列中等待的时间不计入 CPU 时间
+                task_inner.usage_timestamp = get_time();
                 // 手动释放互斥锁，不能等到编译器自己回收（会在函数结束后），临界区扩大可能造成死锁
                 drop(task_inner);
 
                 self.inner.borrow_mut().current = Some(task);
+                // 内核栈可能被其它核回收后重新分配，本核 TLB 中可能还有其旧的映射
+                flush_pending();
                 // 从 idle 控制流切换至目标任务
                 // 执行完 switch 后， self.idle_task_cx_ptr 的值是指向由 switch.S 从当前 run 的栈空间
                 // 分配到的 *TaskContext
                 unsafe { __switch(idle_task_cx_ptr2, next_task_cx_ptr2) }
+                // 回到 idle 控制流时，换出的任务的上下文已保存完毕，这时才能让其它核运行它。
+                // 如果在换出前就放回就绪队列，其它核可能在上下文保存完之前就切换过去。
+                // 阻塞的任务如果在换出过程中被唤醒，状态也已变为 Ready
+                if let Some(task) = self.take_current() {
+                    let mut task_inner = task.acquire_inner_lock();
+                    task_inner.on_cpu = false;
+                    task_inner.account_time(false);
+                    let ready = task_inner.task_status == TaskStatus::Ready;
+                    drop(task_inner);
+                    if ready {
+                        add_task(task);
+                    }
+                }
+            } else {
+                // 内核态不响应时钟中断，没有任务可运行时由 idle 控制流检查定时器，
+                // 否则所有任务都在睡眠时将无人唤醒它们。控制台输入同理
+                check_timer();
+                poll_console();
             }
         }
     }
 }
 
 lazy_static! {
-    /// 只实现了单核，所以只需要实例化一个单例
-    pub static ref PROCESSOR: Processor = Processor::new();
+    /// 每个核一个 Processor，下标为 hart 编号
+    pub static ref PROCESSORS: Vec<Processor> = (0..MAX_HART_NUM).map(|_| Processor::new()).collect();
 }
 
-pub fn run_tasks() {
-    PROCESSOR.run()
+/// 当前核的 Processor
+fn current_processor() -> &'static Processor {
+    &PROCESSORS[hart_id()]
 }
 
-/// 换出当前任务的 TCB
-pub fn take_current_task() -> Option<Arc<TCB>> {
-    PROCESSOR.take_current()
+pub fn run_tasks() {
+    current_processor().run()
 }
 
 /// 获取当前任务
 pub fn current_task() -> Option<Arc<TCB>> {
-    PROCESSOR.current()
+    current_processor().current()
+}
+
+/// 获取当前任务所属的进程
+pub fn current_process() -> Arc<PCB> {
+    current_task().unwrap().process.upgrade().unwrap()
 }
 
 /// 获取当前任务的用户空间 token(satp)
 pub fn current_user_token() -> usize {
-    let task = current_task().unwrap();
-    let token = task.acquire_inner_lock().get_user_token();
+    let process = current_process();
+    let token = process.acquire_inner_lock().get_user_token();
     token
 }
 
@@ -112,11 +148,23 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
     current_task().unwrap().acquire_inner_lock().get_trap_cx()
 }
 
-/// 切换至 idle 控制流并开启新一轮调试。
+/// 当前任务的 TrapContext 在用户地址空间中的虚拟地址
+pub fn current_trap_cx_user_va() -> usize {
+    current_task()
+        .unwrap()
+        .acquire_inner_lock()
+        .res
+        .as_ref()
+        .unwrap()
+        .trap_cx_user_va()
+}
+
+/// 切换至 idle 控制流并开启新一轮调度。当前任务仍留在 Processor 中，由 idle 控制流根据
+/// 其状态决定是否放回就绪队列。
 /// 这里实际上是继续运行 Processor.run 中 __switch 后的位置
 /// 执行后，*switched_task_cx_ptr2 = *TaskContext as usize(*TaskContext 是从此进程栈空间分配的)
 pub fn schedule(switched_task_cx_ptr2: *const usize) {
-    let idle_task_cx_ptr2 = PROCESSOR.get_idle_task_cx_ptr2();
+    let idle_task_cx_ptr2 = current_processor().get_idle_task_cx_ptr2();
     unsafe {
         __switch(switched_task_cx_ptr2, idle_task_cx_ptr2);
     }
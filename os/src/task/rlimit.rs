@@ -0,0 +1,138 @@
+//! 进程的资源限制（rlimit）。每种资源有软限制与硬限制，超过软限制时系统调用失败或收到信号；
+//! 软限制可以在硬限制以内任意调整，硬限制只能降低。fork 时子进程继承父进程的限制，exec 后保持不变
+
+use crate::config::{
+    CLOCK_FREQ, MAX_FD_NUM, MAX_PROCESS_NUM, MAX_USER_STACK_SIZE, PAGE_SIZE, USER_STACK_SIZE,
+};
+use crate::error::{SysError, SysResult};
+
+use super::{current_process, send_signal_to_process, signal::SignalFlags};
+
+// 资源编号与 Linux 一致，只有下面几种会被检查，其余的可以设置但不起作用
+
+/// 进程的 CPU 时间，单位为秒。超过软限制后每秒收到一次 SIGXCPU，达到硬限制时收到 SIGKILL
+pub const RLIMIT_CPU: usize = 0;
+/// 新线程与 exec 后主线程的用户栈大小，单位为字节
+pub const RLIMIT_STACK: usize = 3;
+/// 系统中尚未退出的进程数，达到后 fork 返回 EAGAIN
+pub const RLIMIT_NPROC: usize = 6;
+/// 文件描述符的上限，即可以分配的最大描述符加一，达到后返回 EMFILE
+pub const RLIMIT_NOFILE: usize = 7;
+/// 地址空间大小，单位为字节，超过后 brk 失败，mmap、exec、创建线程返回 ENOMEM
+pub const RLIMIT_AS: usize = 9;
+/// 资源种类数
+pub const RLIM_NLIMITS: usize = 16;
+/// 不限制
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// 一种资源的限制，内存布局与用户库一致
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct RLimit {
+    /// 软限制，即实际生效的限制
+    pub cur: usize,
+    /// 硬限制，即软限制的上限
+    pub max: usize,
+}
+
+impl RLimit {
+    const INFINITY: Self = Self {
+        cur: RLIM_INFINITY,
+        max: RLIM_INFINITY,
+    };
+}
+
+/// 进程的各项资源限制
+#[derive(Clone, Copy)]
+pub struct ResourceLimits {
+    limits: [RLimit; RLIM_NLIMITS],
+    /// 最近一次因超过 CPU 时间软限制发送 SIGXCPU 时的 CPU 秒数
+    cpu_warned: usize,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        let mut limits = [RLimit::INFINITY; RLIM_NLIMITS];
+        limits[RLIMIT_STACK] = RLimit {
+            cur: USER_STACK_SIZE,
+            max: MAX_USER_STACK_SIZE,
+        };
+        limits[RLIMIT_NPROC] = RLimit {
+            cur: MAX_PROCESS_NUM,
+            max: MAX_PROCESS_NUM,
+        };
+        limits[RLIMIT_NOFILE] = RLimit {
+            cur: MAX_FD_NUM,
+            max: MAX_FD_NUM,
+        };
+        Self {
+            limits,
+            cpu_warned: 0,
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// 获取资源 resource 的限制，resource 不合法时返回 EINVAL
+    pub fn get(&self, resource: usize) -> SysResult<RLimit> {
+        self.limits.get(resource).copied().ok_or(SysError::EINVAL)
+    }
+
+    /// 设置资源 resource 的限制。resource 不合法或软限制大于硬限制时返回 EINVAL，提高硬限制时返回 EPERM
+    pub fn set(&mut self, resource: usize, limit: RLimit) -> SysResult<()> {
+        let old = self.get(resource)?;
+        if limit.cur > limit.max {
+            return Err(SysError::EINVAL);
+        }
+        if limit.max > old.max {
+            return Err(SysError::EPERM);
+        }
+        self.limits[resource] = limit;
+        if resource == RLIMIT_CPU {
+            self.cpu_warned = 0;
+        }
+        Ok(())
+    }
+
+    /// fork 出的子进程继承的限制，子进程的 CPU 时间从零开始计算
+    pub fn inherit(&self) -> Self {
+        Self {
+            limits: self.limits,
+            cpu_warned: 0,
+        }
+    }
+
+    /// 资源 resource 的软限制
+    pub fn cur(&self, resource: usize) -> usize {
+        self.limits[resource].cur
+    }
+
+    /// 新分配的用户栈大小：按页向上取整，至少一页，且不超过为每个线程预留的栈空间
+    pub fn stack_size(&self) -> usize {
+        let size = self.cur(RLIMIT_STACK).min(MAX_USER_STACK_SIZE);
+        ((size + PAGE_SIZE - 1) / PAGE_SIZE).max(1) * PAGE_SIZE
+    }
+}
+
+/// 检查当前进程的 CPU 时间是否超过限制，在时钟中断中调用，调用方不能持有任何锁。
+/// 达到硬限制时发送 SIGKILL；超过软限制后，CPU 时间每增加一秒发送一次 SIGXCPU
+pub fn check_cpu_limit() {
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    let limit = inner.rlimits.limits[RLIMIT_CPU];
+    if limit.cur == RLIM_INFINITY {
+        return;
+    }
+    let usage = inner.usage();
+    let seconds = (usage.utime + usage.stime) / CLOCK_FREQ;
+    let signal = if seconds >= limit.max {
+        SignalFlags::SIGKILL
+    } else if seconds >= limit.cur && seconds > inner.rlimits.cpu_warned {
+        inner.rlimits.cpu_warned = seconds;
+        SignalFlags::SIGXCPU
+    } else {
+        return;
+    };
+    drop(inner);
+    let _ = send_signal_to_process(&process, signal);
+}
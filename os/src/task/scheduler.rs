@@ -0,0 +1,84 @@
+use alloc::{collections::VecDeque, sync::Arc};
+
+use super::task::TCB;
+
+/// 调度器，管理就绪任务并决定下一个运行的任务。
+/// 具体使用哪种调度算法由 cargo feature 决定，见 manager.rs
+pub trait Scheduler {
+    fn new() -> Self;
+    /// 添加可运行 TCB
+    fn add(&mut self, task: Arc<TCB>);
+    /// 选出下一个要运行的 TCB，并将其移出就绪队列
+    fn fetch(&mut self) -> Option<Arc<TCB>>;
+}
+
+/// 时间片轮转调度，按先进先出的顺序运行就绪任务，不考虑优先级
+#[cfg(not(feature = "sched_stride"))]
+pub struct RRScheduler {
+    /// 就绪队列
+    /// 使用 Arc 是为了减少对 TCB 结构的数据拷贝开销；在一些情况下会更方便
+    ready_queue: VecDeque<Arc<TCB>>,
+}
+
+#[cfg(not(feature = "sched_stride"))]
+impl Scheduler for RRScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+
+    fn add(&mut self, task: Arc<TCB>) {
+        self.ready_queue.push_back(task);
+    }
+
+    /// 从就绪列表中获取第一个 TCB
+    fn fetch(&mut self) -> Option<Arc<TCB>> {
+        self.ready_queue.pop_front()
+    }
+}
+
+/// stride 的基数，任务每运行一个时间片，其 stride 增加 BIG_STRIDE / priority
+#[cfg(feature = "sched_stride")]
+pub const BIG_STRIDE: u64 = 1 << 20;
+
+/// 步长调度：每次选出 stride 最小的任务运行，任务获得的 CPU 时间与优先级成正比
+#[cfg(feature = "sched_stride")]
+pub struct StrideScheduler {
+    /// 就绪队列，任务数不多，直接线性查找 stride 最小的任务
+    ready_queue: VecDeque<Arc<TCB>>,
+    /// 最近一次被选中任务的 stride。
+    /// 新建或长时间未就绪的任务加入时 stride 至少从这里开始，避免其长期独占 CPU
+    min_stride: u64,
+}
+
+#[cfg(feature = "sched_stride")]
+impl Scheduler for StrideScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+            min_stride: 0,
+        }
+    }
+
+    fn add(&mut self, task: Arc<TCB>) {
+        let mut task_inner = task.acquire_inner_lock();
+        task_inner.stride = task_inner.stride.max(self.min_stride);
+        drop(task_inner);
+        self.ready_queue.push_back(task);
+    }
+
+    fn fetch(&mut self) -> Option<Arc<TCB>> {
+        let (idx, _) = self
+            .ready_queue
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, task)| task.acquire_inner_lock().stride)?;
+        let task = self.ready_queue.remove(idx).unwrap();
+        let mut task_inner = task.acquire_inner_lock();
+        self.min_stride = task_inner.stride;
+        task_inner.stride += BIG_STRIDE / task_inner.priority as u64;
+        drop(task_inner);
+        Some(task)
+    }
+}
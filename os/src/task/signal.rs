@@ -0,0 +1,351 @@
+use bitflags::*;
+
+use crate::{
+    error::{SysError, SysResult},
+    trap::TrapContext,
+};
+
+use super::{
+    current_process, current_task, exit_current_and_run_next, pgid2processes,
+    suspend_current_and_run_next, wakeup_task, PCB,
+};
+
+/// 支持的最大信号编号，信号编号从 1 开始
+pub const MAX_SIG: usize = 31;
+
+/// 默认处理方式
+pub const SIG_DFL: usize = 0;
+/// 忽略信号
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// 信号集合，第 n 位表示编号为 n 的信号，编号与 Linux 一致
+    pub struct SignalFlags: u32 {
+        const SIGHUP = 1 << 1;
+        const SIGINT = 1 << 2;
+        const SIGQUIT = 1 << 3;
+        const SIGILL = 1 << 4;
+        const SIGTRAP = 1 << 5;
+        const SIGABRT = 1 << 6;
+        const SIGBUS = 1 << 7;
+        const SIGFPE = 1 << 8;
+        /// 不能被捕获、屏蔽或忽略
+        const SIGKILL = 1 << 9;
+        const SIGUSR1 = 1 << 10;
+        const SIGSEGV = 1 << 11;
+        const SIGUSR2 = 1 << 12;
+        const SIGPIPE = 1 << 13;
+        const SIGALRM = 1 << 14;
+        const SIGTERM = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD = 1 << 17;
+        const SIGCONT = 1 << 18;
+        /// 不能被捕获、屏蔽或忽略
+        const SIGSTOP = 1 << 19;
+        const SIGTSTP = 1 << 20;
+        const SIGTTIN = 1 << 21;
+        const SIGTTOU = 1 << 22;
+        const SIGURG = 1 << 23;
+        const SIGXCPU = 1 << 24;
+        const SIGXFSZ = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF = 1 << 27;
+        const SIGWINCH = 1 << 28;
+        const SIGIO = 1 << 29;
+        const SIGPWR = 1 << 30;
+        const SIGSYS = 1 << 31;
+    }
+}
+
+impl SignalFlags {
+    /// 编号为 signum 的信号，编号不合法时返回 None
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if signum == 0 || signum > MAX_SIG {
+            None
+        } else {
+            Self::from_bits(1 << signum)
+        }
+    }
+
+    /// 信号编号，只对单个信号有意义
+    pub fn signum(&self) -> usize {
+        self.bits().trailing_zeros() as usize
+    }
+
+    /// 默认动作为忽略的信号
+    fn ignored_by_default(&self) -> bool {
+        (Self::SIGCHLD | Self::SIGURG | Self::SIGWINCH | Self::SIGCONT).contains(*self)
+    }
+
+    /// 默认动作为暂停的信号
+    fn stops_by_default(&self) -> bool {
+        (Self::SIGSTOP | Self::SIGTSTP | Self::SIGTTIN | Self::SIGTTOU).contains(*self)
+    }
+}
+
+/// 信号处理动作，内存布局与用户库一致
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalAction {
+    /// 处理函数地址，也可以是 SIG_DFL 或 SIG_IGN
+    pub handler: usize,
+    /// 处理信号期间额外屏蔽的信号
+    pub mask: SignalFlags,
+    /// 处理函数返回后跳转的地址，一般是用户库中调用 sigreturn 的函数。
+    /// 为 0 时处理函数需要自己调用 sigreturn
+    pub restorer: usize,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+            restorer: 0,
+        }
+    }
+}
+
+/// 进程的信号处理动作表，下标为信号编号，由进程内所有线程共享
+#[derive(Clone)]
+pub struct SignalActions {
+    pub table: [SignalAction; MAX_SIG + 1],
+}
+
+impl Default for SignalActions {
+    fn default() -> Self {
+        Self {
+            table: [SignalAction::default(); MAX_SIG + 1],
+        }
+    }
+}
+
+/// 进入信号处理函数前压在用户栈上的栈帧，sigreturn 时据此恢复被打断的执行流。
+/// 只保存用户可以修改的寄存器，sstatus 等由内核维护的状态不能交给用户
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SignalFrame {
+    /// 被打断时的通用寄存器
+    x: [usize; 32],
+    /// 被打断时的 sepc
+    sepc: usize,
+    /// 进入处理函数前的信号屏蔽字
+    mask: SignalFlags,
+    /// 上一个信号栈帧的地址，信号嵌套时使用
+    prev: usize,
+}
+
+/// 将 frame 写到用户地址 addr 处，地址不可写时返回 false
+fn push_signal_frame(addr: usize, frame: &SignalFrame) -> bool {
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    inner
+        .memory_set
+        .copy_out(addr as *mut SignalFrame, frame)
+        .is_ok()
+}
+
+/// 读取用户地址 addr 处的信号栈帧，地址不可读时返回 None
+fn pop_signal_frame(addr: usize) -> Option<SignalFrame> {
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    let mut frame = inner
+        .memory_set
+        .copy_in(addr as *const SignalFrame)
+        .ok()?;
+    // 屏蔽字来自用户内存，不能借此屏蔽 SIGKILL、SIGSTOP
+    frame.mask = SignalFlags::from_bits_truncate(frame.mask.bits())
+        - (SignalFlags::SIGKILL | SignalFlags::SIGSTOP);
+    Some(frame)
+}
+
+/// 向进程发送信号。信号记在主线程上，由主线程返回用户态时处理。进程已退出时返回 ESRCH
+pub fn send_signal_to_process(process: &PCB, signal: SignalFlags) -> SysResult<()> {
+    let mut inner = process.acquire_inner_lock();
+    if inner.is_zombie {
+        return Err(SysError::ESRCH);
+    }
+    let task = inner.get_task(0);
+    // SIGCONT 即使被屏蔽也会让暂停的进程继续运行
+    if signal == SignalFlags::SIGCONT {
+        inner.stop_signal = None;
+    }
+    drop(inner);
+    let mut task_inner = task.acquire_inner_lock();
+    task_inner.signals |= signal;
+    if signal == SignalFlags::SIGCONT {
+        task_inner.frozen = false;
+    }
+    drop(task_inner);
+    // 打断阻塞中的系统调用，被屏蔽的信号会让线程重新进入阻塞
+    wakeup_task(task);
+    Ok(())
+}
+
+/// 向进程组 pgid 中的所有进程发送信号，进程组不存在时返回 ESRCH
+pub fn send_signal_to_group(pgid: usize, signal: SignalFlags) -> SysResult<()> {
+    let processes = pgid2processes(pgid);
+    if processes.is_empty() {
+        return Err(SysError::ESRCH);
+    }
+    for process in processes {
+        // 进程可能刚刚退出，忽略即可
+        let _ = send_signal_to_process(&process, signal);
+    }
+    Ok(())
+}
+
+/// 向当前线程发送由异常引起的同步信号（SIGSEGV、SIGILL 等）。
+/// 这类信号被屏蔽或忽略时，重新执行出错的指令只会再次陷入，所以此时直接按默认动作终止进程
+pub fn send_fault_signal(signal: SignalFlags) {
+    let signum = signal.signum();
+    let process = current_process();
+    let mut process_inner = process.acquire_inner_lock();
+    let task = current_task().unwrap();
+    let mut task_inner = task.acquire_inner_lock();
+    if task_inner.signal_mask.contains(signal)
+        || process_inner.signal_actions.table[signum].handler == SIG_IGN
+    {
+        task_inner.signal_mask.remove(signal);
+        process_inner.signal_actions.table[signum] = SignalAction::default();
+    }
+    task_inner.signals |= signal;
+}
+
+/// 进程因信号 signum 暂停，唤醒在 waitpid 中等待的父进程，供 WUNTRACED 获知
+fn report_stop(process: &PCB, signum: usize) {
+    let mut inner = process.acquire_inner_lock();
+    inner.stop_signal = Some(signum);
+    let parent = inner.parent.as_ref().and_then(|parent| parent.upgrade());
+    drop(inner);
+    if let Some(parent) = parent {
+        parent.acquire_inner_lock().wait_queue.wake_all();
+    }
+}
+
+/// 以信号 signum 的默认动作终止当前进程，退出码为 -signum。
+/// 非主线程先终止自己，再让主线程以 SIGKILL 结束整个进程
+fn terminate_by_signal(signum: usize) -> ! {
+    let process = current_process();
+    let task = current_task().unwrap();
+    let tid = task.acquire_inner_lock().res.as_ref().unwrap().tid;
+    drop(task);
+    if tid != 0 {
+        let _ = send_signal_to_process(&process, SignalFlags::SIGKILL);
+    }
+    drop(process);
+    exit_current_and_run_next(-(signum as i32));
+    panic!("never here");
+}
+
+/// 处理当前线程的一个待处理信号，返回 false 表示没有可处理的信号
+fn handle_one_signal() -> bool {
+    let process = current_process();
+    // 先进程锁后线程锁
+    let process_inner = process.acquire_inner_lock();
+    let task = current_task().unwrap();
+    let mut task_inner = task.acquire_inner_lock();
+    let deliverable = task_inner.signals - task_inner.signal_mask;
+    let signum = match (1..=MAX_SIG).find(|signum| {
+        deliverable.contains(SignalFlags::from_signum(*signum).unwrap())
+    }) {
+        Some(signum) => signum,
+        None => return false,
+    };
+    let action = process_inner.signal_actions.table[signum];
+    drop(process_inner);
+    let signal = SignalFlags::from_signum(signum).unwrap();
+    task_inner.signals.remove(signal);
+    if signal == SignalFlags::SIGKILL {
+        drop(task_inner);
+        drop(task);
+        drop(process);
+        terminate_by_signal(signum);
+    }
+    if signal == SignalFlags::SIGSTOP {
+        task_inner.frozen = true;
+        drop(task_inner);
+        report_stop(&process, signum);
+        return true;
+    }
+    drop(process);
+    match action.handler {
+        SIG_IGN => {}
+        SIG_DFL => {
+            if signal.stops_by_default() {
+                task_inner.frozen = true;
+                drop(task_inner);
+                report_stop(&current_process(), signum);
+            } else if !signal.ignored_by_default() {
+                drop(task_inner);
+                drop(task);
+                terminate_by_signal(signum);
+            }
+        }
+        handler => {
+            // 在用户栈上构造信号栈帧，然后让线程返回用户态时从处理函数开始执行
+            let trap_cx: &mut TrapContext = task_inner.get_trap_cx();
+            let frame = SignalFrame {
+                x: trap_cx.x,
+                sepc: trap_cx.sepc,
+                mask: task_inner.signal_mask,
+                prev: task_inner.signal_frame,
+            };
+            let frame_addr = trap_cx.x[2].wrapping_sub(core::mem::size_of::<SignalFrame>()) & !0xf;
+            drop(task_inner);
+            if !push_signal_frame(frame_addr, &frame) {
+                // 用户栈已不可用，无法处理信号
+                drop(task);
+                terminate_by_signal(SignalFlags::SIGSEGV.signum());
+            }
+            let mut task_inner = task.acquire_inner_lock();
+            task_inner.signal_frame = frame_addr;
+            task_inner.signal_mask |= action.mask | signal;
+            trap_cx.x[2] = frame_addr;
+            trap_cx.x[10] = signum;
+            trap_cx.x[1] = action.restorer;
+            trap_cx.sepc = handler;
+        }
+    }
+    true
+}
+
+/// 在返回用户态前处理当前线程的待处理信号。
+/// 被暂停的线程在这里让出 CPU，直到收到 SIGCONT 或 SIGKILL
+pub fn handle_signals() {
+    loop {
+        while handle_one_signal() {}
+        let task = current_task().unwrap();
+        let frozen = task.acquire_inner_lock().frozen;
+        drop(task);
+        if !frozen {
+            break;
+        }
+        suspend_current_and_run_next();
+    }
+}
+
+/// 从信号处理函数返回：恢复最近一个信号栈帧中保存的上下文与屏蔽字。
+/// 返回被打断时的 a0，由于系统调用返回值会写入 a0，这样可以保持 a0 不变。
+/// 不在信号处理过程中时返回 EINVAL
+pub fn signal_return() -> SysResult {
+    let task = current_task().unwrap();
+    let frame_addr = task.acquire_inner_lock().signal_frame;
+    if frame_addr == 0 {
+        return Err(SysError::EINVAL);
+    }
+    let frame = match pop_signal_frame(frame_addr) {
+        Some(frame) => frame,
+        None => {
+            drop(task);
+            terminate_by_signal(SignalFlags::SIGSEGV.signum());
+        }
+    };
+    let mut task_inner = task.acquire_inner_lock();
+    task_inner.signal_frame = frame.prev;
+    task_inner.signal_mask = frame.mask;
+    let trap_cx = task_inner.get_trap_cx();
+    trap_cx.x = frame.x;
+    trap_cx.sepc = frame.sepc;
+    Ok(trap_cx.x[10])
+}
@@ -1,26 +1,24 @@
-use alloc::string::String;
-use alloc::vec;
-use alloc::{
-    sync::{Arc, Weak},
-    vec::Vec,
-};
-use riscv::paging::PTE;
+use alloc::sync::{Arc, Weak};
 use spin::{Mutex, MutexGuard};
 
-use crate::fs::{File, Stdin, Stdout};
-use crate::mm::translated_refmut;
 use crate::{
-    config::{kernel_stack_position, TRAP_CONTEXT},
-    mm::{MapPermission, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE},
-    task::pid::pid_alloc,
-    trap::{trap_handler, TrapContext},
+    error::SysResult,
+    mm::{PhysPageNum, SlabBox},
+    timer::get_time,
+    trap::TrapContext,
 };
 
 use super::{
-    pid::{KernelStack, PidHandle},
+    pid::{kstack_alloc, KernelStack, TaskUserRes},
+    process::PCB,
+    signal::SignalFlags,
+    usage::CpuUsage,
     TaskContext,
 };
 
+/// 线程的默认优先级
+pub const DEFAULT_PRIORITY: usize = 16;
+
 // #[derive(...)] 提供一些 trait 的默认实现
 // PartialEq 是实现 == 运算符重载的默认方式
 #[derive(Copy, Clone, PartialEq)]
@@ -31,41 +29,52 @@ pub enum TaskStatus {
     Ready,
     ///
     Running,
-    ///
+    /// 等待某个条件而阻塞，不在就绪队列中，由等待队列唤醒
+    Blocked,
+    /// 线程已退出，等待 waittid 回收
     Exited,
-    /// 进程退出（调用 exit），但系统没有回收所有资源，这时处于 zombie 状态
-    Zombie,
 }
 
 pub struct TCBInner {
-    /// TaskContext 的指针, 处于任务 KernelStack 的顶部，创建 TCB 时赋值。
-    /// 在 __switch 中被修改
-    pub task_cx_ptr: usize,
-    /// 任务状态
-    pub task_status: TaskStatus,
-    /// 任务的地址空间
-    pub memory_set: MemorySet,
-    /// 本任务的 TrapContext 所处的 PPN。
+    /// 线程在进程地址空间中的资源：tid、用户栈、TrapContext。
+    /// 用户栈与 TrapContext 在线程退出时回收，tid 在 TCB 释放时回收
+    pub res: Option<TaskUserRes>,
+    /// 本线程的 TrapContext 所处的 PPN。
     /// 内核无法通过虚拟地址访问到应用的页面，所以只能用 ppn 来得到数据
     /// ppn 能访问的原因是 trapContext 是使用的恒等映射。
     pub trap_cx_ppn: PhysPageNum,
-    /// 在应用地址空间中从  开始到用户栈结束一共包含多少字节，目前不含动态分配的数据
-    pub base_size: usize,
-    /// 父进程的 TCB 结构
-    pub parent: Option<Weak<TCB>>,
-    /// 子进程列表的 TCB 结构
-    pub children: Vec<Arc<TCB>>,
-    /// 退出码
-    pub exit_code: i32,
-
-    // 资源相关
-    /// 文件描述符表，进程打开的文件的描述符列表。
-    //
-    // Vec：表为动态长度，即固定文件数限制
-    // Option: 可利用 None 标志文件描述符是否在使用
-    // Arc: 提供并发共享能力，可被多线程同时使用；内容放在堆上，可不在编译期确定大小
-    // dyn: 表示运行时多态，即在运行时才知道是什么类型
-    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// TaskContext 的指针, 处于线程 KernelStack 的顶部，创建 TCB 时赋值。
+    /// 在 __switch 中被修改
+    pub task_cx_ptr: usize,
+    /// 线程状态
+    pub task_status: TaskStatus,
+    /// 是否正在某个核上运行（包括正在切换出去、上下文还没保存完的阶段）。
+    /// 此时被唤醒的线程由该核的 idle 控制流在切换完成后放回就绪队列
+    pub on_cpu: bool,
+    /// 线程退出码，线程退出前为 None
+    pub exit_code: Option<i32>,
+
+    // 信号相关
+    /// 待处理的信号
+    pub signals: SignalFlags,
+    /// 被屏蔽的信号，屏蔽期间信号保持待处理状态
+    pub signal_mask: SignalFlags,
+    /// 最近一个信号栈帧在用户栈上的地址，不在信号处理函数中时为 0
+    pub signal_frame: usize,
+    /// 是否被 SIGSTOP 等信号暂停，收到 SIGCONT 后恢复
+    pub frozen: bool,
+
+    // 调度相关
+    /// 优先级，不小于 2，越大获得的 CPU 时间越多。只对步长调度有效
+    pub priority: usize,
+    /// 步长调度中已累计的 stride，每被调度一次增加 BIG_STRIDE / priority
+    pub stride: u64,
+
+    // 资源统计
+    /// 线程累计使用的资源
+    pub usage: CpuUsage,
+    /// 上次统计 CPU 时间的时刻，在陷入、返回用户态及切换时更新
+    pub usage_timestamp: usize,
 }
 
 impl TCBInner {
@@ -75,43 +84,39 @@ impl TCBInner {
         &self.task_cx_ptr as *const usize
     }
 
-    /// 获取地址空间的 token
-    pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
-    }
-
-    /// 获取任务的 TrapContext
+    /// 获取线程的 TrapContext
     pub fn get_trap_cx(&self) -> &'static mut TrapContext {
         self.trap_cx_ppn.get_mut()
     }
 
-    fn get_status(&self) -> TaskStatus {
-        self.task_status
-    }
-
-    pub fn is_zombie(&self) -> bool {
-        self.get_status() == TaskStatus::Zombie
-    }
-
-    /// 在当前进程文件描述符表中分配一个空闲的文件描述符
-    pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len()).find(|&fd| self.fd_table[fd].is_none()) {
-            fd
+    /// 将上次统计以来的时间计入用户态（user 为 true）或内核态时间
+    pub fn account_time(&mut self, user: bool) {
+        let now = get_time();
+        let elapsed = now.saturating_sub(self.usage_timestamp);
+        if user {
+            self.usage.utime += elapsed;
         } else {
-            self.fd_table.push(None);
-            self.fd_table.len() - 1
+            self.usage.stime += elapsed;
         }
+        self.usage_timestamp = now;
+    }
+
+    /// 是否有未被屏蔽的待处理信号，有则不应进入阻塞
+    pub fn has_pending_signal(&self) -> bool {
+        !(self.signals - self.signal_mask).is_empty()
     }
 }
 
-/// 程序控制块，内核记录任务执行状态的结构
+/// 线程控制块，内核记录线程执行状态的结构，是调度的基本单位。
+/// 线程所属进程的资源记录在 PCB 中
 pub struct TCB {
     // 不可变数据放外面
-    pub pid: PidHandle,
-    /// 任务对应的内核栈
-    kernel_stack: KernelStack,
-    /// 可变数据
-    inner: Mutex<TCBInner>,
+    /// 所属进程
+    pub process: Weak<PCB>,
+    /// 线程对应的内核栈
+    pub kernel_stack: KernelStack,
+    /// 可变数据，从 tcb 对象缓存中分配
+    inner: SlabBox<Mutex<TCBInner>>,
 }
 
 impl TCB {
@@ -120,160 +125,34 @@ impl TCB {
         self.inner.lock()
     }
 
-    pub fn getpid(&self) -> usize {
-        self.pid.0
-    }
-
-    /// 加载一个 elf 到当前执行进程上下文
-    pub fn exec(&self, elf_data: &[u8], args: Vec<String>) {
-        let (memory_set, mut user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-        // 将参数的指针放到用户栈上
-        // 参考 https://rcore-os.github.io/rCore-Tutorial-Book-v3/chapter7/4cmdargs-and-redirection.html#sys-exec
-        let usize_len = core::mem::size_of::<usize>();
-        // 将 args 存到用户栈的顶部，以0结束；所以这里分配比实际大小多一个空间，用于放0
-        user_sp -= (args.len() + 1) * usize_len;
-        let argv_base = user_sp;
-        let mut argv: Vec<_> = (0..=args.len())
-            .map(|arg| {
-                translated_refmut(
-                    memory_set.token(),
-                    (argv_base + arg * usize_len) as *mut usize,
-                )
-            })
-            .collect();
-        *argv[args.len()] = 0; // 以0表示参数结束
-        for i in 0..args.len() {
-            // 将参数数据复制到 user_sp 参数后的部分
-            user_sp -= args[i].len() + 1;
-            *argv[i] = user_sp; // 参数指针位置，即入参的参数、数据都在栈上
-            let mut p = user_sp;
-            for c in args[i].as_bytes() {
-                *translated_refmut(memory_set.token(), p as *mut u8) = *c;
-                p += 1;
-            }
-            // 以 \0 结尾
-            *translated_refmut(memory_set.token(), p as *mut u8) = 0;
-        }
-        // make the user_sp aligned to 8B for k210 platform
-        // 按 4字节对齐 
-        user_sp -= user_sp % core::mem::size_of::<usize>();
-        // 继续持有当前 PCB
-        let mut inner = self.acquire_inner_lock();
-        inner.memory_set = memory_set;
-        inner.trap_cx_ppn = trap_cx_ppn;
-
-        let mut trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.lock().token(),
-            self.kernel_stack.get_top(),
-            trap_handler as usize,
-        );
-        trap_cx.x[10] = args.len();
-        trap_cx.x[11] = argv_base;
-        *inner.get_trap_cx() = trap_cx;
-    }
-
-    /// 从当前任务 fork 一个新任务。
-    /// 经过 fork 后，相同的有：
-    /// 1. 所有虚拟逻辑段地址
-    /// 不同的：
-    /// 1. pid 及其对应的 kernelStack
-    /// 2. taskContext 位置（放在 KernelStack 栈顶）
-    /// 3. 所有 ppn，含 trapContext 所在的 ppn
-    pub fn fork(self: &Arc<TCB>) -> Arc<TCB> {
-        let mut parent_inner = self.acquire_inner_lock();
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-        // 分配 pid 及 kernelStack
-        let pid = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid);
-        let kernel_stack_top = kernel_stack.get_top();
-        let task_cx_ptr = kernel_stack.push_on_top(TaskContext::goto_trap_return());
-        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
-        for fd in parent_inner.fd_table.iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
-                new_fd_table.push(None);
-            }
-        }
-        let tcb = Arc::new(TCB {
-            pid,
-            kernel_stack,
-            inner: Mutex::new(TCBInner {
-                task_cx_ptr: task_cx_ptr as usize,
-                task_status: TaskStatus::Ready,
-                memory_set,
-                trap_cx_ppn,
-                base_size: parent_inner.base_size,
-                parent: Some(Arc::downgrade(self)),
-                children: Vec::new(),
-                exit_code: 0,
-                fd_table: new_fd_table,
-            }),
-        });
-        parent_inner.children.push(tcb.clone());
-        let trap_cx = tcb.acquire_inner_lock().get_trap_cx();
-        trap_cx.kernel_sp = kernel_stack_top;
-        tcb
-    }
-
-    /// 获取 elf_data(应用镜像入口) 指针，返回新建的程序控制块
-    pub fn new(elf_data: &[u8]) -> Self {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-        // 分配 pid 及内核栈
-        let pid = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid);
-        let kernel_stack_top = kernel_stack.get_top();
-        // push a task context which goes to trap_return to the top of kernel stack
+    /// 在 process 中新建一个线程，分配 tid 与内核栈，并在内核栈顶放置跳转到 trap_return 的 TaskContext。
+    /// alloc_user_res 为 true 时同时映射线程的用户栈和 TrapContext；TrapContext 的内容由调用方初始化。
+    /// 页桢不足时返回 ENOMEM，已分配的资源随之回收
+    pub fn new(process: Arc<PCB>, alloc_user_res: bool) -> SysResult<Self> {
+        let res = TaskUserRes::new(Arc::clone(&process), alloc_user_res)?;
+        let trap_cx_ppn = res.trap_cx_ppn();
+        let kernel_stack = kstack_alloc()?;
         // 将 kernel_stack 的顶部设置为 taskContext，并将其 ra 置为 restore
         let task_cx_ptr = kernel_stack.push_on_top(TaskContext::goto_trap_return());
-        let tcb = Self {
-            pid,
+        Ok(Self {
+            process: Arc::downgrade(&process),
             kernel_stack,
-            inner: Mutex::new(TCBInner {
+            inner: SlabBox::new(Mutex::new(TCBInner {
+                res: Some(res),
+                trap_cx_ppn,
                 task_cx_ptr: task_cx_ptr as usize, // 指向 kernel_stack 的顶部
                 task_status: TaskStatus::Ready,
-                memory_set,
-                trap_cx_ppn,
-                base_size: user_sp,
-                parent: None,
-                children: Vec::new(),
-                exit_code: 0,
-                fd_table: vec![
-                    // 标准输入 0
-                    Some(Arc::new(Stdin)),
-                    // 标准输出 1
-                    Some(Arc::new(Stdout)),
-                    // 错误输出 2
-                    Some(Arc::new(Stdout)),
-                ],
-            }),
-        };
-        // 初始化用户空间的 TrapContext
-        let trap_cx = tcb.acquire_inner_lock().get_trap_cx();
-        println!("Got TrapContext: {:p}", trap_cx);
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.lock().token(),
-            kernel_stack_top,
-            trap_handler as usize,
-        );
-        println!("Got tcb");
-        tcb
+                on_cpu: false,
+                exit_code: None,
+                signals: SignalFlags::empty(),
+                signal_mask: SignalFlags::empty(),
+                signal_frame: 0,
+                frozen: false,
+                priority: DEFAULT_PRIORITY,
+                stride: 0,
+                usage: CpuUsage::default(),
+                usage_timestamp: 0,
+            })),
+        })
     }
 }
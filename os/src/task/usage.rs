@@ -0,0 +1,96 @@
+//! 资源使用统计：用户态与内核态的 CPU 时间、上下文切换次数与缺页次数。
+//! 线程在 TCB 中各自统计，进程的统计为其所有线程之和
+
+use crate::config::CLOCK_FREQ;
+
+/// 线程或进程累计使用的资源，时间以时钟周期为单位
+#[derive(Clone, Copy, Default)]
+pub struct CpuUsage {
+    /// 用户态运行时间
+    pub utime: usize,
+    /// 内核态运行时间
+    pub stime: usize,
+    /// 不需要读取磁盘的缺页次数，如延迟分配、写时复制
+    pub minflt: usize,
+    /// 需要读取磁盘的缺页次数
+    pub majflt: usize,
+    /// 主动让出 CPU 的次数，如阻塞、yield
+    pub nvcsw: usize,
+    /// 时间片用完被抢占的次数
+    pub nivcsw: usize,
+}
+
+impl CpuUsage {
+    /// 累加 other 的统计
+    pub fn add(&mut self, other: &Self) {
+        self.utime += other.utime;
+        self.stime += other.stime;
+        self.minflt += other.minflt;
+        self.majflt += other.majflt;
+        self.nvcsw += other.nvcsw;
+        self.nivcsw += other.nivcsw;
+    }
+
+    /// 转换为 getrusage 返回给用户的格式
+    pub fn to_rusage(&self) -> Rusage {
+        Rusage {
+            utime: TimeVal::from_ticks(self.utime),
+            stime: TimeVal::from_ticks(self.stime),
+            minflt: self.minflt,
+            majflt: self.majflt,
+            nvcsw: self.nvcsw,
+            nivcsw: self.nivcsw,
+        }
+    }
+}
+
+/// 时钟周期数转换为毫秒
+pub fn ticks_to_ms(ticks: usize) -> usize {
+    ticks / (CLOCK_FREQ / 1000)
+}
+
+/// 时间，与 Linux 的 struct timeval 一致
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TimeVal {
+    pub sec: usize,
+    pub usec: usize,
+}
+
+impl TimeVal {
+    fn from_ticks(ticks: usize) -> Self {
+        Self {
+            sec: ticks / CLOCK_FREQ,
+            usec: ticks % CLOCK_FREQ * 1_000_000 / CLOCK_FREQ,
+        }
+    }
+}
+
+/// getrusage 与 waitpid 返回的资源使用情况，内存布局与用户库一致。
+/// 只包含 Linux struct rusage 中内核有统计的字段
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Rusage {
+    /// 用户态运行时间
+    pub utime: TimeVal,
+    /// 内核态运行时间
+    pub stime: TimeVal,
+    pub minflt: usize,
+    pub majflt: usize,
+    pub nvcsw: usize,
+    pub nivcsw: usize,
+}
+
+/// times 返回的 CPU 时间，单位为毫秒，内存布局与用户库一致
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Tms {
+    /// 进程的用户态时间
+    pub utime: usize,
+    /// 进程的内核态时间
+    pub stime: usize,
+    /// 已回收的子进程的用户态时间之和
+    pub cutime: usize,
+    /// 已回收的子进程的内核态时间之和
+    pub cstime: usize,
+}
@@ -0,0 +1,67 @@
+use alloc::{collections::VecDeque, sync::Arc};
+
+use super::{current_task, mark_current_blocked, wakeup_task, TCB};
+
+/// 等待队列：记录因等待某个条件而阻塞的线程，条件满足时由 wake_one 或 wake_all 唤醒。
+/// 队列本身不加锁，一般放在保护等待条件的结构（如 PCBInner）中，由同一把锁保护，
+/// 这样检查条件与加入队列之间不会错过唤醒
+pub struct WaitQueue {
+    queue: VecDeque<Arc<TCB>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// 将当前线程标记为阻塞并加入队列。之后调用方需要先释放保护队列的锁，
+    /// 再调用 block_current_and_run_next 让出 CPU。
+    /// 线程有待处理的信号时不阻塞，返回 false，以便尽快返回用户态处理信号
+    pub fn block_current(&mut self) -> bool {
+        let task = match mark_current_blocked() {
+            Some(task) => task,
+            None => {
+                // 不再等待，去掉之前被信号唤醒时留下的记录
+                self.remove(&current_task().unwrap());
+                return false;
+            }
+        };
+        // 被信号提前唤醒的线程可能还留在队列中
+        if !self.queue.iter().any(|t| Arc::ptr_eq(t, &task)) {
+            self.queue.push_back(task);
+        }
+        true
+    }
+
+    /// 唤醒队列中最早阻塞的一个线程，返回是否有线程被唤醒
+    pub fn wake_one(&mut self) -> bool {
+        match self.queue.pop_front() {
+            Some(task) => {
+                wakeup_task(task);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 将线程从队列中移除，返回线程是否在队列中。线程被信号等其它原因唤醒后不再等待时需要调用，
+    /// 否则之后的 wake_one 可能唤醒它而不是真正在等待的线程
+    pub fn remove(&mut self, task: &Arc<TCB>) -> bool {
+        let len = self.queue.len();
+        self.queue.retain(|t| !Arc::ptr_eq(t, task));
+        self.queue.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// 唤醒队列中的所有线程，被唤醒的线程需要重新检查等待的条件
+    pub fn wake_all(&mut self) {
+        while let Some(task) = self.queue.pop_front() {
+            wakeup_task(task);
+        }
+    }
+}
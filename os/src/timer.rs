@@ -1,6 +1,16 @@
+use alloc::{collections::BinaryHeap, sync::Arc};
+use core::cmp::Ordering;
+use core::sync::atomic::{self, AtomicUsize};
+
+use lazy_static::lazy_static;
 use riscv::register::time;
+use spin::Mutex;
 
-use crate::{config::CLOCK_FREQ, sbi::set_timer};
+use crate::{
+    config::CLOCK_FREQ,
+    sbi::set_timer,
+    task::{block_current_and_run_next, mark_current_blocked, wakeup_task, TCB},
+};
 
 /// 时钟频率，100Hz
 const TICKS_PER_SEC: usize = 100;
@@ -16,4 +26,97 @@ pub fn get_time_ms() -> usize {
 
 pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC)
-}
\ No newline at end of file
+}
+
+/// 定时器：到期时间（毫秒）及到期时唤醒的线程，id 用于取消定时器
+pub struct Timer {
+    pub expire_ms: usize,
+    pub task: Arc<TCB>,
+    pub id: usize,
+}
+
+/// 下一个定时器的 id
+static NEXT_TIMER_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_ms == other.expire_ms
+    }
+}
+
+impl Eq for Timer {}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// BinaryHeap 是大根堆，这里反过来比较，使到期时间最早的定时器在堆顶
+impl Ord for Timer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.expire_ms.cmp(&self.expire_ms)
+    }
+}
+
+lazy_static! {
+    /// 定时器队列，按到期时间排序，由各核共享
+    static ref TIMERS: Mutex<BinaryHeap<Timer>> = Mutex::new(BinaryHeap::new());
+}
+
+/// 将定时器加入队列，返回其 id
+fn push_timer(timers: &mut BinaryHeap<Timer>, expire_ms: usize, task: Arc<TCB>) -> usize {
+    let id = NEXT_TIMER_ID.fetch_add(1, atomic::Ordering::Relaxed);
+    timers.push(Timer { expire_ms, task, id });
+    id
+}
+
+/// 阻塞当前线程直到 expire_ms，被唤醒后返回。
+/// 线程有待处理的信号时不阻塞，返回 false。被唤醒时不一定已经到期（例如被信号唤醒），
+/// 调用方需要自己检查时间
+pub fn block_current_until(expire_ms: usize) -> bool {
+    let mut timers = TIMERS.lock();
+    // 持有定时器队列的锁时标记为阻塞，check_timer 不会在此之前唤醒它
+    let task = match mark_current_blocked() {
+        Some(task) => task,
+        None => return false,
+    };
+    let id = push_timer(&mut timers, expire_ms, task);
+    drop(timers);
+    block_current_and_run_next();
+    cancel_timer(id);
+    true
+}
+
+/// 添加一个定时器，到期时唤醒 task，返回定时器的 id。用于线程同时在其它等待队列上阻塞、
+/// 需要超时的场景，调用方负责将线程标记为阻塞，并在被唤醒后用 cancel_timer 取消定时器
+pub fn add_timer(expire_ms: usize, task: Arc<TCB>) -> usize {
+    push_timer(&mut TIMERS.lock(), expire_ms, task)
+}
+
+/// 取消还没有到期的定时器 id，释放其持有的线程。线程在到期前因其它原因被唤醒时调用，
+/// 否则定时器到期时会把之后在别处阻塞的线程错误地唤醒。已到期的定时器不在队列中，什么也不做
+pub fn cancel_timer(id: usize) {
+    let mut timers = TIMERS.lock();
+    if timers.iter().any(|timer| timer.id == id) {
+        // BinaryHeap 不支持删除任意元素，去掉该定时器后重建
+        let rest = core::mem::take(&mut *timers)
+            .into_vec()
+            .into_iter()
+            .filter(|timer| timer.id != id)
+            .collect();
+        *timers = rest;
+    }
+}
+
+/// 唤醒所有已到期定时器上的线程，在时钟中断及核空闲时调用
+pub fn check_timer() {
+    let current_ms = get_time_ms();
+    let mut timers = TIMERS.lock();
+    while let Some(timer) = timers.peek() {
+        if timer.expire_ms > current_ms {
+            break;
+        }
+        wakeup_task(timers.pop().unwrap().task);
+    }
+}
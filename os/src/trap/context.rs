@@ -20,6 +20,9 @@ pub struct TrapContext {
     pub kernel_sp: usize,
     /// trap handler 入口的虚拟地址
     pub trap_handler: usize,
+    /// 返回用户态时所在 hart 的编号，陷入时由 __alltraps 恢复到 tp 寄存器。
+    /// 线程可能在不同的核上运行，所以每次返回用户态前都要重新写入
+    pub hart_id: usize,
 }
 
 impl TrapContext {
@@ -47,6 +50,7 @@ impl TrapContext {
             kernel_satp,
             kernel_sp,
             trap_handler,
+            hart_id: 0,
         };
         cx.set_sp(sp);
         cx
@@ -1,10 +1,10 @@
 mod context;
 
-use crate::{config::{TRAMPOLINE, TRAP_CONTEXT}, syscall::syscall, task::{current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next}, timer::set_next_trigger};
+use crate::{config::TRAMPOLINE, fs::poll_console, mm::{on_kernel_entry, on_user_return, PTEFlags, PageFault, VirtAddr}, smp::hart_id, syscall::syscall, task::{check_cpu_limit, current_process, current_task, current_trap_cx, current_trap_cx_user_va, current_user_token, handle_signals, preempt_current_and_run_next, send_fault_signal, SignalFlags}, timer::{check_timer, set_next_trigger}};
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
-    sie, stval, stvec,
+    sie, sip, stval, stvec,
 };
 
 global_asm!(include_str!("trap.S"));
@@ -24,6 +24,13 @@ pub fn enable_timer_interrupt() {
     }
 }
 
+/// 启用 S 模式软件中断，用于接收其它核发来的核间中断
+pub fn enable_software_interrupt() {
+    unsafe {
+        sie::set_ssoft();
+    }
+}
+
 /// 设置用户程序陷入时的处理函数(统一到跳板地址)
 fn set_user_trap_entry() {
     // 跳板地址实际上就是 __alltraps 的地址
@@ -43,6 +50,9 @@ fn set_kernel_trap_entry() {
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    on_kernel_entry();
+    // 从这里开始计入内核态时间
+    current_task().unwrap().acquire_inner_lock().account_time(true);
     let scause = scause::read();
     let stval = stval::read();
     match scause.cause() {
@@ -50,34 +60,94 @@ pub fn trap_handler() -> ! {
             let mut cx = current_trap_cx();
             // 来自 U 特权级的 environment call(ecall)，即系统调用
             cx.sepc += 4; // spec 在 trap 时，会被修改为 trap 前的最后一条指令，这里+4是让它指向下一条指令
-                          // a0 = syscall(a7, a0, a1, a2)，系统调用规定的寄存器
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+                          // a0 = syscall(a7, a0, ..., a5)，系统调用规定的寄存器
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
             // 经过可能的 sys_exec 后，当前任务已发生变化，所以需要重新加载 cx
             cx = current_trap_cx();
             cx.x[10] = result as usize;
         }
-        Trap::Exception(Exception::StoreFault) | 
         Trap::Exception(Exception::StorePageFault) |
-        Trap::Exception(Exception::InstructionFault) |
         Trap::Exception(Exception::InstructionPageFault) |
-        Trap::Exception(Exception::LoadFault) |
         Trap::Exception(Exception::LoadPageFault) => {
+            // 延迟分配、已换出与写时复制的页面都在这里补上，补上后重新执行出错的指令
+            let is_write = matches!(scause.cause(), Trap::Exception(Exception::StorePageFault));
+            let required = match scause.cause() {
+                Trap::Exception(Exception::StorePageFault) => PTEFlags::W,
+                Trap::Exception(Exception::InstructionPageFault) => PTEFlags::X,
+                _ => PTEFlags::R,
+            };
+            let vpn = VirtAddr::from(stval).floor();
+            let process = current_process();
+            let mut process_inner = process.acquire_inner_lock();
+            // 页面可能已被同一进程在其它核上运行的线程处理过，此时重新执行即可
+            let fault = process_inner.memory_set.handle_page_fault(vpn, is_write);
+            let handled = fault != PageFault::Invalid
+                || process_inner.memory_set.is_user_accessible(vpn, required);
+            if handled {
+                process_inner.memory_set.flush_local_tlb(vpn);
+            }
+            drop(process_inner);
+            drop(process);
+            match fault {
+                PageFault::Minor => {
+                    current_task().unwrap().acquire_inner_lock().usage.minflt += 1;
+                }
+                PageFault::Major => {
+                    current_task().unwrap().acquire_inner_lock().usage.majflt += 1;
+                }
+                PageFault::OutOfMemory => {
+                    println!(
+                        "[kernel] Out of memory in application, bad addr = {:#x}.",
+                        stval
+                    );
+                    crate::mm::report_memory();
+                    send_fault_signal(SignalFlags::SIGKILL);
+                }
+                PageFault::Invalid => {}
+            }
+            if !handled {
+                println!(
+                    "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}.",
+                    scause.cause(),
+                    stval,
+                    current_trap_cx().sepc,
+                );
+                send_fault_signal(SignalFlags::SIGSEGV);
+            }
+        }
+        Trap::Exception(Exception::StoreFault) |
+        Trap::Exception(Exception::InstructionFault) |
+        Trap::Exception(Exception::LoadFault) => {
             println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
+                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}.",
                 scause.cause(),
                 stval,
                 current_trap_cx().sepc,
-            );;
-            exit_current_and_run_next(-2);
+            );
+            send_fault_signal(SignalFlags::SIGSEGV);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            println!("[kernel] IllegalInstruction in application, core dumped.");
-            exit_current_and_run_next(-3);
-            //run_next_app();
+            println!("[kernel] IllegalInstruction in application.");
+            send_fault_signal(SignalFlags::SIGILL);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
-            suspend_current_and_run_next();
+            // 唤醒到期的睡眠线程
+            check_timer();
+            // 读入控制台输入，Ctrl-C、Ctrl-Z 需要及时发给前台进程组
+            poll_console();
+            // CPU 时间超过 RLIMIT_CPU 时发送 SIGXCPU 或 SIGKILL
+            check_cpu_limit();
+            preempt_current_and_run_next();
+        }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            // 核间中断只用于 TLB shootdown，陷入即说明已离开用户态，返回前会刷新待刷新的页表项
+            unsafe {
+                sip::clear_ssoft();
+            }
         }
         _ => {
             panic!(
@@ -93,10 +163,18 @@ pub fn trap_handler() -> ! {
 /// 陷入完成后的返回函数
 #[no_mangle]
 pub fn trap_return() -> ! {
+    // 返回用户态前处理待处理的信号，可能会终止或暂停当前任务
+    handle_signals();
     set_user_trap_entry();
-    let trap_cx_ptr = TRAP_CONTEXT;
+    // 每个线程的 TrapContext 位于不同的虚拟页
+    let trap_cx_ptr = current_trap_cx_user_va();
     // 获取当前任务的页表入口
     let user_satp = current_user_token();
+    // 线程可能换到了其它核上运行，记录下次陷入时要恢复的 hart 编号
+    current_trap_cx().hart_id = hart_id();
+    // 到这里为止计入内核态时间
+    current_task().unwrap().acquire_inner_lock().account_time(false);
+    on_user_return(user_satp);
     extern "C" {
         fn __alltraps();
         fn __restore();
@@ -16,19 +16,18 @@ use alloc::string::String;
 #[no_mangle]
 pub fn main(argc: usize, argv: &[&str]) -> i32 {
     assert!(argc == 2);
-    let fd = open(argv[1], OpenFlags::RDONLY);
-    if fd == -1 {
-        panic!("Error occured when opening file");
-    }
-    let fd = fd as usize;
+    let fd = match open(argv[1], OpenFlags::RDONLY) {
+        Ok(fd) => fd,
+        Err(errno) => panic!("Error occured when opening file: {:?}", errno),
+    };
     let mut buf = [0u8; 16];
     let mut s = String::new();
     loop {
-        let size = read(fd, &mut buf) as usize;
+        let size = read(fd, &mut buf).unwrap();
         if size == 0 { break; }
         s.push_str(core::str::from_utf8(&buf[..size]).unwrap());
     }
     println!("{}", s);
-    close(fd);
+    close(fd).unwrap();
     0
 }
\ No newline at end of file
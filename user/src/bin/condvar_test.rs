@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    condvar_create, condvar_signal, condvar_wait, exit, mutex_blocking_create, mutex_lock,
+    mutex_unlock, sleep, thread_create, waittid, Errno,
+};
+
+static mut READY: bool = false;
+static mut CONDVAR: usize = 0;
+static mut MUTEX: usize = 0;
+
+/// 等待主线程设置 READY
+fn waiter(_arg: usize) {
+    unsafe {
+        mutex_lock(MUTEX).unwrap();
+        while !core::ptr::read_volatile(&READY) {
+            condvar_wait(CONDVAR, MUTEX).unwrap();
+        }
+        mutex_unlock(MUTEX).unwrap();
+    }
+    println!("waiter woken up");
+    exit(0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(condvar_signal(100), Err(Errno::EINVAL));
+    unsafe {
+        CONDVAR = condvar_create().unwrap();
+        MUTEX = mutex_blocking_create().unwrap();
+        assert_eq!(condvar_wait(CONDVAR, 100), Err(Errno::EINVAL));
+    }
+    let tid = thread_create(waiter as usize, 0).unwrap();
+    // 让等待线程先阻塞在条件变量上
+    sleep(50);
+    unsafe {
+        mutex_lock(MUTEX).unwrap();
+        READY = true;
+        condvar_signal(CONDVAR).unwrap();
+        mutex_unlock(MUTEX).unwrap();
+    }
+    assert_eq!(waittid(tid), Ok(0));
+    println!("condvar_test passed!");
+    0
+}
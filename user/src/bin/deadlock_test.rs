@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    enable_deadlock_detect, exit, mutex_blocking_create, mutex_lock, mutex_unlock,
+    semaphore_create, semaphore_down, semaphore_up, sleep, thread_create, waittid, Errno,
+};
+
+const PHILOSOPHER_NUM: usize = 2;
+
+/// 每个哲学家左手边叉子对应的互斥锁 id
+static mut FORKS: [usize; PHILOSOPHER_NUM] = [0; PHILOSOPHER_NUM];
+static mut SEMAPHORES: [usize; 2] = [0; 2];
+
+/// 哲学家先拿左手的叉子，再拿右手的叉子
+fn philosopher(id: usize) {
+    unsafe {
+        let (left, right) = (FORKS[id], FORKS[(id + 1) % PHILOSOPHER_NUM]);
+        assert_eq!(mutex_lock(left), Ok(()));
+        assert_eq!(mutex_lock(right), Ok(()));
+        mutex_unlock(left).unwrap();
+        mutex_unlock(right).unwrap();
+    }
+    exit(0);
+}
+
+/// 同一线程重复获取一把互斥锁
+fn self_deadlock_test() {
+    let mutex = mutex_blocking_create().unwrap();
+    assert_eq!(mutex_lock(mutex), Ok(()));
+    assert_eq!(mutex_lock(mutex), Err(Errno::EDEADLK));
+    mutex_unlock(mutex).unwrap();
+    println!("self_deadlock_test passed!");
+}
+
+/// 两个哲学家各拿一把叉子再去拿对方的叉子
+fn philosopher_test() {
+    unsafe {
+        for fork in FORKS.iter_mut() {
+            *fork = mutex_blocking_create().unwrap();
+        }
+        assert_eq!(mutex_lock(FORKS[0]), Ok(()));
+        let tid = thread_create(philosopher as usize, 1).unwrap();
+        // 等 1 号哲学家拿到 FORKS[1] 后阻塞在 FORKS[0] 上
+        sleep(50);
+        assert_eq!(mutex_lock(FORKS[1]), Err(Errno::EDEADLK));
+        // 放下叉子，1 号哲学家就可以吃饭了
+        mutex_unlock(FORKS[0]).unwrap();
+        assert_eq!(waittid(tid), Ok(0));
+    }
+    println!("philosopher_test passed!");
+}
+
+fn semaphore_worker(_arg: usize) {
+    unsafe {
+        assert_eq!(semaphore_down(SEMAPHORES[1]), Ok(()));
+        assert_eq!(semaphore_down(SEMAPHORES[0]), Ok(()));
+        semaphore_up(SEMAPHORES[0]).unwrap();
+        semaphore_up(SEMAPHORES[1]).unwrap();
+    }
+    exit(0);
+}
+
+/// 两个资源数为 1 的信号量，两个线程以相反的顺序获取
+fn semaphore_test() {
+    unsafe {
+        for sem in SEMAPHORES.iter_mut() {
+            *sem = semaphore_create(1).unwrap();
+        }
+        assert_eq!(semaphore_down(SEMAPHORES[0]), Ok(()));
+        let tid = thread_create(semaphore_worker as usize, 0).unwrap();
+        sleep(50);
+        assert_eq!(semaphore_down(SEMAPHORES[1]), Err(Errno::EDEADLK));
+        semaphore_up(SEMAPHORES[0]).unwrap();
+        assert_eq!(waittid(tid), Ok(0));
+    }
+    println!("semaphore_test passed!");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(enable_deadlock_detect(true), Ok(()));
+    self_deadlock_test();
+    philosopher_test();
+    semaphore_test();
+    println!("deadlock_test passed!");
+    0
+}
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::slice;
+use user_lib::{
+    close, exec, exit, fork, mmap, munmap, open, pipe, read, waitpid, write, Errno, OpenFlags,
+    ProtFlags,
+};
+
+const PAGE_SIZE: usize = 4096;
+/// 没有映射的用户地址
+const BAD_ADDR: usize = 0x1000;
+
+/// 把任意地址当作缓冲区传给系统调用，由内核检查地址是否合法
+fn bad_buffer(addr: usize, len: usize) -> &'static mut [u8] {
+    unsafe { slice::from_raw_parts_mut(addr as *mut u8, len) }
+}
+
+/// 未映射、只读、跨页后未映射的缓冲区都返回 EFAULT，而不是让内核崩溃
+fn buffer_test() {
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd).unwrap();
+    assert_eq!(write(pipe_fd[1], bad_buffer(BAD_ADDR, 8)), Err(Errno::EFAULT));
+    assert_eq!(write(pipe_fd[1], b"x"), Ok(1));
+    // 字符串常量位于只读段，内核不能写入
+    let rodata = "read only";
+    assert_eq!(
+        read(pipe_fd[0], bad_buffer(rodata.as_ptr() as usize, 1)),
+        Err(Errno::EFAULT)
+    );
+    let mut buf = [0u8; 1];
+    assert_eq!(read(pipe_fd[0], &mut buf), Ok(1));
+    assert_eq!(buf[0], b'x');
+
+    // 缓冲区的后半部分跨到了未映射的页中
+    let start = mmap(0, 2 * PAGE_SIZE, ProtFlags::READ | ProtFlags::WRITE).unwrap();
+    munmap(start + PAGE_SIZE, PAGE_SIZE).unwrap();
+    assert_eq!(
+        write(pipe_fd[1], bad_buffer(start + PAGE_SIZE - 4, 8)),
+        Err(Errno::EFAULT)
+    );
+    assert_eq!(write(pipe_fd[1], bad_buffer(start + PAGE_SIZE - 4, 4)), Ok(4));
+    munmap(start, PAGE_SIZE).unwrap();
+    close(pipe_fd[0]).unwrap();
+    close(pipe_fd[1]).unwrap();
+    println!("buffer_test passed!");
+}
+
+/// 参数中的字符串与输出参数指针不合法时返回 EFAULT
+fn pointer_test() {
+    let bad_str = unsafe { core::str::from_utf8_unchecked(bad_buffer(BAD_ADDR, 1)) };
+    assert_eq!(open(bad_str, OpenFlags::RDONLY), Err(Errno::EFAULT));
+    assert_eq!(exec(bad_str, &[0 as *const u8]), Err(Errno::EFAULT));
+    assert_eq!(
+        exec("efault_test\0", &[BAD_ADDR as *const u8, 0 as *const u8]),
+        Err(Errno::EFAULT)
+    );
+    let pipe_fd = unsafe { slice::from_raw_parts_mut(BAD_ADDR as *mut usize, 2) };
+    assert_eq!(pipe(pipe_fd), Err(Errno::EFAULT));
+
+    let pid = fork().unwrap();
+    if pid == 0 {
+        exit(0);
+    }
+    let bad_exit_code = unsafe { &mut *(BAD_ADDR as *mut i32) };
+    assert_eq!(waitpid(pid, bad_exit_code), Err(Errno::EFAULT));
+    // 地址不合法时子进程不会被回收
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 0);
+    println!("pointer_test passed!");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    buffer_test();
+    pointer_test();
+    println!("efault_test passed!");
+    0
+}
@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{env, exec, execve, exit, fork, waitpid};
+
+const KEY: &str = "ENV_TEST";
+const VALUE: &str = "hello world";
+
+/// fork 出子进程重新执行本程序，参数为 mode，返回子进程的退出码
+fn run_child(mode: &str, inherit: bool) -> i32 {
+    let pid = fork().unwrap();
+    if pid == 0 {
+        let args = [
+            "env_test\0".as_ptr(),
+            mode.as_ptr(),
+            core::ptr::null::<u8>(),
+        ];
+        if inherit {
+            exec("env_test\0", &args).unwrap();
+        } else {
+            execve("env_test\0", &args, &[core::ptr::null::<u8>()]).unwrap();
+        }
+        unreachable!();
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    exit_code
+}
+
+/// 设置、覆盖与删除环境变量
+fn local_test() {
+    // 可能从 shell 继承了同名变量
+    env::remove_var(KEY);
+    assert_eq!(env::var(KEY), None);
+    env::set_var(KEY, "old");
+    env::set_var(KEY, VALUE);
+    assert_eq!(env::var(KEY).as_deref(), Some(VALUE));
+    // 键名是另一个键的前缀时不能混淆
+    env::set_var("ENV", "short");
+    assert_eq!(env::var(KEY).as_deref(), Some(VALUE));
+    assert_eq!(env::var("ENV").as_deref(), Some("short"));
+    env::remove_var("ENV");
+    assert_eq!(env::var("ENV"), None);
+    assert!(env::vars()
+        .iter()
+        .any(|(key, value)| key == KEY && value == VALUE));
+    println!("local_test passed!");
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 2 {
+        match argv[1] {
+            // exec 会继承环境变量
+            "inherit" => assert_eq!(env::var(KEY).as_deref(), Some(VALUE)),
+            // execve 传入空的 envp
+            "empty" => assert!(env::vars().is_empty()),
+            _ => exit(-1),
+        }
+        return 0;
+    }
+    local_test();
+    assert_eq!(run_child("inherit\0", true), 0);
+    assert_eq!(run_child("empty\0", false), 0);
+    println!("env_test passed!");
+    0
+}
@@ -16,18 +16,14 @@ use user_lib::{
 pub fn main() -> i32 {
     let test_str = "Hello, world!";
     let filea = "filea\0";
-    let fd = open(filea, OpenFlags::CREATE | OpenFlags::WRONLY);
-    assert!(fd > 0);
-    let fd = fd as usize;
-    write(fd, test_str.as_bytes());
-    close(fd);
+    let fd = open(filea, OpenFlags::CREATE | OpenFlags::WRONLY).unwrap();
+    write(fd, test_str.as_bytes()).unwrap();
+    close(fd).unwrap();
 
-    let fd = open(filea, OpenFlags::RDONLY);
-    assert!(fd > 0);
-    let fd = fd as usize;
+    let fd = open(filea, OpenFlags::RDONLY).unwrap();
     let mut buffer = [0u8; 100];
-    let read_len = read(fd, &mut buffer) as usize;
-    close(fd);
+    let read_len = read(fd, &mut buffer).unwrap();
+    close(fd).unwrap();
 
     assert_eq!(
         test_str,
@@ -11,7 +11,7 @@ static NUM: usize = 30;
 #[no_mangle]
 pub fn main() -> i32 {
     for _ in 0..NUM {
-        let pid = fork();
+        let pid = fork().unwrap();
         if pid == 0 {
             let current_time = get_time();
             let sleep_length = (current_time as i32 as isize) * (current_time as i32 as isize) % 1000 + 1000;
@@ -24,10 +24,10 @@ pub fn main() -> i32 {
 
     let mut exit_code: i32 = 0;
     for _ in 0..NUM {
-        assert!(wait(&mut exit_code) > 0);
+        assert!(wait(&mut exit_code).is_ok());
         assert_eq!(exit_code, 0);
     }
-    assert!(wait(&mut exit_code) < 0);
+    assert!(wait(&mut exit_code).is_err());
     println!("forktest2 test passed!");
     0
 }
\ No newline at end of file
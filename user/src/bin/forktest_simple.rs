@@ -4,14 +4,14 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{fork, getpid, wait};
+use user_lib::{fork, getpid, wait, Errno};
 
 #[no_mangle]
 pub fn main() -> i32 {
-    assert_eq!(wait(&mut 0i32), -1);
+    assert_eq!(wait(&mut 0i32), Err(Errno::ECHILD));
     println!("sys_wait without child process test passed!");
     println!("parent start, pid = {}!", getpid());
-    let pid = fork();
+    let pid = fork().unwrap();
     if pid == 0 {
         // child process
         println!("hello child process!");
@@ -20,7 +20,7 @@ pub fn main() -> i32 {
         // parent process
         let mut exit_code: i32 = 0;
         println!("ready waiting on parent process!");
-        assert_eq!(pid, wait(&mut exit_code));
+        assert_eq!(wait(&mut exit_code), Ok(pid));
         assert_eq!(exit_code, 100);
         println!("child process pid = {}, exit code = {}", pid, exit_code);
         0
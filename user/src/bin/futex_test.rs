@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use user_lib::{
+    exit, futex_wait, futex_wait_timeout, futex_wake, get_time, sleep, sync::Mutex, thread_create,
+    waittid, yield_, Errno,
+};
+
+const THREAD_NUM: usize = 4;
+const PER_THREAD: usize = 200;
+
+static COUNTER: Mutex<usize> = Mutex::new(0);
+static FLAG: AtomicU32 = AtomicU32::new(0);
+
+/// 在锁内先读后写，中间让出 CPU，制造竞争
+fn adder(_arg: usize) {
+    for _ in 0..PER_THREAD {
+        let mut counter = COUNTER.lock();
+        let value = *counter;
+        yield_();
+        *counter = value + 1;
+    }
+    exit(0);
+}
+
+/// 值不匹配时不阻塞；阻塞的线程被 futex_wake 唤醒
+fn wait_wake_test() {
+    assert_eq!(futex_wait(&FLAG, 1), Err(Errno::EAGAIN));
+    assert_eq!(futex_wake(&FLAG, 1), Ok(0));
+    let tid = thread_create(waiter as usize, 0).unwrap();
+    // 等待线程阻塞后再唤醒
+    sleep(50);
+    FLAG.store(1, Ordering::SeqCst);
+    assert_eq!(futex_wake(&FLAG, 1), Ok(1));
+    assert_eq!(waittid(tid), Ok(0));
+    println!("wait_wake_test passed!");
+}
+
+fn waiter(_arg: usize) {
+    while FLAG.load(Ordering::SeqCst) == 0 {
+        let _ = futex_wait(&FLAG, 0);
+    }
+    exit(0);
+}
+
+/// 没有被唤醒时等到超时返回，超时参数通过第四个参数寄存器传递
+fn timeout_test() {
+    let flag = AtomicU32::new(0);
+    let start = get_time();
+    assert_eq!(futex_wait_timeout(&flag, 0, 50), Err(Errno::ETIMEDOUT));
+    assert!(get_time() - start >= 50);
+    println!("timeout_test passed!");
+}
+
+fn mutex_test() {
+    let tids: Vec<usize> = (0..THREAD_NUM)
+        .map(|_| thread_create(adder as usize, 0).unwrap())
+        .collect();
+    for tid in tids {
+        assert_eq!(waittid(tid), Ok(0));
+    }
+    assert_eq!(*COUNTER.lock(), THREAD_NUM * PER_THREAD);
+    println!("mutex_test passed!");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    wait_wake_test();
+    timeout_test();
+    mutex_test();
+    println!("futex_test passed!");
+    0
+}
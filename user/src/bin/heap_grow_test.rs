@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{brk, mprotect, sbrk, ProtFlags};
+
+/// 远超原先 16 KiB 固定用户堆的分配量
+const LEN: usize = 256 * 1024;
+const PAGE_SIZE: usize = 4096;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let start_brk = brk(0);
+    let mut v: Vec<usize> = Vec::with_capacity(LEN);
+    for i in 0..LEN {
+        v.push(i);
+    }
+    for i in 0..LEN {
+        assert_eq!(v[i], i);
+    }
+    assert!(brk(0) > start_brk);
+    println!("heap grew from {:#x} to {:#x}", start_brk, brk(0));
+    drop(v);
+
+    // 堆顶可以收缩，但不能低于堆底
+    let top = sbrk(0).unwrap();
+    assert_eq!(sbrk(4096), Ok(top));
+    assert_eq!(sbrk(-4096), Ok(top + 4096));
+    assert_eq!(brk(1), top);
+
+    // 堆被 mprotect 拆分成多段后，扩大只延伸最高一段，缩小回收新堆顶之上的所有页面
+    let base = (top + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+    let page = |i: usize| (base + i * PAGE_SIZE) as *mut u8;
+    assert_eq!(brk(base + 4 * PAGE_SIZE), base + 4 * PAGE_SIZE);
+    assert_eq!(mprotect(base + PAGE_SIZE, PAGE_SIZE, ProtFlags::READ), Ok(()));
+    assert_eq!(brk(base + 6 * PAGE_SIZE), base + 6 * PAGE_SIZE);
+    for &i in [0, 2, 3, 4, 5].iter() {
+        unsafe {
+            page(i).write_volatile(1);
+        }
+    }
+    assert_eq!(brk(base + PAGE_SIZE), base + PAGE_SIZE);
+    assert_eq!(brk(base + 6 * PAGE_SIZE), base + 6 * PAGE_SIZE);
+    unsafe {
+        assert_eq!(page(0).read_volatile(), 1);
+        for i in 1..6 {
+            // 重新扩大的部分可写，且内容为 0
+            assert_eq!(page(i).read_volatile(), 0);
+            page(i).write_volatile(2);
+        }
+    }
+    assert_eq!(brk(top), top);
+    println!("heap_grow_test passed!");
+    0
+}
@@ -9,17 +9,19 @@ extern crate user_lib;
 
 #[no_mangle]
 fn main() -> i32 {
-    if fork() == 0 {
+    if fork().unwrap() == 0 {
         // rust 不会在字符串后插入 \0(因为 rust 的字符串是用胖指针表示的)
-        exec("user_shell\0", &[0 as *const u8]); 
+        exec("user_shell\0", &[0 as *const u8]).unwrap();
     } else {
         loop {
             let mut exit_code: i32 = 0;
-            let pid = wait(&mut exit_code);
-            if pid == -1 {
-                yield_();
-                continue;
-            }
+            let pid = match wait(&mut exit_code) {
+                Ok(pid) => pid,
+                Err(_) => {
+                    yield_();
+                    continue;
+                }
+            };
             println!(
                 "[initproc] Released a zombie process, pid={}, exit_code={}",
                 pid,
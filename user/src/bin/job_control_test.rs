@@ -0,0 +1,108 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, getpgid, getpid, getsid, kill, killpg, pipe, setpgid, setsid, sleep,
+    tcgetpgrp, waitpid, waitpid_with_options, wifstopped, wstopsig, Errno, SIGCONT, SIGTERM,
+    SIGTSTP, WUNTRACED,
+};
+
+/// 子进程可以新建进程组；会话首进程不能再 setsid
+fn pgid_test() {
+    let pid = fork().unwrap();
+    if pid == 0 {
+        let pid = getpid() as usize;
+        assert_eq!(setpgid(0, 0), Ok(()));
+        assert_eq!(getpgid(0), Ok(pid));
+        // 已经是进程组组长，不能新建会话
+        assert_eq!(setsid(), Err(Errno::EPERM));
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 0);
+
+    let pid = fork().unwrap();
+    if pid == 0 {
+        let pid = getpid() as usize;
+        assert_eq!(setsid(), Ok(pid));
+        assert_eq!(getsid(0), Ok(pid));
+        assert_eq!(getpgid(0), Ok(pid));
+        // 会话首进程不能改变进程组
+        assert_eq!(setpgid(0, 0), Err(Errno::EPERM));
+        exit(0);
+    }
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 0);
+    // 只能设置自己或子进程
+    assert_eq!(setpgid(1, 0), Err(Errno::ESRCH));
+    println!("pgid_test passed!");
+}
+
+/// WUNTRACED 报告被暂停的子进程，SIGCONT 后子进程继续运行直到退出
+fn stop_test() {
+    let pid = fork().unwrap();
+    if pid == 0 {
+        sleep(100);
+        exit(7);
+    }
+    kill(pid, SIGTSTP).unwrap();
+    let mut exit_code = 0;
+    assert_eq!(waitpid_with_options(pid as isize, &mut exit_code, WUNTRACED), Ok(pid));
+    assert!(wifstopped(exit_code));
+    assert_eq!(wstopsig(exit_code), SIGTSTP);
+    kill(pid, SIGCONT).unwrap();
+    assert_eq!(waitpid_with_options(pid as isize, &mut exit_code, WUNTRACED), Ok(pid));
+    assert!(!wifstopped(exit_code));
+    assert_eq!(exit_code, 7);
+    println!("stop_test passed!");
+}
+
+/// killpg 向进程组中的所有进程发送信号
+fn killpg_test() {
+    let pid = fork().unwrap();
+    if pid == 0 {
+        setpgid(0, 0).unwrap();
+        // 孙进程继承进程组
+        let grandchild = fork().unwrap();
+        if grandchild == 0 {
+            sleep(1000);
+            exit(0);
+        }
+        let mut exit_code = 0;
+        waitpid(grandchild, &mut exit_code).unwrap();
+        exit(exit_code);
+    }
+    // 父子进程都设置一次，发送信号时进程组一定已经存在
+    let _ = setpgid(pid, pid);
+    sleep(20);
+    assert_eq!(killpg(pid, SIGTERM), Ok(()));
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, -SIGTERM);
+    println!("killpg_test passed!");
+}
+
+/// 只有终端支持前台进程组
+fn tty_test() {
+    assert!(tcgetpgrp(0).is_ok());
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd).unwrap();
+    assert_eq!(tcgetpgrp(pipe_fd[0]), Err(Errno::ENOTTY));
+    close(pipe_fd[0]).unwrap();
+    close(pipe_fd[1]).unwrap();
+    println!("tty_test passed!");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    pgid_test();
+    stop_test();
+    killpg_test();
+    tty_test();
+    println!("job_control_test passed!");
+    0
+}
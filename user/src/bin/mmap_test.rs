@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, mprotect, munmap, Errno, ProtFlags};
+
+/// 远大于用户堆的缓冲区，1 MiB
+const LEN: usize = 1 << 20;
+const PAGE_SIZE: usize = 4096;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let rw = ProtFlags::READ | ProtFlags::WRITE;
+    let start = mmap(0, LEN, rw).unwrap();
+    let buf = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, LEN) };
+    // 页面由内核在首次访问时清零
+    assert!(buf.iter().all(|b| *b == 0));
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    for (i, b) in buf.iter().enumerate() {
+        assert_eq!(*b, i as u8);
+    }
+    println!("mmap {:#x} bytes at {:#x} ok", LEN, start);
+
+    // 与已有映射重叠、未对齐、无权限都应失败
+    assert_eq!(mmap(start, PAGE_SIZE, rw), Err(Errno::EEXIST));
+    assert_eq!(mmap(start + 1, PAGE_SIZE, rw), Err(Errno::EINVAL));
+    assert_eq!(mmap(0, PAGE_SIZE, ProtFlags::empty()), Err(Errno::EINVAL));
+
+    // 只读后仍可读
+    assert_eq!(mprotect(start, PAGE_SIZE, ProtFlags::READ), Ok(()));
+    assert_eq!(buf[1], 1);
+    // 只写的映射同时可读，写入后能读回
+    assert_eq!(mprotect(start, PAGE_SIZE, ProtFlags::WRITE), Ok(()));
+    buf[0] = 0xff;
+    assert_eq!(buf[0], 0xff);
+    let write_only = mmap(0, PAGE_SIZE, ProtFlags::WRITE).unwrap();
+    unsafe {
+        (write_only as *mut usize).write_volatile(0x1234);
+        assert_eq!((write_only as *const usize).read_volatile(), 0x1234);
+    }
+    let write_exec = mmap(0, PAGE_SIZE, ProtFlags::WRITE | ProtFlags::EXEC).unwrap();
+    unsafe {
+        (write_exec as *mut usize).write_volatile(0x5678);
+    }
+    assert_eq!(munmap(write_only, PAGE_SIZE), Ok(()));
+    assert_eq!(munmap(write_exec, PAGE_SIZE), Ok(()));
+
+    // 从中间解除一页映射，两侧仍可访问，重复解除失败
+    let hole = start + LEN / 2;
+    assert_eq!(munmap(hole, PAGE_SIZE), Ok(()));
+    assert_eq!(munmap(hole, PAGE_SIZE), Err(Errno::EINVAL));
+    assert_eq!(buf[LEN / 2 - 1], (LEN / 2 - 1) as u8);
+    assert_eq!(buf[LEN / 2 + PAGE_SIZE], (LEN / 2 + PAGE_SIZE) as u8);
+    // 空洞可以被重新映射，且内容为0
+    assert_eq!(mmap(hole, PAGE_SIZE, rw), Ok(hole));
+    assert_eq!(buf[LEN / 2], 0);
+
+    assert_eq!(munmap(start, LEN), Ok(()));
+    println!("mmap_test passed!");
+    0
+}
@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{
+    exit, mutex_blocking_create, mutex_create, mutex_lock, mutex_unlock, thread_create, waittid,
+    yield_, Errno,
+};
+
+const THREAD_NUM: usize = 4;
+const PER_THREAD: usize = 200;
+
+static mut COUNTER: usize = 0;
+
+/// 在锁内先读后写，中间让出 CPU，没有互斥时必然丢失更新
+fn adder(mutex_id: usize) {
+    for _ in 0..PER_THREAD {
+        assert_eq!(mutex_lock(mutex_id), Ok(()));
+        unsafe {
+            let value = core::ptr::read_volatile(&COUNTER);
+            yield_();
+            core::ptr::write_volatile(&mut COUNTER, value + 1);
+        }
+        assert_eq!(mutex_unlock(mutex_id), Ok(()));
+    }
+    exit(0);
+}
+
+fn run(mutex_id: usize) {
+    unsafe {
+        COUNTER = 0;
+    }
+    let tids: Vec<usize> = (0..THREAD_NUM)
+        .map(|_| thread_create(adder as usize, mutex_id).unwrap())
+        .collect();
+    for tid in tids {
+        assert_eq!(waittid(tid), Ok(0));
+    }
+    assert_eq!(
+        unsafe { core::ptr::read_volatile(&COUNTER) },
+        THREAD_NUM * PER_THREAD
+    );
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mutex_lock(100), Err(Errno::EINVAL));
+    assert_eq!(mutex_unlock(100), Err(Errno::EINVAL));
+    let spin = mutex_create().unwrap();
+    let blocking = mutex_blocking_create().unwrap();
+    assert_ne!(spin, blocking);
+    run(spin);
+    println!("spin mutex passed!");
+    run(blocking);
+    println!("blocking mutex passed!");
+    println!("mutex_test passed!");
+    0
+}
@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, getpid, set_priority, wait, Errno};
+
+/// 每个子进程运行的时长（毫秒）
+const RUN_MS: isize = 1000;
+
+/// 以优先级 prio 空转 RUN_MS 毫秒，统计完成的循环次数。
+/// 使用步长调度（SCHED=stride）时，循环次数应大致与优先级成正比
+fn spin(prio: isize) -> ! {
+    assert_eq!(set_priority(prio), Ok(prio as usize));
+    let start = get_time();
+    let mut count: usize = 0;
+    while get_time() < start + RUN_MS {
+        for _ in 0..1000 {
+            count = count.wrapping_add(1);
+        }
+    }
+    println!("pid {} priority {} count {}", getpid(), prio, count);
+    exit(0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(set_priority(1), Err(Errno::EINVAL));
+    assert_eq!(set_priority(0), Err(Errno::EINVAL));
+    for prio in &[5, 10, 20, 40] {
+        if fork().unwrap() == 0 {
+            spin(*prio);
+        }
+    }
+    let mut exit_code: i32 = 0;
+    for _ in 0..4 {
+        assert!(wait(&mut exit_code).is_ok());
+        assert_eq!(exit_code, 0);
+    }
+    println!("priority passed!");
+    0
+}
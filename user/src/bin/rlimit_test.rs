@@ -0,0 +1,186 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, dup, exit, fork, getrlimit, mmap, pipe, read, sbrk, setrlimit, sigaction,
+    thread_create, waitpid, waittid, write, Errno, ProtFlags, RLimit, SignalAction, SignalFlags,
+    RLIMIT_AS, RLIMIT_CPU, RLIMIT_NOFILE, RLIMIT_NPROC, RLIMIT_STACK, RLIM_INFINITY, SIGKILL,
+    SIGXCPU,
+};
+
+const PAGE_SIZE: usize = 4096;
+
+/// 在子进程中执行 f，返回子进程的退出码
+fn run_in_child(f: fn()) -> i32 {
+    let pid = fork().unwrap();
+    if pid == 0 {
+        f();
+        exit(0);
+        unreachable!();
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    exit_code
+}
+
+/// 软限制不能超过硬限制，硬限制只能降低，子进程继承父进程的限制
+fn set_get_test() {
+    let old = getrlimit(RLIMIT_NOFILE).unwrap();
+    assert!(old.cur <= old.max);
+    let raised = RLimit {
+        cur: old.cur,
+        max: RLIM_INFINITY,
+    };
+    if old.max != RLIM_INFINITY {
+        assert_eq!(setrlimit(RLIMIT_NOFILE, &raised), Err(Errno::EPERM));
+    }
+    let invalid = RLimit { cur: 2, max: 1 };
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &invalid), Err(Errno::EINVAL));
+    assert_eq!(getrlimit(100), Err(Errno::EINVAL));
+    let exit_code = run_in_child(|| {
+        let limit = RLimit { cur: 16, max: 32 };
+        setrlimit(RLIMIT_NOFILE, &limit).unwrap();
+        let code = run_in_child(|| {
+            assert_eq!(getrlimit(RLIMIT_NOFILE), Ok(RLimit { cur: 16, max: 32 }));
+        });
+        assert_eq!(code, 0);
+    });
+    assert_eq!(exit_code, 0);
+    assert_eq!(getrlimit(RLIMIT_NOFILE), Ok(old));
+    println!("set_get_test passed!");
+}
+
+/// 文件描述符达到 RLIMIT_NOFILE 后返回 EMFILE
+fn nofile_test() {
+    let exit_code = run_in_child(|| {
+        let limit = RLimit { cur: 8, max: 8 };
+        setrlimit(RLIMIT_NOFILE, &limit).unwrap();
+        let mut last = 0;
+        loop {
+            match dup(0) {
+                Ok(fd) => last = fd,
+                Err(errno) => {
+                    assert_eq!(errno, Errno::EMFILE);
+                    break;
+                }
+            }
+        }
+        assert_eq!(last, 7);
+        let mut fds = [0usize; 2];
+        assert_eq!(pipe(&mut fds), Err(Errno::EMFILE));
+        close(last).unwrap();
+        assert_eq!(dup(0), Ok(last));
+    });
+    assert_eq!(exit_code, 0);
+    println!("nofile_test passed!");
+}
+
+/// 进程数达到 RLIMIT_NPROC 后 fork 返回 EAGAIN
+fn nproc_test() {
+    let exit_code = run_in_child(|| {
+        let limit = RLimit { cur: 1, max: 1 };
+        setrlimit(RLIMIT_NPROC, &limit).unwrap();
+        assert_eq!(fork(), Err(Errno::EAGAIN));
+    });
+    assert_eq!(exit_code, 0);
+    println!("nproc_test passed!");
+}
+
+/// 地址空间超过 RLIMIT_AS 后 mmap 返回 ENOMEM，堆不能再扩大
+fn as_test() {
+    let exit_code = run_in_child(|| {
+        let limit = RLimit {
+            cur: 16 * 1024 * 1024,
+            max: RLIM_INFINITY,
+        };
+        setrlimit(RLIMIT_AS, &limit).unwrap();
+        let rw = ProtFlags::READ | ProtFlags::WRITE;
+        assert_eq!(mmap(0, 32 * 1024 * 1024, rw), Err(Errno::ENOMEM));
+        assert!(mmap(0, PAGE_SIZE, rw).is_ok());
+        assert_eq!(sbrk(32 * 1024 * 1024), Err(Errno::ENOMEM));
+        assert!(sbrk(PAGE_SIZE as isize).is_ok());
+    });
+    assert_eq!(exit_code, 0);
+    println!("as_test passed!");
+}
+
+/// 使用 32KiB 栈空间的线程，默认 8KiB 的用户栈放不下
+fn deep_stack_worker(_arg: usize) {
+    let mut buf = [0u8; 32 * 1024];
+    for i in (0..buf.len()).step_by(PAGE_SIZE) {
+        unsafe {
+            (&mut buf[i] as *mut u8).write_volatile(1);
+        }
+    }
+    exit(buf[0] as i32);
+    unreachable!()
+}
+
+/// 提高 RLIMIT_STACK 后，新线程的用户栈随之变大
+fn stack_test() {
+    let exit_code = run_in_child(|| {
+        let limit = getrlimit(RLIMIT_STACK).unwrap();
+        let new_limit = RLimit {
+            cur: 64 * 1024,
+            max: limit.max,
+        };
+        setrlimit(RLIMIT_STACK, &new_limit).unwrap();
+        let tid = thread_create(deep_stack_worker as usize, 0).unwrap();
+        assert_eq!(waittid(tid), Ok(1));
+    });
+    assert_eq!(exit_code, 0);
+    println!("stack_test passed!");
+}
+
+/// SIGXCPU 处理函数向这个管道写入一个字节，告诉父进程收到了信号
+static mut XCPU_PIPE: usize = 0;
+
+fn xcpu_handler(_signum: i32) {
+    unsafe {
+        write(XCPU_PIPE, b"x").unwrap();
+    }
+}
+
+/// CPU 时间超过软限制收到 SIGXCPU，达到硬限制被 SIGKILL 杀死
+fn cpu_test() {
+    let mut fds = [0usize; 2];
+    pipe(&mut fds).unwrap();
+    unsafe {
+        XCPU_PIPE = fds[1];
+    }
+    let exit_code = run_in_child(|| {
+        let action = SignalAction::new(xcpu_handler as usize, SignalFlags::empty());
+        sigaction(SIGXCPU, Some(&action), None).unwrap();
+        let limit = RLimit { cur: 1, max: 2 };
+        setrlimit(RLIMIT_CPU, &limit).unwrap();
+        let mut count: usize = 0;
+        loop {
+            count = count.wrapping_add(1);
+            unsafe {
+                (&mut count as *mut usize).write_volatile(count);
+            }
+        }
+    });
+    assert_eq!(exit_code, -SIGKILL);
+    close(fds[1]).unwrap();
+    let mut buf = [0u8; 4];
+    assert!(read(fds[0], &mut buf).unwrap() >= 1);
+    assert_eq!(buf[0], b'x');
+    close(fds[0]).unwrap();
+    println!("cpu_test passed!");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    set_get_test();
+    nofile_test();
+    nproc_test();
+    as_test();
+    stack_test();
+    cpu_test();
+    println!("rlimit_test passed!");
+    0
+}
@@ -9,11 +9,11 @@ use user_lib::{fork, exec, wait};
 #[no_mangle]
 pub fn main() -> i32 {
     for i in 0..1000 {
-        if fork() == 0 {
-            exec("pipe_large_test\0", &[0 as *const u8]);
+        if fork().unwrap() == 0 {
+            exec("pipe_large_test\0", &[0 as *const u8]).unwrap();
         } else {
             let mut _unused: i32 = 0;
-            wait(&mut _unused);
+            wait(&mut _unused).unwrap();
             println!("Iter {} OK.", i);
         }
     }
@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, getrusage, sbrk, times, wait4, yield_, Errno, Rusage, Tms,
+    RUSAGE_CHILDREN, RUSAGE_SELF, RUSAGE_THREAD,
+};
+
+const PAGE_SIZE: usize = 4096;
+
+/// 在用户态空转 ms 毫秒
+fn spin(ms: isize) {
+    let start = get_time();
+    let mut count: usize = 0;
+    while get_time() < start + ms {
+        for _ in 0..1000 {
+            count = count.wrapping_add(1);
+        }
+    }
+}
+
+/// 空转的时间大部分计入用户态
+fn cpu_time_test() {
+    let before = getrusage(RUSAGE_SELF).unwrap();
+    spin(200);
+    let after = getrusage(RUSAGE_SELF).unwrap();
+    let utime = after.utime.as_millis() - before.utime.as_millis();
+    assert!(utime >= 100, "utime {}ms", utime);
+    // 单线程进程中，线程的统计就是进程的统计
+    let thread = getrusage(RUSAGE_THREAD).unwrap();
+    assert!(thread.utime.as_millis() >= after.utime.as_millis());
+    println!("cpu_time_test passed!");
+}
+
+/// yield 计为主动的上下文切换，第一次访问延迟分配的堆页面计为缺页
+fn counter_test() {
+    let before = getrusage(RUSAGE_SELF).unwrap();
+    for _ in 0..10 {
+        yield_();
+    }
+    let pages = 4;
+    let start = sbrk((pages * PAGE_SIZE) as isize).unwrap();
+    for i in 0..pages {
+        unsafe {
+            ((start + i * PAGE_SIZE) as *mut u8).write_volatile(1);
+        }
+    }
+    let after = getrusage(RUSAGE_SELF).unwrap();
+    assert!(after.nvcsw >= before.nvcsw + 10);
+    assert!(after.minflt >= before.minflt + pages);
+    println!("counter_test passed!");
+}
+
+/// 子进程的统计通过 wait4 返回，并计入 RUSAGE_CHILDREN 与 times
+fn children_test() {
+    let before = getrusage(RUSAGE_CHILDREN).unwrap();
+    let pid = fork().unwrap();
+    if pid == 0 {
+        spin(200);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    let mut rusage = Rusage::default();
+    assert_eq!(wait4(pid as isize, &mut exit_code, 0, &mut rusage), Ok(pid));
+    assert_eq!(exit_code, 0);
+    assert!(rusage.utime.as_millis() >= 100);
+    let after = getrusage(RUSAGE_CHILDREN).unwrap();
+    assert!(after.utime.as_millis() >= before.utime.as_millis() + rusage.utime.as_millis());
+    let mut tms = Tms::default();
+    assert!(times(&mut tms).unwrap() > 0);
+    assert!(tms.cutime >= rusage.utime.as_millis());
+    println!("children_test passed!");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    cpu_time_test();
+    counter_test();
+    children_test();
+    assert_eq!(getrusage(2).err(), Some(Errno::EINVAL));
+    println!("rusage_test passed!");
+    0
+}
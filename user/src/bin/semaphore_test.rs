@@ -0,0 +1,74 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{
+    exit, mutex_blocking_create, mutex_lock, mutex_unlock, semaphore_create, semaphore_down,
+    semaphore_up, thread_create, waittid, Errno,
+};
+
+/// 环形缓冲区大小
+const BUFFER_SIZE: usize = 4;
+const PRODUCER_NUM: usize = 3;
+const PER_PRODUCER: usize = 50;
+
+static mut BUFFER: [usize; BUFFER_SIZE] = [0; BUFFER_SIZE];
+static mut FRONT: usize = 0;
+static mut TAIL: usize = 0;
+
+/// 同步原语的 id：空槽数、已填充数、缓冲区互斥锁
+static mut SEM_EMPTY: usize = 0;
+static mut SEM_FULL: usize = 0;
+static mut MUTEX: usize = 0;
+
+fn producer(id: usize) {
+    unsafe {
+        for i in 0..PER_PRODUCER {
+            semaphore_down(SEM_EMPTY).unwrap();
+            mutex_lock(MUTEX).unwrap();
+            BUFFER[TAIL] = id * PER_PRODUCER + i;
+            TAIL = (TAIL + 1) % BUFFER_SIZE;
+            mutex_unlock(MUTEX).unwrap();
+            semaphore_up(SEM_FULL).unwrap();
+        }
+    }
+    exit(0);
+}
+
+/// 生产者-消费者：缓冲区满时生产者阻塞，空时消费者阻塞
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(semaphore_down(100), Err(Errno::EINVAL));
+    assert_eq!(semaphore_up(100), Err(Errno::EINVAL));
+    unsafe {
+        SEM_EMPTY = semaphore_create(BUFFER_SIZE).unwrap();
+        SEM_FULL = semaphore_create(0).unwrap();
+        MUTEX = mutex_blocking_create().unwrap();
+    }
+    let tids: Vec<usize> = (0..PRODUCER_NUM)
+        .map(|id| thread_create(producer as usize, id).unwrap())
+        .collect();
+    // 每个值都应恰好被消费一次
+    let mut consumed = [false; PRODUCER_NUM * PER_PRODUCER];
+    for _ in 0..PRODUCER_NUM * PER_PRODUCER {
+        unsafe {
+            semaphore_down(SEM_FULL).unwrap();
+            mutex_lock(MUTEX).unwrap();
+            let value = BUFFER[FRONT];
+            FRONT = (FRONT + 1) % BUFFER_SIZE;
+            mutex_unlock(MUTEX).unwrap();
+            semaphore_up(SEM_EMPTY).unwrap();
+            assert!(!consumed[value]);
+            consumed[value] = true;
+        }
+    }
+    for tid in tids {
+        assert_eq!(waittid(tid), Ok(0));
+    }
+    println!("semaphore_test passed!");
+    0
+}
@@ -0,0 +1,129 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, getpid, kill, mmap, sigaction, sigprocmask, sleep, waitpid, yield_, Errno,
+    ProtFlags, SignalAction, SignalFlags, SIGILL, SIGKILL, SIGSEGV, SIGSTOP, SIGUSR1, SIGUSR2,
+    SIG_IGN,
+};
+
+const PAGE_SIZE: usize = 4096;
+
+static mut HANDLED: usize = 0;
+
+fn user_sig_handler(signum: i32) {
+    println!("signal {} handled", signum);
+    unsafe {
+        HANDLED += 1;
+    }
+}
+
+fn handled() -> usize {
+    unsafe { core::ptr::read_volatile(&HANDLED) }
+}
+
+/// 处理函数返回后回到被打断的位置继续执行
+fn sig_handler_test() {
+    let action = SignalAction::new(user_sig_handler as usize, SignalFlags::empty());
+    let mut old_action = SignalAction::default();
+    assert_eq!(sigaction(SIGUSR1, Some(&action), Some(&mut old_action)), Ok(()));
+    assert_eq!(old_action.handler, 0);
+    let before = handled();
+    assert_eq!(kill(getpid() as usize, SIGUSR1), Ok(()));
+    assert_eq!(handled(), before + 1);
+    println!("sig_handler_test passed!");
+}
+
+/// 被屏蔽的信号保持待处理，解除屏蔽后才被处理
+fn sig_mask_test() {
+    let action = SignalAction::new(user_sig_handler as usize, SignalFlags::empty());
+    assert_eq!(sigaction(SIGUSR2, Some(&action), None), Ok(()));
+    let before = handled();
+    sigprocmask(SignalFlags::SIGUSR2);
+    assert_eq!(kill(getpid() as usize, SIGUSR2), Ok(()));
+    yield_();
+    assert_eq!(handled(), before);
+    sigprocmask(SignalFlags::empty());
+    assert_eq!(handled(), before + 1);
+    println!("sig_mask_test passed!");
+}
+
+/// 被忽略的信号不会终止进程；SIGKILL、SIGSTOP 的动作不能修改
+fn sig_ignore_test() {
+    let mut action = SignalAction::default();
+    action.handler = SIG_IGN;
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), Ok(()));
+    assert_eq!(kill(getpid() as usize, SIGUSR1), Ok(()));
+    assert_eq!(sigaction(SIGKILL, Some(&action), None), Err(Errno::EINVAL));
+    assert_eq!(sigaction(SIGSTOP, Some(&action), None), Err(Errno::EINVAL));
+    assert_eq!(sigaction(0, Some(&action), None), Err(Errno::EINVAL));
+    assert_eq!(kill(getpid() as usize, 0), Err(Errno::EINVAL));
+    println!("sig_ignore_test passed!");
+}
+
+/// 没有处理函数时，非法访存以 SIGSEGV 终止进程
+fn sig_segv_test() {
+    let pid = fork().unwrap();
+    if pid == 0 {
+        unsafe {
+            (0 as *mut u8).write_volatile(0);
+        }
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, -SIGSEGV);
+    println!("sig_segv_test passed!");
+}
+
+/// 被忽略的 SIGILL 仍然会终止出错的进程
+fn sig_ill_test() {
+    let pid = fork().unwrap();
+    if pid == 0 {
+        let mut action = SignalAction::default();
+        action.handler = SIG_IGN;
+        sigaction(SIGILL, Some(&action), None).unwrap();
+        // 全 0 的指令字是非法指令，匿名映射的页面由内核清零
+        let code = mmap(0, PAGE_SIZE, ProtFlags::READ | ProtFlags::EXEC).unwrap();
+        let f: fn() = unsafe { core::mem::transmute(code) };
+        f();
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, -SIGILL);
+    println!("sig_ill_test passed!");
+}
+
+/// SIGKILL 可以终止其它进程
+fn sig_kill_test() {
+    let pid = fork().unwrap();
+    if pid == 0 {
+        loop {
+            yield_();
+        }
+    }
+    sleep(10);
+    assert_eq!(kill(pid, SIGKILL), Ok(()));
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, -SIGKILL);
+    // 已退出的进程不能再接收信号
+    assert_eq!(kill(pid, SIGKILL), Err(Errno::ESRCH));
+    println!("sig_kill_test passed!");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    sig_handler_test();
+    sig_mask_test();
+    sig_ignore_test();
+    sig_segv_test();
+    sig_ill_test();
+    sig_kill_test();
+    println!("sig_tests passed!");
+    0
+}
@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, getrusage, mmap, munmap, waitpid, ProtFlags, RUSAGE_SELF};
+
+const PAGE_SIZE: usize = 4096;
+/// 5 MiB，超过 QEMU 上内核之外的全部物理内存，只有换出页面才能全部放下
+const PAGES: usize = 1280;
+/// 子进程修改的页数
+const CHILD_PAGES: usize = 16;
+
+/// 第 i 页第 j 个字的内容
+fn pattern(seed: usize, i: usize, j: usize) -> usize {
+    seed ^ (i << 20) ^ j
+}
+
+fn fill(base: usize, seed: usize, pages: usize) {
+    for i in 0..pages {
+        let page = (base + i * PAGE_SIZE) as *mut usize;
+        // 每页只写开头和结尾的几个字，避免测试耗时太久
+        for &j in [0, 1, PAGE_SIZE / 8 - 1].iter() {
+            unsafe {
+                page.add(j).write_volatile(pattern(seed, i, j));
+            }
+        }
+    }
+}
+
+fn verify(base: usize, seed: usize, pages: usize) {
+    for i in 0..pages {
+        let page = (base + i * PAGE_SIZE) as *const usize;
+        for &j in [0, 1, PAGE_SIZE / 8 - 1].iter() {
+            let value = unsafe { page.add(j).read_volatile() };
+            assert_eq!(value, pattern(seed, i, j), "page {} word {}", i, j);
+        }
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let len = PAGES * PAGE_SIZE;
+    let base = mmap(0, len, ProtFlags::READ | ProtFlags::WRITE).unwrap();
+    fill(base, 0x5a5a, PAGES);
+    verify(base, 0x5a5a, PAGES);
+    // 已换出的页面再次访问时需要从交换区读回
+    assert!(getrusage(RUSAGE_SELF).unwrap().majflt > 0);
+    println!("swap out and in passed!");
+
+    // 子进程与父进程共享已换出的页面，修改后互不影响
+    let pid = fork().unwrap();
+    if pid == 0 {
+        verify(base, 0x5a5a, PAGES);
+        fill(base, 0xa5a5, CHILD_PAGES);
+        verify(base, 0xa5a5, CHILD_PAGES);
+        exit(0);
+        unreachable!();
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 0);
+    verify(base, 0x5a5a, PAGES);
+    println!("swap after fork passed!");
+
+    munmap(base, len).unwrap();
+    println!("swap_test passed!");
+    0
+}
@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{exit, gettid, thread_create, waittid, Errno};
+
+const THREAD_NUM: usize = 3;
+const LOOP: usize = 1000;
+
+/// 线程入口，不能返回，结束时调用 exit
+pub fn worker(id: usize) {
+    for _ in 0..LOOP {
+        print!("{}", (b'a' + id as u8) as char);
+    }
+    println!("\nthread {} (tid {}) exited.", id, gettid());
+    exit(id as i32 + 1);
+    unreachable!()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(gettid(), 0);
+    // 不能等待自己，也不能等待不存在的线程
+    assert_eq!(waittid(0), Err(Errno::EDEADLK));
+    assert_eq!(waittid(100), Err(Errno::ESRCH));
+    let mut tids = Vec::new();
+    for id in 0..THREAD_NUM {
+        tids.push(thread_create(worker as usize, id).unwrap());
+    }
+    for (id, tid) in tids.iter().enumerate() {
+        let exit_code = waittid(*tid).unwrap();
+        assert_eq!(exit_code, id as i32 + 1);
+        println!("thread#{} exited with code {}", tid, exit_code);
+    }
+    println!("threads test passed!");
+    0
+}
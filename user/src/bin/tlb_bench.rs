@@ -0,0 +1,118 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, get_time, pipe, read, tlb_stats, wait, waitpid, write, yield_, TlbStats,
+};
+
+const PAGE_SIZE: usize = 4096;
+const MAX_CHILD: usize = 40;
+/// 每个子进程写入的页数，每页触发一次写时复制
+const CHILD_PAGES: usize = 4;
+/// 每个子进程让出 CPU 的次数
+const CHILD_YIELDS: usize = 8;
+/// 管道往返次数
+const ROUNDS: usize = 200;
+
+static mut BUFFER: [u8; CHILD_PAGES * PAGE_SIZE] = [0; CHILD_PAGES * PAGE_SIZE];
+
+/// 打印一个阶段的统计。清空整个 TLB 后，之后每次访问新的页都要重新查页表；
+/// 不使用 ASID 时，每次返回用户态与陷入内核都会清空一次
+fn report(name: &str, stats: &TlbStats, time: isize) {
+    println!(
+        "{}: {}ms, {} user returns, {} full flushes (without asid: {}), {} asid flushes, {} page flushes, {} shootdowns",
+        name,
+        time,
+        stats.user_returns,
+        stats.full_flushes,
+        stats.user_returns * 2,
+        stats.asid_flushes,
+        stats.page_flushes,
+        stats.shootdowns
+    );
+}
+
+/// 与 forktest 相同的负载：创建多个子进程，子进程写入若干页后多次让出 CPU
+fn fork_bench() -> TlbStats {
+    let start = get_time();
+    let before = tlb_stats().unwrap();
+    for _ in 0..MAX_CHILD {
+        let pid = fork().unwrap();
+        if pid == 0 {
+            for i in 0..CHILD_PAGES {
+                unsafe {
+                    BUFFER[i * PAGE_SIZE] = i as u8;
+                }
+            }
+            for _ in 0..CHILD_YIELDS {
+                yield_();
+            }
+            exit(0);
+            unreachable!();
+        }
+    }
+    let mut exit_code: i32 = 0;
+    for _ in 0..MAX_CHILD {
+        wait(&mut exit_code).unwrap();
+        assert_eq!(exit_code, 0);
+    }
+    let stats = tlb_stats().unwrap().since(&before);
+    report("fork", &stats, get_time() - start);
+    stats
+}
+
+/// 两个进程通过管道来回传递一个字节，每次往返都在两个地址空间之间切换
+fn pipe_bench() -> TlbStats {
+    let mut ping = [0usize; 2];
+    let mut pong = [0usize; 2];
+    pipe(&mut ping).unwrap();
+    pipe(&mut pong).unwrap();
+    let start = get_time();
+    let before = tlb_stats().unwrap();
+    let pid = fork().unwrap();
+    let mut buf = [0u8; 1];
+    if pid == 0 {
+        close(ping[1]).unwrap();
+        close(pong[0]).unwrap();
+        for _ in 0..ROUNDS {
+            assert_eq!(read(ping[0], &mut buf), Ok(1));
+            assert_eq!(write(pong[1], &buf), Ok(1));
+        }
+        exit(0);
+        unreachable!();
+    }
+    close(ping[0]).unwrap();
+    close(pong[1]).unwrap();
+    for i in 0..ROUNDS {
+        buf[0] = i as u8;
+        assert_eq!(write(ping[1], &buf), Ok(1));
+        assert_eq!(read(pong[0], &mut buf), Ok(1));
+        assert_eq!(buf[0], i as u8);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 0);
+    close(ping[1]).unwrap();
+    close(pong[0]).unwrap();
+    let stats = tlb_stats().unwrap().since(&before);
+    report("pipe", &stats, get_time() - start);
+    stats
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fork_stats = fork_bench();
+    let pipe_stats = pipe_bench();
+    for stats in [fork_stats, pipe_stats].iter() {
+        // 每个阶段至少有一次刷新了本地址空间的全部页表项（fork 后父进程的写权限被去掉）
+        assert!(stats.full_flushes + stats.asid_flushes > 0);
+        if stats.full_flushes >= stats.user_returns * 2 {
+            println!("asid is not supported, every switch flushes the whole TLB");
+        }
+    }
+    println!("tlb_bench passed!");
+    0
+}
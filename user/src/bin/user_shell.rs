@@ -14,11 +14,318 @@ const BS: u8 = 0x08u8;
 use alloc::string::String;
 use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{OpenFlags, close, dup, exec, fork, open, waitpid};
+use user_lib::{
+    env, exit, getpid, killpg, setpgid, setsid, sigaction, tcsetpgrp, waitpid_with_options,
+    wifstopped, OpenFlags, SignalAction, close, dup, exec, fork, open, SIGCONT, SIGINT, SIGTSTP,
+    SIGTTIN, SIGTTOU, SIG_IGN, WNOHANG, WUNTRACED,
+};
+
+/// 标准输入，即控制台
+const STDIN: usize = 0;
+
+/// 将参数中的 $NAME 替换为环境变量 NAME 的值，NAME 由字母、数字和下划线组成，
+/// 不存在的变量替换为空串
+fn expand_vars(arg: &str) -> String {
+    let mut result = String::new();
+    let mut chars = arg.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            result.push(c);
+            continue;
+        }
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_') {
+                break;
+            }
+            name.push(c);
+            chars.next();
+        }
+        if name.is_empty() {
+            result.push('$');
+        } else if let Some(value) = env::var(name.as_str()) {
+            result.push_str(value.as_str());
+        }
+    }
+    result
+}
+
+/// 内建命令 export KEY=VALUE，在 shell 自身中设置环境变量，之后执行的程序都会继承
+fn export(args: &[String]) {
+    for arg in args {
+        match arg.find('=') {
+            Some(pos) if pos > 0 => env::set_var(&arg[..pos], &arg[pos + 1..]),
+            _ => println!("export: invalid argument {}", arg),
+        }
+    }
+}
+
+/// 作业，每个作业是一个独立的进程组，组长即作业中唯一的进程
+struct Job {
+    /// 作业编号，fg、bg 通过 %id 指定作业
+    id: usize,
+    /// 进程 pid，同时也是进程组 id
+    pid: usize,
+    /// 命令行
+    cmd: String,
+    /// 是否被暂停
+    stopped: bool,
+}
+
+/// shell 的作业表
+struct Shell {
+    /// shell 自身的进程组 id，没有前台作业时 shell 是前台进程组
+    pgid: usize,
+    jobs: Vec<Job>,
+}
+
+impl Shell {
+    /// 成为新会话的首进程并占据控制台。shell 自己忽略作业控制相关的信号，
+    /// 子进程 exec 后这些信号会恢复默认动作
+    fn new() -> Self {
+        let pgid = match setsid() {
+            Ok(sid) => sid,
+            Err(_) => getpid() as usize,
+        };
+        let ignore = SignalAction {
+            handler: SIG_IGN,
+            ..Default::default()
+        };
+        for signum in [SIGINT, SIGTSTP, SIGTTIN, SIGTTOU].iter() {
+            sigaction(*signum, Some(&ignore), None).unwrap();
+        }
+        tcsetpgrp(STDIN, pgid).unwrap();
+        Self {
+            pgid,
+            jobs: Vec::new(),
+        }
+    }
+
+    /// 记录一个新作业，编号为当前最大编号加一
+    fn add_job(&mut self, pid: usize, cmd: &str, stopped: bool) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job {
+            id,
+            pid,
+            cmd: String::from(cmd),
+            stopped,
+        });
+        id
+    }
+
+    /// 按 fg、bg 的参数查找作业：%id 或 id，缺省时为最近的作业
+    fn find_job(&self, arg: Option<&String>) -> Option<usize> {
+        match arg {
+            None => self.jobs.len().checked_sub(1),
+            Some(arg) => {
+                let id: usize = arg.trim_start_matches('%').parse().ok()?;
+                self.jobs.iter().position(|job| job.id == id)
+            }
+        }
+    }
+
+    /// 等待前台作业 pid 退出或被暂停，之后 shell 重新成为前台进程组
+    fn wait_foreground(&mut self, pid: usize, cmd: &str) {
+        let mut exit_code: i32 = 0;
+        let exit_pid = waitpid_with_options(pid as isize, &mut exit_code, WUNTRACED);
+        assert_eq!(exit_pid, Ok(pid));
+        tcsetpgrp(STDIN, self.pgid).unwrap();
+        let index = self.jobs.iter().position(|job| job.pid == pid);
+        if wifstopped(exit_code) {
+            let id = match index {
+                Some(index) => {
+                    self.jobs[index].stopped = true;
+                    self.jobs[index].id
+                }
+                None => self.add_job(pid, cmd, true),
+            };
+            println!("[{}]  Stopped  {}", id, cmd);
+        } else {
+            if let Some(index) = index {
+                self.jobs.remove(index);
+            }
+            println!("Shell: Process {} exited with code {}", pid, exit_code);
+        }
+    }
+
+    /// 回收已结束的后台作业，并记录被暂停的后台作业，在每次显示提示符前调用
+    fn reap_jobs(&mut self) {
+        loop {
+            let mut exit_code: i32 = 0;
+            let pid = match waitpid_with_options(-1, &mut exit_code, WNOHANG | WUNTRACED) {
+                Ok(pid) if pid > 0 => pid,
+                _ => break,
+            };
+            let index = match self.jobs.iter().position(|job| job.pid == pid) {
+                Some(index) => index,
+                None => continue,
+            };
+            if wifstopped(exit_code) {
+                let job = &mut self.jobs[index];
+                job.stopped = true;
+                println!("[{}]  Stopped  {}", job.id, job.cmd);
+            } else {
+                let job = self.jobs.remove(index);
+                println!("[{}]  Done({})  {}", job.id, exit_code, job.cmd);
+            }
+        }
+    }
+
+    /// 内建命令 jobs
+    fn list_jobs(&self) {
+        for job in self.jobs.iter() {
+            let state = if job.stopped { "Stopped" } else { "Running" };
+            println!("[{}]  {}  {}", job.id, state, job.cmd);
+        }
+    }
+
+    /// 内建命令 fg：让作业继续运行并成为前台作业
+    fn foreground(&mut self, arg: Option<&String>) {
+        let index = match self.find_job(arg) {
+            Some(index) => index,
+            None => {
+                println!("fg: no such job");
+                return;
+            }
+        };
+        let job = &mut self.jobs[index];
+        job.stopped = false;
+        let (pid, cmd) = (job.pid, job.cmd.clone());
+        println!("{}", cmd);
+        let _ = tcsetpgrp(STDIN, pid);
+        let _ = killpg(pid, SIGCONT);
+        self.wait_foreground(pid, cmd.as_str());
+    }
+
+    /// 内建命令 bg：让暂停的作业在后台继续运行
+    fn background(&mut self, arg: Option<&String>) {
+        let index = match self.find_job(arg) {
+            Some(index) => index,
+            None => {
+                println!("bg: no such job");
+                return;
+            }
+        };
+        let job = &mut self.jobs[index];
+        job.stopped = false;
+        let _ = killpg(job.pid, SIGCONT);
+        println!("[{}]  {} &", job.id, job.cmd);
+    }
+
+    /// 执行一行命令。命令以 & 结尾时在后台运行
+    fn run(&mut self, line: &str) {
+        let mut args_copy: Vec<String> = line
+            .split(' ')
+            .filter(|arg| !arg.is_empty())
+            .map(expand_vars)
+            .collect();
+        let mut background = false;
+        if let Some(last) = args_copy.last_mut() {
+            if last.ends_with('&') {
+                last.pop();
+                background = true;
+                if last.is_empty() {
+                    args_copy.pop();
+                }
+            }
+        }
+        if args_copy.is_empty() {
+            return;
+        }
+        let cmd = line.trim_end_matches(|c: char| c == ' ' || c == '&');
+        match args_copy[0].as_str() {
+            "export" => return export(&args_copy[1..]),
+            "jobs" => return self.list_jobs(),
+            "fg" => return self.foreground(args_copy.get(1)),
+            "bg" => return self.background(args_copy.get(1)),
+            _ => {}
+        }
+
+        args_copy.iter_mut().for_each(|s| {
+            s.push('\0');
+        });
+
+        let mut input = String::new();
+        if let Some((idx, _)) = args_copy
+            .iter()
+            .enumerate()
+            .find(|(_, arg)| arg.as_str() == "<\0")
+        {
+            input = args_copy[idx + 1].clone();
+            args_copy.drain(idx..=idx + 1);
+        }
+
+        let mut output = String::new();
+        if let Some((idx, _)) = args_copy
+            .iter()
+            .enumerate()
+            .find(|(_, arg)| arg.as_str() == ">\0")
+        {
+            output = args_copy[idx + 1].clone();
+            args_copy.drain(idx..=idx + 1);
+        }
+
+        let mut args_addr: Vec<*const u8> = args_copy.iter().map(|arg| arg.as_ptr()).collect();
+        args_addr.push(0 as *const u8);
+        let pid = fork().unwrap();
+        if pid == 0 {
+            // 子进程自成一个进程组，前台作业同时占据控制台。
+            // 父子进程都设置一次，无论谁先运行，父进程开始等待时进程组都已设置好
+            setpgid(0, 0).unwrap();
+            if !background {
+                let _ = tcsetpgrp(STDIN, getpid() as usize);
+            }
+            // 输入重定向
+            if !input.is_empty() {
+                let input_fd = match open(input.as_str(), OpenFlags::RDONLY) {
+                    Ok(fd) => fd,
+                    Err(errno) => {
+                        println!("Error when opening file {}: {:?}", input, errno);
+                        exit(-4);
+                        unreachable!();
+                    }
+                };
+                close(0).unwrap();
+                assert_eq!(dup(input_fd), Ok(0));
+                close(input_fd).unwrap();
+            }
+            // 输出重定向
+            if !output.is_empty() {
+                let output_fd = match open(output.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY)
+                {
+                    Ok(fd) => fd,
+                    Err(errno) => {
+                        println!("Error when opening file {}: {:?}", output, errno);
+                        exit(-4);
+                        unreachable!();
+                    }
+                };
+                close(1).unwrap();
+                assert_eq!(dup(output_fd), Ok(1));
+                close(output_fd).unwrap();
+            }
+            // child process
+            if let Err(errno) = exec(args_copy[0].as_str(), args_addr.as_slice()) {
+                println!("Error when executing: {:?}", errno);
+                exit(-4);
+            }
+            unreachable!();
+        }
+        let _ = setpgid(pid, pid);
+        if background {
+            let id = self.add_job(pid, cmd, false);
+            println!("[{}] {}", id, pid);
+        } else {
+            let _ = tcsetpgrp(STDIN, pid);
+            self.wait_foreground(pid, cmd);
+        }
+    }
+}
 
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
+    let mut shell = Shell::new();
     let mut line: String = String::new();
     print!(">> ");
     loop {
@@ -27,84 +334,10 @@ pub fn main() -> i32 {
             LF | CR => {
                 println!("");
                 if !line.is_empty() {
-                    let args: Vec<_> = line.as_str().split(' ').collect();
-                    let mut args_copy: Vec<String> = args
-                        .iter()
-                        .map(|&arg| {
-                            let mut string = String::new();
-                            string.push_str(arg);
-                            string
-                        })
-                        .collect();
-
-                    args_copy.iter_mut().for_each(|s| {
-                        s.push('\0');
-                    });
-
-                    let mut input = String::new();
-                    if let Some((idx, _)) = args_copy
-                        .iter()
-                        .enumerate()
-                        .find(|(_, arg)| arg.as_str() == "<\0")
-                    {
-                        input = args_copy[idx + 1].clone();
-                        args_copy.drain(idx..=idx + 1);
-                    }
-
-                    let mut output = String::new();
-                    if let Some((idx, _)) = args_copy
-                        .iter()
-                        .enumerate()
-                        .find(|(_, arg)| arg.as_str() == ">\0")
-                    {
-                        output = args_copy[idx + 1].clone();
-                        args_copy.drain(idx..=idx + 1);
-                    }
-
-                    let mut args_addr: Vec<*const u8> =
-                        args_copy.iter().map(|arg| arg.as_ptr()).collect();
-                    args_addr.push(0 as *const u8);
-                    let pid = fork();
-                    if pid == 0 {
-                        // 输入重定向
-                        if !input.is_empty() {
-                            let input_fd = open(input.as_str(), OpenFlags::RDONLY);
-                            if input_fd == -1 {
-                                println!("Error when opening file {}", input);
-                                return -4;
-                            }
-                            let input_fd = input_fd as usize;
-                            close(0);
-                            assert_eq!(dup(input_fd), 0);
-                            close(input_fd);
-                        }
-                        // 输出重定向
-                        if !output.is_empty() {
-                            let output_fd =
-                                open(output.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY);
-                            if output_fd == -1 {
-                                println!("Error when opening file {}", output);
-                                return -4;
-                            }
-                            let output_fd = output_fd as usize;
-                            close(1);
-                            assert_eq!(dup(output_fd), 1);
-                            close(output_fd);
-                        }
-                        // child process
-                        if exec(line.as_str(), args_addr.as_slice()) == -1 {
-                            println!("Error when executing!");
-                            return -4;
-                        }
-                        unreachable!();
-                    } else {
-                        let mut exit_code: i32 = 0;
-                        let exit_pid = waitpid(pid as usize, &mut exit_code);
-                        assert_eq!(pid, exit_pid);
-                        println!("Shell: Process {} exited with code {}", pid, exit_code);
-                    }
+                    shell.run(line.as_str());
                     line.clear();
                 }
+                shell.reap_jobs();
                 print!(">> ");
             }
             BS | DL => {
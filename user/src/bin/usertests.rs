@@ -5,36 +5,66 @@
 extern crate user_lib;
 
 static TESTS: &[&str] = &[
+    "condvar_test\0",
+    "deadlock_test\0",
+    "efault_test\0",
+    "env_test\0",
     "exit\0",
     "fantastic_text\0",
     "forktest\0",
     "forktest2\0",
     "forktest_simple\0",
+    "futex_test\0",
+    "heap_grow_test\0",
     "hello_world\0",
+    "job_control_test\0",
     "matrix\0",
+    "mmap_test\0",
+    "mutex_test\0",
+    "priority\0",
+    "rlimit_test\0",
+    "rusage_test\0",
+    "sig_tests\0",
     "sleep\0",
     "sleep_simple\0",
+    "semaphore_test\0",
     "stack_overflow\0",
+    "swap_test\0",
+    "threads\0",
+    "tlb_bench\0",
+    "waitpid_test\0",
     "yield\0",
 ];
 
-use user_lib::{exec, fork, waitpid};
+use user_lib::{exec, fork, get_time, wait4, Rusage};
 
 #[no_mangle]
 pub fn main() -> i32 {
+    let start = get_time();
     for test in TESTS {
         println!("Usertests: Running {}", test);
-        let pid = fork();
+        let pid = fork().unwrap();
         if pid == 0 {
-            exec(*test, &[0 as *const u8]);
+            exec(*test, &[0 as *const u8]).unwrap();
             panic!("unreachable!");
         } else {
             let mut exit_code: i32 = Default::default();
-            let wait_pid = waitpid(pid as usize, &mut exit_code);
-            assert_eq!(pid, wait_pid);
+            let mut rusage = Rusage::default();
+            let wait_pid = wait4(pid as isize, &mut exit_code, 0, &mut rusage);
+            assert_eq!(wait_pid, Ok(pid));
             println!("\x1b[32mUsertests: Test {} in Process {} exited with code {}\x1b[0m", test, pid, exit_code);
+            println!(
+                "Usertests: {} user {}ms, sys {}ms, {} page faults, {}/{} context switches",
+                test,
+                rusage.utime.as_millis(),
+                rusage.stime.as_millis(),
+                rusage.minflt + rusage.majflt,
+                rusage.nvcsw,
+                rusage.nivcsw,
+            );
         }
     }
+    println!("Usertests: total {}ms", get_time() - start);
     println!("Usertests passed!");
     0
 }
\ No newline at end of file
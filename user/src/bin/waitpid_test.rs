@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, getpid, kill, sigaction, sleep, waitpid, waitpid_with_options, SignalAction,
+    Errno, SignalFlags, SIGUSR1, WNOHANG,
+};
+
+static mut HANDLED: usize = 0;
+
+fn user_sig_handler(_signum: i32) {
+    unsafe {
+        HANDLED += 1;
+    }
+}
+
+/// WNOHANG 不阻塞；不带选项时阻塞到子进程退出
+fn wnohang_test() {
+    let pid = fork().unwrap();
+    if pid == 0 {
+        sleep(50);
+        exit(3);
+    }
+    let mut exit_code = 0;
+    assert_eq!(
+        waitpid_with_options(pid as isize, &mut exit_code, WNOHANG),
+        Ok(0)
+    );
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 3);
+    assert_eq!(
+        waitpid_with_options(-1, &mut exit_code, WNOHANG),
+        Err(Errno::ECHILD)
+    );
+    println!("wnohang_test passed!");
+}
+
+/// 阻塞的 waitpid 被信号打断，信号处理完后继续等待
+fn interrupted_test() {
+    let action = SignalAction::new(user_sig_handler as usize, SignalFlags::empty());
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), Ok(()));
+    let parent = getpid() as usize;
+    let pid = fork().unwrap();
+    if pid == 0 {
+        sleep(20);
+        kill(parent, SIGUSR1).unwrap();
+        sleep(20);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid, &mut exit_code), Ok(pid));
+    assert_eq!(exit_code, 0);
+    assert_eq!(unsafe { core::ptr::read_volatile(&HANDLED) }, 1);
+    println!("interrupted_test passed!");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    wnohang_test();
+    interrupted_test();
+    println!("waitpid_test passed!");
+    0
+}
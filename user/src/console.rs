@@ -10,7 +10,8 @@ const STDOUT: usize = 1;
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        write(STDOUT, s.as_bytes());
+        // 标准输出被关闭时丢弃输出，与之前的行为一致，也避免 panic 时再次输出失败
+        let _ = write(STDOUT, s.as_bytes());
         Ok(())
     }
 }
@@ -36,6 +37,6 @@ macro_rules! println {
 /// 从标准输入流中读取一个字符
 pub fn getchar() -> u8 {
     let mut c = [0u8; 1];
-    read(STDIN, &mut c);
+    read(STDIN, &mut c).unwrap();
     c[0]
 }
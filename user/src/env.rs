@@ -0,0 +1,83 @@
+//! 进程的环境变量。启动时从内核放在用户栈上的 envp 中复制一份，
+//! exec 时再将当前的环境变量传给新程序
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::sync::Mutex;
+
+/// 以 "KEY=VALUE" 形式保存的环境变量
+static ENVIRON: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// 读取以空指针结尾的 envp 数组，只在 _start 中调用一次
+pub(crate) fn init(envp: usize) {
+    let mut environ = ENVIRON.lock();
+    let mut ptr = envp as *const usize;
+    loop {
+        let str_start = unsafe { ptr.read_volatile() };
+        if str_start == 0 {
+            break;
+        }
+        let len = (0usize..)
+            .find(|i| unsafe { ((str_start + *i) as *const u8).read_volatile() } == 0)
+            .unwrap();
+        let bytes = unsafe { core::slice::from_raw_parts(str_start as *const u8, len) };
+        if let Ok(entry) = core::str::from_utf8(bytes) {
+            environ.push(entry.to_string());
+        }
+        ptr = ptr.wrapping_add(1);
+    }
+}
+
+/// entry 为 "KEY=VALUE" 时返回其中的 VALUE
+fn value_of<'a>(entry: &'a str, key: &str) -> Option<&'a str> {
+    let value = entry.strip_prefix(key)?;
+    value.strip_prefix('=')
+}
+
+/// 获取环境变量 key 的值，不存在时返回 None
+pub fn var(key: &str) -> Option<String> {
+    ENVIRON
+        .lock()
+        .iter()
+        .find_map(|entry| value_of(entry, key))
+        .map(|value| value.to_string())
+}
+
+/// 设置环境变量 key 为 value，已存在时覆盖
+pub fn set_var(key: &str, value: &str) {
+    let mut environ = ENVIRON.lock();
+    let entry = alloc::format!("{}={}", key, value);
+    match environ.iter_mut().find(|entry| value_of(entry, key).is_some()) {
+        Some(old) => *old = entry,
+        None => environ.push(entry),
+    }
+}
+
+/// 删除环境变量 key
+pub fn remove_var(key: &str) {
+    ENVIRON
+        .lock()
+        .retain(|entry| value_of(entry, key).is_none());
+}
+
+/// 所有环境变量的 (KEY, VALUE) 副本
+pub fn vars() -> Vec<(String, String)> {
+    ENVIRON
+        .lock()
+        .iter()
+        .filter_map(|entry| {
+            let pos = entry.find('=')?;
+            Some((entry[..pos].to_string(), entry[pos + 1..].to_string()))
+        })
+        .collect()
+}
+
+/// 当前环境变量以 \0 结尾的副本，供 exec 构造 envp 数组
+pub(crate) fn environ_strings() -> Vec<String> {
+    ENVIRON
+        .lock()
+        .iter()
+        .map(|entry| alloc::format!("{}\0", entry))
+        .collect()
+}
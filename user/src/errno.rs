@@ -0,0 +1,57 @@
+//! 系统调用的错误码，数值与内核的 SysError 一致
+
+/// 系统调用失败时返回的错误码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Errno(pub isize);
+
+impl Errno {
+    /// 操作不允许
+    pub const EPERM: Errno = Errno(1);
+    /// 文件不存在
+    pub const ENOENT: Errno = Errno(2);
+    /// 进程或线程不存在
+    pub const ESRCH: Errno = Errno(3);
+    /// 被信号打断
+    pub const EINTR: Errno = Errno(4);
+    /// 输入输出错误
+    pub const EIO: Errno = Errno(5);
+    /// 参数过长
+    pub const E2BIG: Errno = Errno(7);
+    /// 不是合法的可执行文件
+    pub const ENOEXEC: Errno = Errno(8);
+    /// 文件描述符不合法或不支持该读写方向
+    pub const EBADF: Errno = Errno(9);
+    /// 没有符合条件的子进程
+    pub const ECHILD: Errno = Errno(10);
+    /// 资源暂时不可用，稍后重试
+    pub const EAGAIN: Errno = Errno(11);
+    /// 内存不足
+    pub const ENOMEM: Errno = Errno(12);
+    /// 地址不可访问
+    pub const EFAULT: Errno = Errno(14);
+    /// 已经存在
+    pub const EEXIST: Errno = Errno(17);
+    /// 参数不合法
+    pub const EINVAL: Errno = Errno(22);
+    /// 打开的文件过多
+    pub const EMFILE: Errno = Errno(24);
+    /// 不是终端
+    pub const ENOTTY: Errno = Errno(25);
+    /// 存储空间不足
+    pub const ENOSPC: Errno = Errno(28);
+    /// 会导致死锁
+    pub const EDEADLK: Errno = Errno(35);
+    /// 不支持的系统调用
+    pub const ENOSYS: Errno = Errno(38);
+    /// 等待超时
+    pub const ETIMEDOUT: Errno = Errno(110);
+
+    /// 将系统调用的返回值转换为 Result，负数为错误码
+    pub fn check(ret: isize) -> Result<usize, Errno> {
+        if ret < 0 {
+            Err(Errno(-ret))
+        } else {
+            Ok(ret as usize)
+        }
+    }
+}
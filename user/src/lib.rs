@@ -9,19 +9,52 @@ extern crate bitflags;
 
 extern crate alloc;
 
+use alloc::string::String;
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::NonNull;
+use core::sync::atomic::AtomicU32;
 use syscall::*;
 
-/// 用户堆空间大小
-const USER_HEAP_SIZE: usize = 16384;
+pub use errno::Errno;
 
-/// 使用编译好的数组作为用户空间的堆
-static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
+/// 用户堆每次向内核申请扩展的最小字节数
+const USER_HEAP_GROW_SIZE: usize = 16384;
+
+/// 可增长的用户堆：空间不足时通过 sbrk 向内核申请更多内存，再交给伙伴分配器管理
+struct GrowableHeap(LockedHeap);
+
+unsafe impl GlobalAlloc for GrowableHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        if let Ok(ptr) = heap.alloc(layout) {
+            return ptr.as_ptr();
+        }
+        // 伙伴分配器按 2 的幂对齐分块，多申请一倍才能保证新空间中有一块满足要求
+        let block = layout
+            .size()
+            .max(layout.align())
+            .next_power_of_two()
+            .max(USER_HEAP_GROW_SIZE);
+        let start = match sbrk((block * 2) as isize) {
+            Ok(start) => start,
+            Err(_) => return core::ptr::null_mut(),
+        };
+        heap.add_to_heap(start, start + block * 2);
+        heap.alloc(layout)
+            .map_or(core::ptr::null_mut(), |ptr| ptr.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout)
+    }
+}
 
 /// 全局分配器，内核和用户空间各一个。有了这个分配器，内核才可以使用动态数据类型
+/// 堆空间在第一次分配时才向内核申请
 #[global_allocator]
-static HEAP: LockedHeap = LockedHeap::empty();
+static HEAP: GrowableHeap = GrowableHeap(LockedHeap::empty());
 
 /// 必须要指定全局分配出错时的 handler
 #[alloc_error_handler]
@@ -29,20 +62,19 @@ pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
     panic!("Heap allocation error, layout = {:?}", layout);
 }
 
+pub mod env;
+mod errno;
 mod syscall;
+pub mod sync;
 #[macro_use] // 使 console 中定义的宏能被此 crate 外使用，比如 bin 下面的各应用
 pub mod console;
 mod lang_items;
 
 #[no_mangle] // 不允许编译器混淆
 #[link_section = ".text.entry"] // 告知编译器将此函数放到 .text.entry 节，成为用户程序入口
-pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
+pub extern "C" fn _start(argc: usize, argv: usize, envp: usize) -> ! {
     // 操作系统负责初始化用户程序的 .bss 区间
     // clear_bss(); // 系统还不具有清零 .bss 的能力，需要应用程序自己做
-    unsafe {
-        HEAP.lock()
-            .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
-    }
     println!("user lib run now!!!");
     let mut v: Vec<&'static str> = Vec::new();
     for i in 0..argc {
@@ -59,6 +91,7 @@ pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
                 .unwrap(),
         )
     }
+    env::init(envp);
     exit(main(argc, v.as_slice())); // 调用用户库的 exit 方法
     panic!("unreachable after sys_exit!");
 }
@@ -81,8 +114,8 @@ fn clear_bss() {
     })
 }
 
-pub fn write(fd: usize, buf: &[u8]) -> isize {
-    sys_write(fd, buf)
+pub fn write(fd: usize, buf: &[u8]) -> Result<usize, Errno> {
+    Errno::check(sys_write(fd, buf))
 }
 
 pub fn exit(xstate: i32) -> isize {
@@ -97,66 +130,236 @@ pub fn get_time() -> isize {
     sys_get_time()
 }
 
-pub fn dup(fd: usize) -> isize {
-    sys_dup(fd)
+/// 时间，与 Linux 的 struct timeval 一致
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimeVal {
+    pub sec: usize,
+    pub usec: usize,
+}
+
+impl TimeVal {
+    /// 换算为毫秒
+    pub fn as_millis(&self) -> usize {
+        self.sec * 1000 + self.usec / 1000
+    }
+}
+
+/// 资源使用情况，内存布局与内核一致
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rusage {
+    /// 用户态 CPU 时间
+    pub utime: TimeVal,
+    /// 内核态 CPU 时间
+    pub stime: TimeVal,
+    /// 不需要读取磁盘的缺页次数
+    pub minflt: usize,
+    /// 需要读取磁盘的缺页次数
+    pub majflt: usize,
+    /// 主动让出 CPU 的次数
+    pub nvcsw: usize,
+    /// 被抢占的次数
+    pub nivcsw: usize,
+}
+
+/// times 返回的 CPU 时间，单位为毫秒，内存布局与内核一致
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Tms {
+    /// 进程的用户态时间
+    pub utime: usize,
+    /// 进程的内核态时间
+    pub stime: usize,
+    /// 已回收子进程的用户态时间之和
+    pub cutime: usize,
+    /// 已回收子进程的内核态时间之和
+    pub cstime: usize,
+}
+
+/// getrusage 统计当前进程的所有线程
+pub const RUSAGE_SELF: isize = 0;
+/// getrusage 统计已回收的子进程
+pub const RUSAGE_CHILDREN: isize = -1;
+/// getrusage 只统计当前线程
+pub const RUSAGE_THREAD: isize = 1;
+
+/// 获取当前进程及已回收子进程的 CPU 时间，返回开机以来的毫秒数
+pub fn times(tms: &mut Tms) -> Result<usize, Errno> {
+    Errno::check(sys_times(tms))
+}
+
+/// 获取 who 的资源使用情况
+pub fn getrusage(who: isize) -> Result<Rusage, Errno> {
+    let mut rusage = Rusage::default();
+    Errno::check(sys_getrusage(who, &mut rusage))?;
+    Ok(rusage)
+}
+
+/// 资源限制，内存布局与内核一致
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RLimit {
+    /// 软限制，即实际生效的限制
+    pub cur: usize,
+    /// 硬限制，软限制不能超过它，且只能降低
+    pub max: usize,
+}
+
+/// CPU 时间（秒），超过软限制后每秒收到 SIGXCPU，达到硬限制时收到 SIGKILL
+pub const RLIMIT_CPU: usize = 0;
+/// 新线程及 exec 后的用户栈大小（字节）
+pub const RLIMIT_STACK: usize = 3;
+/// 系统中的进程数，达到后 fork 返回 EAGAIN
+pub const RLIMIT_NPROC: usize = 6;
+/// 文件描述符上限，达到后返回 EMFILE
+pub const RLIMIT_NOFILE: usize = 7;
+/// 地址空间大小（字节），超过后 mmap、exec、创建线程返回 ENOMEM，sbrk 失败
+pub const RLIMIT_AS: usize = 9;
+/// 不限制
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// 获取当前进程对资源 resource 的限制
+pub fn getrlimit(resource: usize) -> Result<RLimit, Errno> {
+    let mut rlimit = RLimit::default();
+    Errno::check(sys_getrlimit(resource, &mut rlimit))?;
+    Ok(rlimit)
+}
+
+/// 设置当前进程对资源 resource 的限制
+pub fn setrlimit(resource: usize, rlimit: &RLimit) -> Result<(), Errno> {
+    Errno::check(sys_setrlimit(resource, rlimit)).map(|_| ())
+}
+
+pub fn dup(fd: usize) -> Result<usize, Errno> {
+    Errno::check(sys_dup(fd))
+}
+
+/// waitpid_with_options 的选项：子进程都还在运行时不阻塞
+pub const WNOHANG: usize = 1;
+/// waitpid_with_options 的选项：同时报告因信号暂停的子进程
+pub const WUNTRACED: usize = 2;
+
+/// 使用 WUNTRACED 时，退出码是否表示子进程被信号暂停
+pub fn wifstopped(exit_code: i32) -> bool {
+    exit_code & 0xff == 0x7f
+}
+
+/// 子进程被暂停时，使其暂停的信号
+pub fn wstopsig(exit_code: i32) -> i32 {
+    (exit_code >> 8) & 0xff
 }
 
 /// 等待任意子进程退出
-pub fn wait(exit_code: &mut i32) -> isize {
+pub fn wait(exit_code: &mut i32) -> Result<usize, Errno> {
+    waitpid_with_options(-1, exit_code, 0)
+}
+
+/// 等待指定子进程退出。
+pub fn waitpid(pid: usize, exit_code: &mut i32) -> Result<usize, Errno> {
+    waitpid_with_options(pid as isize, exit_code, 0)
+}
+
+/// 等待子进程退出，pid 为 -1 表示任意子进程，返回结束的子进程 ID。
+/// options 包含 WNOHANG 时不阻塞，子进程都还在运行则返回 0；没有符合条件的子进程返回 ECHILD。
+/// options 包含 WUNTRACED 时子进程被暂停也会返回，此时 wifstopped(exit_code) 为真
+pub fn waitpid_with_options(
+    pid: isize,
+    exit_code: &mut i32,
+    options: usize,
+) -> Result<usize, Errno> {
     loop {
-        match sys_waitpid(-1, exit_code) {
-            -2 => {
-                // 如果返回 -2， 表示有子进程但进程未结束
-                yield_(); // 主动释放 CPU
-            }
-            exit_pid => return exit_pid, // 结束的子进程 ID
+        match Errno::check(sys_waitpid(pid, exit_code, options, core::ptr::null_mut())) {
+            // 阻塞等待被信号打断，信号处理完后重新等待
+            Err(Errno::EINTR) => continue,
+            ret => return ret,
         }
     }
 }
 
-/// 等待指定子进程退出。
-pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
+/// 与 waitpid_with_options 相同，同时通过 rusage 返回子进程（含其已回收的后代）的资源使用
+pub fn wait4(
+    pid: isize,
+    exit_code: &mut i32,
+    options: usize,
+    rusage: &mut Rusage,
+) -> Result<usize, Errno> {
     loop {
-        match sys_waitpid(pid as isize, exit_code) {
-            -2 => {
-                sys_yield();
-            }
-            exit_pid => return exit_pid,
+        match Errno::check(sys_waitpid(pid, exit_code, options, rusage)) {
+            Err(Errno::EINTR) => continue,
+            ret => return ret,
         }
     }
 }
 
+/// 睡眠 period_ms 毫秒。被信号打断后继续睡眠剩余的时间
 pub fn sleep(period_ms: usize) {
-    let start = sys_get_time();
-    while sys_get_time() < start + period_ms as isize {
-        sys_yield();
+    let expire_ms = sys_get_time() + period_ms as isize;
+    loop {
+        let now = sys_get_time();
+        if now >= expire_ms {
+            break;
+        }
+        sys_sleep((expire_ms - now) as usize);
+    }
+}
+
+/// 将堆顶设置为 addr，addr 为 0 时查询当前堆顶。返回调整后的堆顶
+pub fn brk(addr: usize) -> usize {
+    sys_brk(addr) as usize
+}
+
+/// 将堆顶移动 increment 字节，返回原来的堆顶；内存不足时返回 ENOMEM
+pub fn sbrk(increment: isize) -> Result<usize, Errno> {
+    let old = brk(0);
+    if increment == 0 {
+        return Ok(old);
     }
+    let new = (old as isize + increment) as usize;
+    if brk(new) != new {
+        return Err(Errno::ENOMEM);
+    }
+    Ok(old)
 }
 
 /// fork 一个新进程。返回 0 表示子进程，>0 表示父进程。
-pub fn fork() -> isize {
-    sys_fork()
+pub fn fork() -> Result<usize, Errno> {
+    Errno::check(sys_fork())
+}
+
+/// 从文件中读取数据。等待输入时被信号打断，信号处理完后重新读取
+pub fn read(fd: usize, buf: &mut [u8]) -> Result<usize, Errno> {
+    loop {
+        match Errno::check(sys_read(fd, buf)) {
+            Err(Errno::EINTR) => continue,
+            ret => return ret,
+        }
+    }
 }
 
-/// 从文件中读取数据
-pub fn read(fd: usize, buf: &mut [u8]) -> isize {
-    sys_read(fd, buf)
+/// 执行程序 path，替换当前进程的地址空间，新程序继承当前的环境变量。成功时不会返回
+pub fn exec(path: &str, args: &[*const u8]) -> Result<usize, Errno> {
+    let environ: Vec<String> = env::environ_strings();
+    let mut envp: Vec<*const u8> = environ.iter().map(|entry| entry.as_ptr()).collect();
+    envp.push(core::ptr::null());
+    execve(path, args, envp.as_slice())
 }
 
-pub fn exec(path: &str, args: &[*const u8]) -> isize {
-    sys_exec(path, args)
+/// 执行程序 path，并以 envp（"KEY=VALUE\0" 字符串指针数组，以空指针结尾）作为其环境变量
+pub fn execve(path: &str, args: &[*const u8], envp: &[*const u8]) -> Result<usize, Errno> {
+    Errno::check(sys_execve(path, args, envp))
 }
 
 pub fn getpid() -> isize {
     sys_getpid()
 }
 
-pub fn pipe(pipe_fd: &mut [usize]) -> isize {
-    sys_pipe(pipe_fd)
+pub fn pipe(pipe_fd: &mut [usize]) -> Result<(), Errno> {
+    Errno::check(sys_pipe(pipe_fd)).map(|_| ())
 }
 
-pub fn close(fd: usize) -> isize {
-    sys_close(fd)
+pub fn close(fd: usize) -> Result<(), Errno> {
+    Errno::check(sys_close(fd)).map(|_| ())
 }
 
 bitflags! {
@@ -169,6 +372,371 @@ bitflags! {
     }
 }
 
-pub fn open(path: &str, flags: OpenFlags) -> isize {
-    sys_open(path, flags.bits)
+pub fn open(path: &str, flags: OpenFlags) -> Result<usize, Errno> {
+    Errno::check(sys_open(path, flags.bits))
+}
+
+bitflags! {
+    /// mmap/mprotect 的访问权限
+    pub struct ProtFlags: usize {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+/// 映射一段匿名内存，start 为 0 时由内核选择地址。成功返回起始地址
+pub fn mmap(start: usize, len: usize, prot: ProtFlags) -> Result<usize, Errno> {
+    Errno::check(sys_mmap(start, len, prot.bits))
+}
+
+pub fn munmap(start: usize, len: usize) -> Result<(), Errno> {
+    Errno::check(sys_munmap(start, len)).map(|_| ())
+}
+
+pub fn mprotect(start: usize, len: usize, prot: ProtFlags) -> Result<(), Errno> {
+    Errno::check(sys_mprotect(start, len, prot.bits)).map(|_| ())
+}
+
+/// 创建线程，从 entry 开始执行，arg 作为其参数。线程函数不能返回，需要调用 exit 结束
+pub fn thread_create(entry: usize, arg: usize) -> Result<usize, Errno> {
+    Errno::check(sys_thread_create(entry, arg))
+}
+
+pub fn gettid() -> isize {
+    sys_gettid()
+}
+
+/// 等待线程 tid 退出并返回其退出码，线程不存在时返回 ESRCH
+pub fn waittid(tid: usize) -> Result<i32, Errno> {
+    let mut exit_code = 0;
+    loop {
+        match Errno::check(sys_waittid(tid, &mut exit_code)) {
+            Err(Errno::EAGAIN) => {
+                yield_();
+            }
+            ret => return ret.map(|_| exit_code),
+        }
+    }
+}
+
+/// 创建自旋互斥锁，获取不到锁时让出 CPU 后重试。返回互斥锁 id
+pub fn mutex_create() -> Result<usize, Errno> {
+    Errno::check(sys_mutex_create(false))
+}
+
+/// 创建阻塞互斥锁，获取不到锁时阻塞等待。返回互斥锁 id
+pub fn mutex_blocking_create() -> Result<usize, Errno> {
+    Errno::check(sys_mutex_create(true))
+}
+
+/// 获取互斥锁，互斥锁不存在时返回 EINVAL
+pub fn mutex_lock(mutex_id: usize) -> Result<(), Errno> {
+    loop {
+        match Errno::check(sys_mutex_lock(mutex_id)) {
+            // 被信号打断，信号处理完后重新获取
+            Err(Errno::EINTR) => continue,
+            ret => return ret.map(|_| ()),
+        }
+    }
+}
+
+pub fn mutex_unlock(mutex_id: usize) -> Result<(), Errno> {
+    Errno::check(sys_mutex_unlock(mutex_id)).map(|_| ())
+}
+
+/// 创建初始资源数为 res_count 的信号量。返回信号量 id
+pub fn semaphore_create(res_count: usize) -> Result<usize, Errno> {
+    Errno::check(sys_semaphore_create(res_count))
+}
+
+pub fn semaphore_up(sem_id: usize) -> Result<(), Errno> {
+    Errno::check(sys_semaphore_up(sem_id)).map(|_| ())
+}
+
+/// 获取信号量的一个资源，信号量不存在时返回 EINVAL
+pub fn semaphore_down(sem_id: usize) -> Result<(), Errno> {
+    loop {
+        match Errno::check(sys_semaphore_down(sem_id)) {
+            Err(Errno::EINTR) => continue,
+            ret => return ret.map(|_| ()),
+        }
+    }
+}
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+
+/// futex 值仍等于 val 时阻塞，直到被 futex_wake 唤醒。值已改变时返回 EAGAIN，
+/// 被信号打断时返回 EINTR，调用方需要重新检查
+pub fn futex_wait(futex: &AtomicU32, val: u32) -> Result<(), Errno> {
+    futex_wait_timeout(futex, val, 0)
+}
+
+/// 与 futex_wait 相同，但最多等待 timeout_ms 毫秒（为 0 时不超时），超时返回 ETIMEDOUT
+pub fn futex_wait_timeout(futex: &AtomicU32, val: u32, timeout_ms: usize) -> Result<(), Errno> {
+    Errno::check(sys_futex(
+        futex as *const AtomicU32 as *const u32,
+        FUTEX_WAIT,
+        val,
+        timeout_ms,
+    ))
+    .map(|_| ())
+}
+
+/// 最多唤醒 count 个在 futex 上等待的线程，返回唤醒的线程数
+pub fn futex_wake(futex: &AtomicU32, count: u32) -> Result<usize, Errno> {
+    Errno::check(sys_futex(
+        futex as *const AtomicU32 as *const u32,
+        FUTEX_WAKE,
+        count,
+        0,
+    ))
+}
+
+/// 开启或关闭当前进程的死锁检测。开启后，会导致死锁的 mutex_lock、semaphore_down 返回 EDEADLK
+pub fn enable_deadlock_detect(enabled: bool) -> Result<(), Errno> {
+    Errno::check(sys_enable_deadlock_detect(enabled as usize)).map(|_| ())
+}
+
+/// 开机以来的 TLB 刷新统计，内存布局与内核一致
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TlbStats {
+    /// 返回用户态的次数
+    pub user_returns: usize,
+    /// 清空整个 TLB 的次数
+    pub full_flushes: usize,
+    /// 刷新一个 ASID 全部页表项的次数
+    pub asid_flushes: usize,
+    /// 逐页刷新的页数
+    pub page_flushes: usize,
+    /// 发送核间中断的次数
+    pub shootdowns: usize,
+}
+
+impl TlbStats {
+    /// 从 earlier 到现在的增量
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            user_returns: self.user_returns - earlier.user_returns,
+            full_flushes: self.full_flushes - earlier.full_flushes,
+            asid_flushes: self.asid_flushes - earlier.asid_flushes,
+            page_flushes: self.page_flushes - earlier.page_flushes,
+            shootdowns: self.shootdowns - earlier.shootdowns,
+        }
+    }
+}
+
+/// 获取开机以来的 TLB 刷新统计
+pub fn tlb_stats() -> Result<TlbStats, Errno> {
+    let mut stats = TlbStats::default();
+    Errno::check(sys_tlb_stats(&mut stats))?;
+    Ok(stats)
+}
+
+/// 创建条件变量。返回条件变量 id
+pub fn condvar_create() -> Result<usize, Errno> {
+    Errno::check(sys_condvar_create())
+}
+
+pub fn condvar_signal(condvar_id: usize) -> Result<(), Errno> {
+    Errno::check(sys_condvar_signal(condvar_id)).map(|_| ())
+}
+
+/// 释放互斥锁并等待条件变量，返回时重新持有互斥锁。可能被虚假唤醒，调用方需要重新检查条件
+pub fn condvar_wait(condvar_id: usize, mutex_id: usize) -> Result<(), Errno> {
+    match Errno::check(sys_condvar_wait(condvar_id, mutex_id)) {
+        // 重新获取互斥锁时被信号打断
+        Err(Errno::EINTR) => mutex_lock(mutex_id),
+        ret => ret.map(|_| ()),
+    }
+}
+
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+pub const SIGQUIT: i32 = 3;
+pub const SIGILL: i32 = 4;
+pub const SIGTRAP: i32 = 5;
+pub const SIGABRT: i32 = 6;
+pub const SIGBUS: i32 = 7;
+pub const SIGFPE: i32 = 8;
+pub const SIGKILL: i32 = 9;
+pub const SIGUSR1: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+pub const SIGUSR2: i32 = 12;
+pub const SIGPIPE: i32 = 13;
+pub const SIGALRM: i32 = 14;
+pub const SIGTERM: i32 = 15;
+pub const SIGSTKFLT: i32 = 16;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+pub const SIGSTOP: i32 = 19;
+pub const SIGTSTP: i32 = 20;
+pub const SIGTTIN: i32 = 21;
+pub const SIGTTOU: i32 = 22;
+pub const SIGURG: i32 = 23;
+pub const SIGXCPU: i32 = 24;
+pub const SIGXFSZ: i32 = 25;
+pub const SIGVTALRM: i32 = 26;
+pub const SIGPROF: i32 = 27;
+pub const SIGWINCH: i32 = 28;
+pub const SIGIO: i32 = 29;
+pub const SIGPWR: i32 = 30;
+pub const SIGSYS: i32 = 31;
+
+/// 默认处理方式
+pub const SIG_DFL: usize = 0;
+/// 忽略信号
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// 信号集合，第 n 位表示编号为 n 的信号
+    pub struct SignalFlags: u32 {
+        const SIGHUP = 1 << SIGHUP;
+        const SIGINT = 1 << SIGINT;
+        const SIGQUIT = 1 << SIGQUIT;
+        const SIGILL = 1 << SIGILL;
+        const SIGTRAP = 1 << SIGTRAP;
+        const SIGABRT = 1 << SIGABRT;
+        const SIGBUS = 1 << SIGBUS;
+        const SIGFPE = 1 << SIGFPE;
+        const SIGKILL = 1 << SIGKILL;
+        const SIGUSR1 = 1 << SIGUSR1;
+        const SIGSEGV = 1 << SIGSEGV;
+        const SIGUSR2 = 1 << SIGUSR2;
+        const SIGPIPE = 1 << SIGPIPE;
+        const SIGALRM = 1 << SIGALRM;
+        const SIGTERM = 1 << SIGTERM;
+        const SIGSTKFLT = 1 << SIGSTKFLT;
+        const SIGCHLD = 1 << SIGCHLD;
+        const SIGCONT = 1 << SIGCONT;
+        const SIGSTOP = 1 << SIGSTOP;
+        const SIGTSTP = 1 << SIGTSTP;
+        const SIGTTIN = 1 << SIGTTIN;
+        const SIGTTOU = 1 << SIGTTOU;
+        const SIGURG = 1 << SIGURG;
+        const SIGXCPU = 1 << SIGXCPU;
+        const SIGXFSZ = 1 << SIGXFSZ;
+        const SIGVTALRM = 1 << SIGVTALRM;
+        const SIGPROF = 1 << SIGPROF;
+        const SIGWINCH = 1 << SIGWINCH;
+        const SIGIO = 1 << SIGIO;
+        const SIGPWR = 1 << SIGPWR;
+        const SIGSYS = 1 << SIGSYS;
+    }
+}
+
+/// 信号处理动作，内存布局与内核一致
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalAction {
+    /// 处理函数地址，也可以是 SIG_DFL 或 SIG_IGN。处理函数的参数为信号编号
+    pub handler: usize,
+    /// 处理信号期间额外屏蔽的信号
+    pub mask: SignalFlags,
+    /// 处理函数返回后跳转的地址
+    pub restorer: usize,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+            restorer: 0,
+        }
+    }
+}
+
+impl SignalAction {
+    /// 以 handler 处理信号，处理函数返回后自动调用 sigreturn
+    pub fn new(handler: usize, mask: SignalFlags) -> Self {
+        Self {
+            handler,
+            mask,
+            restorer: sig_restorer as usize,
+        }
+    }
+}
+
+/// 信号处理函数返回时跳转到这里，恢复被信号打断前的执行流
+fn sig_restorer() {
+    sys_sigreturn();
+    unreachable!()
+}
+
+pub fn kill(pid: usize, signum: i32) -> Result<(), Errno> {
+    Errno::check(sys_kill(pid as isize, signum)).map(|_| ())
+}
+
+/// 向进程组 pgid 中的所有进程发送信号
+pub fn killpg(pgid: usize, signum: i32) -> Result<(), Errno> {
+    Errno::check(sys_kill(-(pgid as isize), signum)).map(|_| ())
+}
+
+/// 将进程 pid 加入进程组 pgid。pid 为 0 表示当前进程，pgid 为 0 表示新建以 pid 为 id 的进程组
+pub fn setpgid(pid: usize, pgid: usize) -> Result<(), Errno> {
+    Errno::check(sys_setpgid(pid, pgid)).map(|_| ())
+}
+
+/// 进程 pid 的进程组 id，pid 为 0 表示当前进程
+pub fn getpgid(pid: usize) -> Result<usize, Errno> {
+    Errno::check(sys_getpgid(pid))
+}
+
+/// 进程 pid 的会话 id，pid 为 0 表示当前进程
+pub fn getsid(pid: usize) -> Result<usize, Errno> {
+    Errno::check(sys_getsid(pid))
+}
+
+/// 新建会话，返回会话 id。已经是进程组组长时返回 EPERM
+pub fn setsid() -> Result<usize, Errno> {
+    Errno::check(sys_setsid())
+}
+
+/// ioctl 命令：获取终端的前台进程组
+const TIOCGPGRP: usize = 0x540f;
+/// ioctl 命令：设置终端的前台进程组
+const TIOCSPGRP: usize = 0x5410;
+
+/// 终端 fd 的前台进程组，没有前台进程组时为 0
+pub fn tcgetpgrp(fd: usize) -> Result<usize, Errno> {
+    let mut pgid: i32 = 0;
+    Errno::check(sys_ioctl(fd, TIOCGPGRP, &mut pgid as *mut i32 as usize))?;
+    Ok(pgid as usize)
+}
+
+/// 将终端 fd 的前台进程组设为 pgid，Ctrl-C、Ctrl-Z 产生的信号会发给前台进程组
+pub fn tcsetpgrp(fd: usize, pgid: usize) -> Result<(), Errno> {
+    let pgid = pgid as i32;
+    Errno::check(sys_ioctl(fd, TIOCSPGRP, &pgid as *const i32 as usize)).map(|_| ())
+}
+
+/// 设置信号 signum 的处理动作，old_action 不为 None 时返回原来的动作
+pub fn sigaction(
+    signum: i32,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> Result<(), Errno> {
+    Errno::check(sys_sigaction(
+        signum,
+        action.map_or(core::ptr::null(), |action| action as *const _),
+        old_action.map_or(core::ptr::null_mut(), |old_action| old_action as *mut _),
+    ))
+    .map(|_| ())
+}
+
+/// 设置信号屏蔽字，返回原来的屏蔽字
+pub fn sigprocmask(mask: SignalFlags) -> SignalFlags {
+    SignalFlags::from_bits_truncate(sys_sigprocmask(mask.bits) as u32)
+}
+
+pub fn sigreturn() -> isize {
+    sys_sigreturn()
+}
+
+/// 设置当前线程的优先级，优先级越高分到的 CPU 时间越多
+pub fn set_priority(prio: isize) -> Result<usize, Errno> {
+    Errno::check(sys_set_priority(prio))
 }
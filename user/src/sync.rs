@@ -0,0 +1,83 @@
+//! 基于 futex 的用户态同步原语，没有竞争时不需要陷入内核
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use super::{futex_wait, futex_wake};
+
+/// 未加锁
+const UNLOCKED: u32 = 0;
+/// 已加锁，没有线程在等待
+const LOCKED: u32 = 1;
+/// 已加锁，可能有线程在等待，释放时需要唤醒
+const CONTENDED: u32 = 2;
+
+/// 互斥锁。加锁与解锁在没有竞争时只需要一次原子操作，
+/// 只有获取不到锁时才通过 futex 阻塞，释放时有等待者才唤醒
+pub struct Mutex<T> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Mutex<T> {}
+unsafe impl<T: Send> Send for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<T> {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            self.lock_contended();
+        }
+        MutexGuard { mutex: self }
+    }
+
+    /// 有竞争时将状态置为 CONTENDED 并等待，直到换出的状态为 UNLOCKED 即获得锁。
+    /// 获得锁后状态保持 CONTENDED，释放时会多一次不必要的唤醒，但不会漏掉等待者
+    fn lock_contended(&self) {
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            // 值已改变或被信号打断时直接重试
+            let _ = futex_wait(&self.state, CONTENDED);
+        }
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            let _ = futex_wake(&self.state, 1);
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
@@ -1,9 +1,17 @@
+use super::{RLimit, Rusage, SignalAction, TlbStats, Tms};
+
+/// 只需要前三个参数的系统调用，其余参数寄存器置 0
 pub fn syscall(id: usize, args: [usize; 3]) -> isize {
+    syscall6(id, [args[0], args[1], args[2], 0, 0, 0])
+}
+
+/// 最多有六个参数的系统调用
+pub fn syscall6(id: usize, args: [usize; 6]) -> isize {
     let mut ret: isize;
     // 编译器无法判定 asm 是否安全，所以需要用 unsafe 包装起来
     unsafe {
         // risc-V 规定，使用 ecall 完成系统调用
-        // 使用 a0~a6(x10~x16) 传递参数，a0(x17) 传递 syscall id
+        // 使用 a0~a5(x10~x15) 传递参数，a7(x17) 传递 syscall id
         // 使用 a0~a1 传递返回值
         /*
         llvm_asm!(assembly template
@@ -15,8 +23,9 @@ pub fn syscall(id: usize, args: [usize; 3]) -> isize {
             */
         llvm_asm!("ecall"
             : "={x10}" (ret) // 只有一个输出，使用 a0 传递，输出须用 = 开头
-            // 输入为 a0~a6，用 a7 指定调用号。{} 用于将寄存器和变量联系起来
-            : "{x10}" (args[0]), "{x11}" (args[1]), "{x12}" (args[2]), "{x17}" (id)
+            // 输入为 a0~a5，用 a7 指定调用号。{} 用于将寄存器和变量联系起来
+            : "{x10}" (args[0]), "{x11}" (args[1]), "{x12}" (args[2]), "{x13}" (args[3]),
+              "{x14}" (args[4]), "{x15}" (args[5]), "{x17}" (id)
             : "memory" // 告知编译器本汇编代码会修改内存
             : "volatile" // 告知编译器需要将此汇编代码原样放在输出文件中，即不做任何优化
         );
@@ -25,18 +34,52 @@ pub fn syscall(id: usize, args: [usize; 3]) -> isize {
 }
 
 const SYSCALL_DUP: usize = 24;
+const SYSCALL_IOCTL: usize = 29;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE: usize = 59;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_FUTEX: usize = 98;
+const SYSCALL_SLEEP: usize = 101;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_SET_PRIORITY: usize = 140;
+const SYSCALL_TIMES: usize = 153;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_GETSID: usize = 156;
+const SYSCALL_SETSID: usize = 157;
+const SYSCALL_GETRLIMIT: usize = 163;
+const SYSCALL_SETRLIMIT: usize = 164;
+const SYSCALL_GETRUSAGE: usize = 165;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_FORK: usize = 220;
-const SYSCALL_EXEC: usize = 221;
+const SYSCALL_EXECVE: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_ENABLE_DEADLOCK_DETECT: usize = 469;
+const SYSCALL_TLB_STATS: usize = 470;
+const SYSCALL_THREAD_CREATE: usize = 1000;
+const SYSCALL_GETTID: usize = 1001;
+const SYSCALL_WAITTID: usize = 1002;
+const SYSCALL_MUTEX_CREATE: usize = 1010;
+const SYSCALL_MUTEX_LOCK: usize = 1011;
+const SYSCALL_MUTEX_UNLOCK: usize = 1012;
+const SYSCALL_SEMAPHORE_CREATE: usize = 1020;
+const SYSCALL_SEMAPHORE_UP: usize = 1021;
+const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
+const SYSCALL_CONDVAR_CREATE: usize = 1030;
+const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
+const SYSCALL_CONDVAR_WAIT: usize = 1032;
 
 pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     // syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, 2])
@@ -55,6 +98,20 @@ pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0])
 }
 
+/// 功能：对 addr 处的 32 位字进行 futex 操作，op 为 0 时在值等于 val 时等待，最多等待 timeout 毫秒
+/// （为 0 时不超时）；op 为 1 时最多唤醒 val 个等待者。
+/// 返回值：参数不合法返回 -EINVAL，地址不可访问返回 -EFAULT；等待被唤醒返回 0，值不等于 val 返回 -EAGAIN，
+/// 被信号打断返回 -EINTR，超时返回 -ETIMEDOUT；唤醒返回唤醒的线程数。
+/// syscall ID：98
+pub fn sys_futex(addr: *const u32, op: usize, val: u32, timeout: usize) -> isize {
+    syscall6(SYSCALL_FUTEX, [addr as usize, op, val as usize, timeout, 0, 0])
+}
+
+/// 睡眠 period_ms 毫秒。被信号打断时返回 -EINTR
+pub fn sys_sleep(period_ms: usize) -> isize {
+    syscall(SYSCALL_SLEEP, [period_ms, 0, 0])
+}
+
 /// 返回当前 ms 数。由于没有时钟对齐，这里只是机器 的系统开机时间
 pub fn sys_get_time() -> isize {
     syscall(SYSCALL_GET_TIME, [0, 0, 0])
@@ -68,20 +125,69 @@ pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
-pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
-    syscall(SYSCALL_EXEC, [path.as_ptr() as usize, args.as_ptr() as usize, 0])
+/// args、envp 均为以空指针结尾的字符串指针数组，字符串需以 \0 结尾
+pub fn sys_execve(path: &str, args: &[*const u8], envp: &[*const u8]) -> isize {
+    syscall(
+        SYSCALL_EXECVE,
+        [
+            path.as_ptr() as usize,
+            args.as_ptr() as usize,
+            envp.as_ptr() as usize,
+        ],
+    )
 }
 
-/// 等待子进程结束
+/// 等待子进程结束，子进程都还在运行时阻塞
 /// pid: -1 表示任意子进程结束；
-/// exit_code：进程退出码。
+/// exit_code：进程退出码；
+/// options：WNOHANG 表示不阻塞，WUNTRACED 表示同时报告因信号暂停的子进程；
+/// rusage：不为空指针时写回子进程（含其已回收的后代）的资源使用。
 ///
 /// 返回值：
-/// -2 表示子进程存在但尚未结束。
+/// 结束的子进程 ID；
+/// 0 表示指定了 WNOHANG 且子进程尚未结束；
+/// -ECHILD 表示没有符合条件的子进程；
+/// -EINTR 表示等待被信号打断。
 // 进程通过 exit 退出后，它所占用的资源不会立即回收。系统只是回收部分，并将进程标记为僵尸进程。
 // waitpid 可以触发回收，并等待直到进程完全退出。
-pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
-    syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0])
+pub fn sys_waitpid(
+    pid: isize,
+    exit_code: *mut i32,
+    options: usize,
+    rusage: *mut Rusage,
+) -> isize {
+    syscall6(
+        SYSCALL_WAITPID,
+        [pid as usize, exit_code as usize, options, rusage as usize, 0, 0],
+    )
+}
+
+/// 功能：获取当前进程及已回收子进程的 CPU 时间（毫秒），tms 可以为空指针。
+/// 返回值：开机以来的毫秒数，地址不可写返回 -EFAULT。
+/// syscall ID：153
+pub fn sys_times(tms: *mut Tms) -> isize {
+    syscall(SYSCALL_TIMES, [tms as usize, 0, 0])
+}
+
+/// 功能：获取资源使用情况，who 为 RUSAGE_SELF、RUSAGE_CHILDREN 或 RUSAGE_THREAD。
+/// 返回值：成功返回 0，who 不合法返回 -EINVAL，地址不可写返回 -EFAULT。
+/// syscall ID：165
+pub fn sys_getrusage(who: isize, rusage: *mut Rusage) -> isize {
+    syscall(SYSCALL_GETRUSAGE, [who as usize, rusage as usize, 0])
+}
+
+/// 功能：获取当前进程对资源 resource 的限制。
+/// 返回值：成功返回 0，resource 不合法返回 -EINVAL，地址不可写返回 -EFAULT。
+/// syscall ID：163
+pub fn sys_getrlimit(resource: usize, rlimit: *mut RLimit) -> isize {
+    syscall(SYSCALL_GETRLIMIT, [resource, rlimit as usize, 0])
+}
+
+/// 功能：设置当前进程对资源 resource 的限制，fork 出的子进程会继承。
+/// 返回值：成功返回 0，resource 不合法或软限制大于硬限制返回 -EINVAL，提高硬限制返回 -EPERM。
+/// syscall ID：164
+pub fn sys_setrlimit(resource: usize, rlimit: *const RLimit) -> isize {
+    syscall(SYSCALL_SETRLIMIT, [resource, rlimit as usize, 0])
 }
 
 pub fn sys_pipe(pipe: &mut [usize]) -> isize {
@@ -90,7 +196,7 @@ pub fn sys_pipe(pipe: &mut [usize]) -> isize {
 
 /// 功能：当前进程关闭一个文件。
 /// 参数：fd 表示要关闭的文件的文件描述符。
-/// 返回值：如果成功关闭则返回 0 ，否则返回 -1 。可能的出错原因：传入的文件描述符并不对应一个打开的文件。
+/// 返回值：如果成功关闭则返回 0 ，否则返回 -EBADF ：传入的文件描述符并不对应一个打开的文件。
 /// syscall ID：57
 pub fn sys_close(fd: usize) -> isize {
     syscall(SYSCALL_CLOSE, [fd, 0, 0])
@@ -99,7 +205,8 @@ pub fn sys_close(fd: usize) -> isize {
 /// 功能：打开一个常规文件，并返回可以访问它的文件描述符。
 /// 参数：path 描述要打开的文件的文件名（简单起见，文件系统不需要支持目录，所有的文件都放在根目录 / 下），
 /// flags 描述打开文件的标志，具体含义下面给出。
-/// 返回值：如果出现了错误则返回 -1，否则返回打开常规文件的文件描述符。可能的错误原因是：文件不存在。
+/// 返回值：如果出现了错误则返回负的错误码，否则返回打开常规文件的文件描述符。可能的错误原因是：
+/// 文件不存在（-ENOENT）、flags 不合法（-EINVAL）。
 /// syscall ID：56
 pub fn sys_open(path: &str, flags: u32) -> isize {
     syscall(SYSCALL_OPEN, [path.as_ptr() as usize, flags as usize, 0])
@@ -107,4 +214,211 @@ pub fn sys_open(path: &str, flags: u32) -> isize {
 
 pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
-}
\ No newline at end of file
+}
+
+/// 功能：对终端 fd 执行控制命令 cmd，目前只支持 TIOCGPGRP、TIOCSPGRP。
+/// 返回值：成功返回 0，fd 不是终端返回 -ENOTTY，进程组不存在返回 -ESRCH，
+/// 进程组不在当前会话中返回 -EPERM。
+/// syscall ID：29
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, cmd, arg])
+}
+
+/// 功能：调整堆顶（program break）。
+/// 参数：addr 为新的堆顶，为 0 时只查询。
+/// 返回值：调整后的堆顶，失败时保持原值不变。
+/// syscall ID：214
+pub fn sys_brk(addr: usize) -> isize {
+    syscall(SYSCALL_BRK, [addr, 0, 0])
+}
+
+/// 功能：映射一段匿名内存，页面在第一次访问时由内核分配并清零。
+/// 参数：start 为起始地址，须按页对齐，为 0 时由内核选择；len 为长度；
+/// prot 为权限，bit0/1/2 分别表示可读/可写/可执行。
+/// 返回值：成功返回映射的起始地址，参数不合法返回 -EINVAL，与已有映射重叠返回 -EEXIST，
+/// 地址空间不足返回 -ENOMEM。
+/// syscall ID：222
+pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MMAP, [start, len, prot])
+}
+
+/// 功能：取消 [start, start + len) 的映射。
+/// 返回值：成功返回 0，否则返回 -EINVAL。
+/// syscall ID：215
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [start, len, 0])
+}
+
+/// 功能：修改 [start, start + len) 的访问权限，prot 含义同 sys_mmap。
+/// 返回值：成功返回 0，参数不合法返回 -EINVAL，范围内有未映射的页面返回 -ENOMEM。
+/// syscall ID：226
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [start, len, prot])
+}
+
+/// 功能：在当前进程中创建线程，从 entry 开始执行，arg 通过 a0 传给入口函数。
+/// 返回值：新线程的 tid。
+/// syscall ID：1000
+pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+    syscall(SYSCALL_THREAD_CREATE, [entry, arg, 0])
+}
+
+/// 功能：获取当前线程的 tid。
+/// syscall ID：1001
+pub fn sys_gettid() -> isize {
+    syscall(SYSCALL_GETTID, [0, 0, 0])
+}
+
+/// 功能：回收当前进程内已退出的线程 tid，退出码写入 exit_code。
+/// 返回值：成功返回 tid；线程为自身返回 -EDEADLK，不存在返回 -ESRCH，尚未退出返回 -EAGAIN。
+/// syscall ID：1002
+pub fn sys_waittid(tid: usize, exit_code: *mut i32) -> isize {
+    syscall(SYSCALL_WAITTID, [tid, exit_code as usize, 0])
+}
+
+/// 功能：创建互斥锁，blocking 为 true 时为阻塞互斥锁，否则为自旋互斥锁。
+/// 返回值：互斥锁 id。
+/// syscall ID：1010
+pub fn sys_mutex_create(blocking: bool) -> isize {
+    syscall(SYSCALL_MUTEX_CREATE, [blocking as usize, 0, 0])
+}
+
+/// 功能：获取互斥锁。
+/// 返回值：成功返回 0，互斥锁不存在返回 -EINVAL，被信号打断返回 -EINTR，会导致死锁返回 -EDEADLK。
+/// syscall ID：1011
+pub fn sys_mutex_lock(mutex_id: usize) -> isize {
+    syscall(SYSCALL_MUTEX_LOCK, [mutex_id, 0, 0])
+}
+
+/// 功能：释放互斥锁。
+/// 返回值：成功返回 0，互斥锁不存在返回 -EINVAL。
+/// syscall ID：1012
+pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
+    syscall(SYSCALL_MUTEX_UNLOCK, [mutex_id, 0, 0])
+}
+
+/// 功能：创建初始资源数为 res_count 的信号量。
+/// 返回值：信号量 id。
+/// syscall ID：1020
+pub fn sys_semaphore_create(res_count: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_CREATE, [res_count, 0, 0])
+}
+
+/// 功能：释放信号量的一个资源。
+/// 返回值：成功返回 0，信号量不存在返回 -EINVAL。
+/// syscall ID：1021
+pub fn sys_semaphore_up(sem_id: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_UP, [sem_id, 0, 0])
+}
+
+/// 功能：获取信号量的一个资源。
+/// 返回值：成功返回 0，信号量不存在返回 -EINVAL，被信号打断返回 -EINTR，会导致死锁返回 -EDEADLK。
+/// syscall ID：1022
+pub fn sys_semaphore_down(sem_id: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_DOWN, [sem_id, 0, 0])
+}
+
+/// 功能：开启（enabled 为 1）或关闭（为 0）当前进程的死锁检测。
+/// 开启后会导致死锁的 mutex_lock、semaphore_down 返回 -EDEADLK 而不等待。
+/// 返回值：成功返回 0，参数不合法返回 -EINVAL。
+/// syscall ID：469
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    syscall(SYSCALL_ENABLE_DEADLOCK_DETECT, [enabled, 0, 0])
+}
+
+/// 功能：获取开机以来的 TLB 刷新统计，写入 stats。
+/// 返回值：成功返回 0，地址不可写返回 -EFAULT。
+/// syscall ID：470
+pub fn sys_tlb_stats(stats: *mut TlbStats) -> isize {
+    syscall(SYSCALL_TLB_STATS, [stats as usize, 0, 0])
+}
+
+/// 功能：创建条件变量。
+/// 返回值：条件变量 id。
+/// syscall ID：1030
+pub fn sys_condvar_create() -> isize {
+    syscall(SYSCALL_CONDVAR_CREATE, [0, 0, 0])
+}
+
+/// 功能：唤醒一个在条件变量上等待的线程。
+/// 返回值：成功返回 0，条件变量不存在返回 -EINVAL。
+/// syscall ID：1031
+pub fn sys_condvar_signal(condvar_id: usize) -> isize {
+    syscall(SYSCALL_CONDVAR_SIGNAL, [condvar_id, 0, 0])
+}
+
+/// 功能：释放互斥锁并在条件变量上等待，被唤醒后重新获取互斥锁。
+/// 返回值：成功返回 0，条件变量或互斥锁不存在返回 -EINVAL，重新获取互斥锁时被信号打断返回 -EINTR。
+/// syscall ID：1032
+pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    syscall(SYSCALL_CONDVAR_WAIT, [condvar_id, mutex_id, 0])
+}
+
+/// 功能：向进程 pid 发送信号 signum。pid 为 0 时发给当前进程组，小于 -1 时发给进程组 -pid。
+/// 返回值：成功返回 0，进程（组）不存在返回 -ESRCH，信号不合法返回 -EINVAL。
+/// syscall ID：129
+pub fn sys_kill(pid: isize, signum: i32) -> isize {
+    syscall(SYSCALL_KILL, [pid as usize, signum as usize, 0])
+}
+
+/// 功能：设置信号 signum 的处理动作，并通过 old_action 返回原来的动作，两者均可为空指针。
+/// 返回值：成功返回 0，信号不合法返回 -EINVAL，地址不可访问返回 -EFAULT。
+/// syscall ID：134
+pub fn sys_sigaction(
+    signum: i32,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    syscall(
+        SYSCALL_SIGACTION,
+        [signum as usize, action as usize, old_action as usize],
+    )
+}
+
+/// 功能：设置当前线程的信号屏蔽字。
+/// 返回值：原来的屏蔽字。
+/// syscall ID：135
+pub fn sys_sigprocmask(mask: u32) -> isize {
+    syscall(SYSCALL_SIGPROCMASK, [mask as usize, 0, 0])
+}
+
+/// 功能：从信号处理函数返回。
+/// 返回值：不在信号处理函数中时返回 -EINVAL，否则不会返回。
+/// syscall ID：139
+pub fn sys_sigreturn() -> isize {
+    syscall(SYSCALL_SIGRETURN, [0, 0, 0])
+}
+
+/// 功能：设置当前线程的优先级，只在内核使用步长调度时生效。
+/// 参数：prio 为新的优先级，不能小于 2。
+/// 返回值：成功返回设置的优先级，否则返回 -EINVAL。
+/// syscall ID：140
+pub fn sys_set_priority(prio: isize) -> isize {
+    syscall(SYSCALL_SET_PRIORITY, [prio as usize, 0, 0])
+}
+
+/// 功能：将进程 pid 加入进程组 pgid，pid 为 0 表示当前进程，pgid 为 0 表示新建以 pid 为 id 的进程组。
+/// 返回值：成功返回 0，pid 不是自己或子进程返回 -ESRCH，跨会话返回 -EPERM。
+/// syscall ID：154
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(SYSCALL_SETPGID, [pid, pgid, 0])
+}
+
+/// 功能：获取进程 pid 的进程组 id，pid 为 0 表示当前进程。
+/// syscall ID：155
+pub fn sys_getpgid(pid: usize) -> isize {
+    syscall(SYSCALL_GETPGID, [pid, 0, 0])
+}
+
+/// 功能：获取进程 pid 的会话 id，pid 为 0 表示当前进程。
+/// syscall ID：156
+pub fn sys_getsid(pid: usize) -> isize {
+    syscall(SYSCALL_GETSID, [pid, 0, 0])
+}
+
+/// 功能：新建会话并成为会话首进程。
+/// 返回值：新会话 id，已经是进程组组长时返回 -EPERM。
+/// syscall ID：157
+pub fn sys_setsid() -> isize {
+    syscall(SYSCALL_SETSID, [0, 0, 0])
+}